@@ -0,0 +1,62 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, Listener, Topic, TreeScanner};
+
+// Counts every allocation, so the benchmark can report how many `accept` makes.
+struct CountingAlloc;
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn alloc_benchmark(c: &mut Criterion) {
+    let mut topic = TreeScanner::default();
+    for i in 0..100 {
+        topic.subscribe(
+            Counter::default(),
+            Filter::single("hello", format!("v{}", i % 10)),
+        );
+    }
+    topic.subscribe(Counter::default(), Filter::EMPTY);
+    let evt = Event::single("hello", "v3");
+
+    const ROUNDS: usize = 10_000;
+    topic.accept(&evt);
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ROUNDS {
+        topic.accept(&evt);
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "allocations per accept: {}",
+        allocations as f64 / ROUNDS as f64
+    );
+
+    c.bench_function("shallow-tree", |b| {
+        b.iter(|| topic.accept(&evt));
+    });
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &tagsub::Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, alloc_benchmark);
+criterion_main!(benches);
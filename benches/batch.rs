@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, Listener, Topic, TreeScanner};
+
+fn batch_benchmark(c: &mut Criterion) {
+    let mut topic = TreeScanner::default();
+    for i in 0..1_000 {
+        let filter = Filter::builder()
+            .eq("user", format!("u{}", i % 100))
+            .eq("region", format!("r{}", i % 7))
+            .build();
+        topic.subscribe(Counter::default(), filter);
+    }
+    let evts: Vec<Event> = (0..1_000)
+        .map(|i| {
+            Event::builder()
+                .tag("user", format!("u{}", i % 150))
+                .tag("region", format!("r{}", i % 7))
+                .build()
+        })
+        .collect();
+
+    c.bench_function("per-event", |b| {
+        b.iter(|| {
+            for evt in &evts {
+                topic.accept(evt);
+            }
+        });
+    });
+    c.bench_function("batch", |b| {
+        b.iter(|| topic.accept_batch(&evts));
+    });
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &tagsub::Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, batch_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{BitsetScanner, Event, Filter, LinearScan, Listener, Topic};
+
+const SUBSCRIPTIONS: usize = 20_000;
+
+// Four tags with a handful of values each. Every subscription constrains two or three of them,
+// some to a pair of values, so a typical event matches a few percent of the subscriptions.
+fn filter(i: usize) -> Filter {
+    let mut builder = Filter::builder().eq("env", format!("e{}", i % 3)).any_of(
+        "region",
+        [format!("r{}", i % 5), format!("r{}", (i / 5) % 5)],
+    );
+    if i.is_multiple_of(2) {
+        builder = builder.eq("tier", format!("t{}", (i / 7) % 4));
+    }
+    builder.build()
+}
+
+fn event(n: usize) -> Event {
+    Event::builder()
+        .tag("env", format!("e{}", n % 3))
+        .tag("region", format!("r{}", n % 5))
+        .tag("tier", format!("t{}", n % 4))
+        .tag("host", format!("h{}", n))
+        .build()
+}
+
+fn bench_topic<T: Topic<Counter> + Listener>(c: &mut Criterion, name: &str, mut topic: T) {
+    for i in 0..SUBSCRIPTIONS {
+        topic.subscribe(Counter::default(), filter(i));
+    }
+    let evts: Vec<Event> = (0..16).map(event).collect();
+    c.bench_function(&format!("low-cardinality/{}", name), |b| {
+        b.iter(|| {
+            for evt in &evts {
+                topic.accept(evt);
+            }
+        });
+    });
+}
+
+fn bitset_benchmark(c: &mut Criterion) {
+    bench_topic(c, "linear-scan", LinearScan::default());
+    bench_topic(c, "bitset-scanner", BitsetScanner::default());
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, bitset_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,88 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    collections::BTreeSet,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, InternedEvent, Interner};
+
+// Counts every allocation, so the benchmark can report how many matching an event makes.
+struct CountingAlloc;
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn filter(i: usize) -> Filter {
+    [
+        (
+            "service",
+            BTreeSet::from([format!("service-with-a-long-name-{}", i % 10)]),
+        ),
+        ("region", BTreeSet::from([format!("region-{}", i % 3)])),
+    ]
+    .into_iter()
+    .collect()
+}
+
+// Reports how many allocations `f` makes per call, once it has warmed up.
+fn allocations_per_call(name: &str, mut f: impl FnMut()) {
+    const ROUNDS: usize = 10_000;
+    f();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ROUNDS {
+        f();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{name}: allocations per event: {}",
+        allocations as f64 / ROUNDS as f64
+    );
+}
+
+// Both sides check the same event against the same filters. Neither allocates per event: string
+// matching never did, and the interned side translates each event into a reused buffer. What
+// interning saves is the string comparisons.
+fn intern_benchmark(c: &mut Criterion) {
+    let evt: Event = [
+        ("service", "service-with-a-long-name-4"),
+        ("region", "region-1"),
+    ]
+    .into_iter()
+    .collect();
+
+    let filters: Vec<_> = (0..1_000).map(filter).collect();
+    let strings = || black_box(filters.iter().filter(|f| f.matches(&evt)).count());
+    allocations_per_call("strings", || {
+        strings();
+    });
+    c.bench_function("strings", |b| b.iter(strings));
+
+    let mut interner = Interner::new();
+    let interned: Vec<_> = filters
+        .iter()
+        .map(|f| interner.intern_filter(f).unwrap())
+        .collect();
+    let mut buf = InternedEvent::default();
+    let mut symbols = || {
+        interner.lookup_event_into(&evt, &mut buf);
+        black_box(interned.iter().filter(|f| f.matches(&buf)).count())
+    };
+    allocations_per_call("interned", || {
+        symbols();
+    });
+    c.bench_function("interned", |b| b.iter(&mut symbols));
+}
+
+criterion_group!(benches, intern_benchmark);
+criterion_main!(benches);
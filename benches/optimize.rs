@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, Listener, Topic, TreeScanner};
+
+// Every subscription wants a single user, but the first one also wants several low-cardinality
+// flags. Those flags reach the pipeline first, so every other subscription sits at the end of a
+// chain of passthroughs that each event has to walk before it gets to the tag that actually tells
+// subscriptions apart.
+fn skewed() -> TreeScanner<Counter> {
+    let mut topic = TreeScanner::default();
+    let mut flagged = Filter::builder();
+    for t in 0..8 {
+        flagged = flagged.eq(format!("flag{}", t), "on");
+    }
+    topic.subscribe(Counter::default(), flagged.eq("user", "u0").build());
+    for i in 0..10_000 {
+        topic.subscribe(
+            Counter::default(),
+            Filter::single("user", format!("u{}", i)),
+        );
+    }
+    topic
+}
+
+fn optimize_benchmark(c: &mut Criterion) {
+    let evt = Event::builder()
+        .tag("flag3", "on")
+        .tag("user", "u42")
+        .build();
+
+    let mut topic = skewed();
+    println!(
+        "nodes visited before optimize: {}",
+        topic.nodes_visited(&evt)
+    );
+    c.bench_function("unoptimized", |b| {
+        b.iter(|| topic.accept(&evt));
+    });
+
+    let mut topic = skewed();
+    topic.optimize();
+    println!(
+        "nodes visited after optimize: {}",
+        topic.nodes_visited(&evt)
+    );
+    c.bench_function("optimized", |b| {
+        b.iter(|| topic.accept(&evt));
+    });
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &tagsub::Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, optimize_benchmark);
+criterion_main!(benches);
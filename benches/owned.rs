@@ -0,0 +1,36 @@
+use std::sync::mpsc;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use tagsub::{ChannelListener, Event, Filter, LinearScan, Topic};
+
+// A single channel listener, so `accept` clones each event into the channel while `accept_owned`
+// moves it there.
+fn owned_benchmark(c: &mut Criterion) {
+    let (tx, rx) = mpsc::channel();
+    let mut topic = LinearScan::default();
+    topic.subscribe(ChannelListener::new(tx), Filter::single("hello", "world"));
+    let evt = Event::builder()
+        .tag("hello", "world")
+        .data(vec![0u8; 4096])
+        .build();
+    // Each iteration gets its own event, as if it had just been produced, and the channel is
+    // emptied outside of the timed part.
+    let fresh = || {
+        rx.try_iter().for_each(drop);
+        evt.clone()
+    };
+
+    c.bench_function("one-channel/borrowed", |b| {
+        b.iter_batched(
+            fresh,
+            |evt| topic.accept_counted(&evt),
+            BatchSize::SmallInput,
+        );
+    });
+    c.bench_function("one-channel/owned", |b| {
+        b.iter_batched(fresh, |evt| topic.accept_owned(evt), BatchSize::SmallInput);
+    });
+}
+
+criterion_group!(benches, owned_benchmark);
+criterion_main!(benches);
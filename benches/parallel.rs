@@ -0,0 +1,32 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, LinearScan, Listener, Topic};
+
+fn parallel_benchmark(c: &mut Criterion) {
+    let mut topic = LinearScan::default();
+    let filter = Filter::single("hello", "world");
+    for _ in 0..10_000 {
+        topic.subscribe(Busy::default(), filter.clone());
+    }
+    let evt = Event::single("hello", "world");
+
+    c.bench_function("serial", |b| {
+        b.iter(|| topic.accept(&evt));
+    });
+    c.bench_function("parallel", |b| {
+        b.iter(|| topic.accept_parallel(&evt));
+    });
+}
+
+// A listener that does a little CPU-bound work per event.
+#[derive(Default)]
+struct Busy(u64);
+impl Listener for Busy {
+    fn accept(&mut self, _evt: &Event) {
+        for i in 0..1_000 {
+            self.0 = black_box(self.0.wrapping_mul(31).wrapping_add(i));
+        }
+    }
+}
+
+criterion_group!(benches, parallel_benchmark);
+criterion_main!(benches);
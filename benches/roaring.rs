@@ -0,0 +1,79 @@
+use std::collections::BTreeSet;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use roaring::RoaringBitmap;
+use tagsub::{Event, Filter, InvertedIndex, Listener, Topic};
+
+// Every subscription wants one region and one tier, so each posting list holds a quarter or a
+// third of all subscriptions and an event's candidates are their intersection.
+fn region(i: usize) -> usize {
+    i % 4
+}
+
+fn tier(i: usize) -> usize {
+    i % 3
+}
+
+// The posting lists an event for region 1 and tier 2 intersects, as plain sets of ids.
+fn btree_postings(n: usize) -> (BTreeSet<usize>, BTreeSet<usize>) {
+    let regions = (0..n).filter(|&i| region(i) == 1).collect();
+    let tiers = (0..n).filter(|&i| tier(i) == 2).collect();
+    (regions, tiers)
+}
+
+fn roaring_postings(n: usize) -> (RoaringBitmap, RoaringBitmap) {
+    let n = n as u32;
+    let regions = (0..n).filter(|&i| region(i as usize) == 1).collect();
+    let tiers = (0..n).filter(|&i| tier(i as usize) == 2).collect();
+    (regions, tiers)
+}
+
+fn intersection_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("intersection");
+    for n in [10_000, 100_000, 1_000_000] {
+        let (regions, tiers) = btree_postings(n);
+        group.bench_with_input(BenchmarkId::new("btree-set", n), &n, |b, _| {
+            b.iter(|| regions.intersection(&tiers).count());
+        });
+        let (regions, tiers) = roaring_postings(n);
+        group.bench_with_input(BenchmarkId::new("roaring", n), &n, |b, _| {
+            b.iter(|| (&regions & &tiers).len());
+        });
+    }
+    group.finish();
+}
+
+// The whole index, which with this bench's `roaring` feature keeps its posting lists in bitmaps.
+fn index_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("roaring-inverted-index");
+    group.sample_size(20);
+    for n in [10_000, 100_000] {
+        let mut topic = InvertedIndex::default();
+        for i in 0..n {
+            let filter = Filter::builder()
+                .eq("region", format!("r{}", region(i)))
+                .eq("tier", format!("t{}", tier(i)))
+                .build();
+            topic.subscribe(Counter::default(), filter);
+        }
+        let evt = Event::builder()
+            .tag("region", "r1")
+            .tag("tier", "t2")
+            .build();
+        group.bench_with_input(BenchmarkId::new("accept", n), &n, |b, _| {
+            b.iter(|| topic.accept(&evt));
+        });
+    }
+    group.finish();
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, intersection_benchmark, index_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, InvertedIndex, LinearScan, Listener, Topic, TreeScanner};
+
+const SUBSCRIPTIONS: usize = 50_000;
+
+// Each subscription wants a single user in a single region, so any given event matches only a
+// handful of them.
+fn filter(i: usize) -> Filter {
+    Filter::builder()
+        .eq("user", format!("u{}", i % 10_000))
+        .eq("region", format!("r{}", i % 7))
+        .build()
+}
+
+fn event(user: usize, region: usize) -> Event {
+    Event::builder()
+        .tag("user", format!("u{}", user))
+        .tag("region", format!("r{}", region))
+        .build()
+}
+
+fn bench_topic<T: Topic<Counter> + Listener>(c: &mut Criterion, name: &str, mut topic: T) {
+    for i in 0..SUBSCRIPTIONS {
+        topic.subscribe(Counter::default(), filter(i));
+    }
+    let hit = event(1_234, 1_234 % 7);
+    let miss = event(99_999, 0);
+    c.bench_function(&format!("{}/selective-hit", name), |b| {
+        b.iter(|| topic.accept(&hit));
+    });
+    c.bench_function(&format!("{}/selective-miss", name), |b| {
+        b.iter(|| topic.accept(&miss));
+    });
+}
+
+fn selective_benchmark(c: &mut Criterion) {
+    bench_topic(c, "linear-scan", LinearScan::default());
+    bench_topic(c, "tree-scanner", TreeScanner::default());
+    bench_topic(c, "inverted-index", InvertedIndex::default());
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl Listener for Counter {
+    fn accept(&mut self, _evt: &tagsub::Event) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, selective_benchmark);
+criterion_main!(benches);
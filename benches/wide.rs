@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use tagsub::{Event, Filter, HashEvent, LinearScan, Listener, Topic};
+
+const TAGS: usize = 50;
+const SUBSCRIPTIONS: usize = 1_000;
+
+// Each subscription constrains three of the event's fifty tags, all of which the event has.
+fn filter(i: usize) -> Filter {
+    Filter::builder()
+        .eq(format!("tag{:02}", i % TAGS), format!("v{}", i % TAGS))
+        .eq(
+            format!("tag{:02}", (i * 7) % TAGS),
+            format!("v{}", (i * 7) % TAGS),
+        )
+        .eq(
+            format!("tag{:02}", (i * 13) % TAGS),
+            format!("v{}", (i * 13) % TAGS),
+        )
+        .build()
+}
+
+fn event() -> Event {
+    (0..TAGS)
+        .map(|i| (format!("tag{:02}", i), format!("v{}", i)))
+        .collect()
+}
+
+fn wide_event_benchmark(c: &mut Criterion) {
+    let mut topic = LinearScan::default();
+    for i in 0..SUBSCRIPTIONS {
+        topic.subscribe(Counter::default(), filter(i));
+    }
+    let btree = event();
+    let hash = HashEvent::from(btree.clone());
+    c.bench_function("wide-event/btree-map", |b| {
+        b.iter(|| topic.accept(&btree));
+    });
+    c.bench_function("wide-event/hash-map", |b| {
+        b.iter(|| topic.accept(&hash));
+    });
+}
+
+#[derive(Default)]
+struct Counter(u32);
+impl<E> Listener<E> for Counter {
+    fn accept(&mut self, _evt: &E) {
+        self.0 += 1;
+    }
+}
+
+criterion_group!(benches, wide_event_benchmark);
+criterion_main!(benches);
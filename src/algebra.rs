@@ -0,0 +1,488 @@
+use crate::{Filter, FilterError, ValueMatch};
+
+impl Filter {
+    /// Whether every event matching `other` also matches `self`, e.g. `env in (prod, staging)`
+    /// subsumes `env=prod AND region=us`. Every filter subsumes itself.
+    ///
+    /// The check is conservative: it only returns true when each of `self`'s constraints is
+    /// implied by one of `other`'s on the same tag, so it may miss implications that depend on
+    /// what a matcher accepts.
+    pub fn subsumes(&self, other: &Filter) -> bool {
+        self.tags.iter().all(|(tag, values)| {
+            match other.tags.get(tag) {
+                // An event matching `other` carries one of these values, so it carries one of
+                // `self`'s too.
+                Some(required) if !required.is_empty() => {
+                    values.is_empty() || required.is_subset(values)
+                }
+                // `other` only requires the tag to be present.
+                Some(_) => values.is_empty(),
+                None => values.is_empty() && other.matchers.contains_key(tag),
+            }
+        }) && self.exclude.iter().all(|(tag, values)| {
+            other.absent.contains(tag)
+                || other
+                    .exclude
+                    .get(tag)
+                    .is_some_and(|excluded| values.is_subset(excluded))
+        }) && self.absent.is_subset(&other.absent)
+            && self.matchers.iter().all(|(tag, m)| {
+                other.matchers.get(tag) == Some(m)
+                    || other
+                        .tags
+                        .get(tag)
+                        .is_some_and(|vs| !vs.is_empty() && vs.iter().all(|v| m.matches(v)))
+            })
+            && self.key_prefixes.iter().all(|prefix| {
+                // `other` requires some key starting with a longer prefix, or a key outright.
+                other
+                    .key_prefixes
+                    .iter()
+                    .chain(other.tags.keys())
+                    .chain(other.matchers.keys())
+                    .any(|key| key.starts_with(prefix.as_str()))
+            })
+    }
+
+    /// A filter matching the events that match both `self` and `other`, for events with at most
+    /// one value per tag. Constraints on different tags are combined, and the value sets of a tag
+    /// that both filters require are intersected.
+    ///
+    /// If no value satisfies both filters, the result still exists but matches nothing; see
+    /// [`Filter::is_satisfiable`]. Returns `None` if the two filters have different matchers for
+    /// the same tag, since a filter holds at most one matcher per tag.
+    pub fn intersect(&self, other: &Filter) -> Option<Filter> {
+        let mut out = self.clone();
+        for (tag, m) in &other.matchers {
+            match out.matchers.get(tag) {
+                Some(existing) if existing != m => return None,
+                _ => {
+                    out.matchers.insert(tag.clone(), m.clone());
+                }
+            }
+        }
+        for (tag, values) in &other.tags {
+            match out.tags.get_mut(tag) {
+                None => {
+                    out.tags.insert(tag.clone(), values.clone());
+                }
+                Some(mine) if mine.is_empty() => *mine = values.clone(),
+                Some(_) if values.is_empty() => {}
+                Some(mine) => {
+                    mine.retain(|v| values.contains(v));
+                    // An empty set would only require the tag to be present, so rule the tag
+                    // out as well to make the filter match nothing.
+                    if mine.is_empty() {
+                        out.absent.insert(tag.clone());
+                    }
+                }
+            }
+        }
+        for (tag, values) in &other.exclude {
+            out.exclude
+                .entry(tag.clone())
+                .or_default()
+                .extend(values.iter().cloned());
+        }
+        out.absent.extend(other.absent.iter().cloned());
+        out.key_prefixes.extend(other.key_prefixes.iter().cloned());
+        Some(out)
+    }
+
+    /// A single filter matching the events that match either `self` or `other`, if there is one.
+    /// That's the case when one of them subsumes the other, or when they are identical except for
+    /// the values they accept for one tag. Otherwise returns `None`, and the two can be combined
+    /// as a [`FilterSet`](crate::FilterSet) instead.
+    pub fn union(&self, other: &Filter) -> Option<Filter> {
+        if self.subsumes(other) {
+            return Some(self.clone());
+        }
+        if other.subsumes(self) {
+            return Some(other.clone());
+        }
+        if self.exclude != other.exclude
+            || self.absent != other.absent
+            || self.matchers != other.matchers
+            || self.key_prefixes != other.key_prefixes
+            || !self.tags.keys().eq(other.tags.keys())
+        {
+            return None;
+        }
+        let mut differing = self
+            .tags
+            .iter()
+            .zip(&other.tags)
+            .filter(|((_, a), (_, b))| a != b);
+        let ((tag, a), (_, b)) = differing.next()?;
+        // A presence-only requirement would have been subsumed above.
+        if differing.next().is_some() || a.is_empty() || b.is_empty() {
+            return None;
+        }
+        let mut out = self.clone();
+        out.tags.insert(tag.clone(), a.union(b).cloned().collect());
+        Some(out)
+    }
+
+    /// Whether the filter has no constraints at all, so that every event matches it. An empty
+    /// `tags` isn't enough on its own: a filter that only excludes values, say, still rules some
+    /// events out.
+    pub fn matches_all(&self) -> bool {
+        *self == Filter::EMPTY
+    }
+
+    /// Whether any event can match the filter at all. A filter that requires a tag it also rules
+    /// out, e.g. one produced by [`Filter::intersect`], matches nothing, and so does one whose
+    /// required values for a tag all fail that tag's matcher, or one with an empty
+    /// [`ValueMatch::Range`]. These are the contradictions [`Filter::validate`] reports.
+    pub fn is_satisfiable(&self) -> bool {
+        self.contradiction().is_none()
+            && self.all_excluded().is_none()
+            && self.empty_range().is_none()
+    }
+
+    /// Checks for constraints that are almost certainly mistakes: ones that keep the filter from
+    /// ever matching, and ones that have no effect.
+    ///
+    /// An empty value set in `tags` is valid: it requires the tag to be present, whatever its
+    /// value. An empty set in `exclude` is not, since it would exclude nothing. Filters with
+    /// several problems report the first one, checking tags in order.
+    pub fn validate(&self) -> Result<(), FilterError> {
+        if let Some(err) = self.contradiction().or_else(|| self.all_excluded()) {
+            return Err(err);
+        }
+        if let Some((tag, _)) = self.exclude.iter().find(|(_, vs)| vs.is_empty()) {
+            return Err(FilterError::EmptyExclusion { tag: tag.clone() });
+        }
+        match self.empty_range() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    // A tag that is required, by value or by matcher, and also ruled out; or one whose every
+    // required value fails its matcher.
+    fn contradiction(&self) -> Option<FilterError> {
+        let required = self.tags.keys().chain(self.matchers.keys());
+        let absent = required.filter(|tag| self.absent.contains(*tag));
+        let unmatched = self.tags.iter().filter_map(|(tag, values)| {
+            let m = self.matchers.get(tag)?;
+            (!values.is_empty() && !values.iter().any(|v| m.matches(v))).then_some(tag)
+        });
+        let tag = absent.chain(unmatched).min()?;
+        Some(FilterError::Contradiction { tag: tag.clone() })
+    }
+
+    // A tag whose every required value is also excluded.
+    fn all_excluded(&self) -> Option<FilterError> {
+        let (tag, _) = self.tags.iter().find(|(tag, values)| {
+            self.exclude
+                .get(*tag)
+                .is_some_and(|excluded| !values.is_empty() && values.is_subset(excluded))
+        })?;
+        Some(FilterError::AllExcluded { tag: tag.clone() })
+    }
+
+    // A range matcher that no value falls within.
+    fn empty_range(&self) -> Option<FilterError> {
+        let (tag, _) = self.matchers.iter().find(|(_, m)| match m {
+            // Written so that a NaN bound counts as empty too.
+            ValueMatch::Range {
+                min,
+                max,
+                inclusive,
+            } => !if *inclusive { min <= max } else { min < max },
+            _ => false,
+        })?;
+        Some(FilterError::EmptyRange { tag: tag.clone() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{Event, LinearScan, Listener, Topic};
+
+    #[test]
+    fn broader_values_subsume() {
+        let broad = Filter::single_any("env", ["prod", "staging"]);
+        let narrow = Filter::single("env", "prod");
+        assert!(broad.subsumes(&narrow));
+        assert!(!narrow.subsumes(&broad));
+
+        let narrower = Filter::builder()
+            .eq("env", "prod")
+            .eq("region", "us")
+            .build();
+        assert!(broad.subsumes(&narrower));
+        assert!(Filter::EMPTY.subsumes(&narrower));
+    }
+
+    #[test]
+    fn extra_keys_are_not_subsumed() {
+        let both = Filter::builder()
+            .eq("env", "prod")
+            .eq("region", "us")
+            .build();
+        let env = Filter::single("env", "prod");
+        assert!(!both.subsumes(&env));
+        assert!(!Filter::single("region", "us").subsumes(&env));
+    }
+
+    #[test]
+    fn equal_filters_subsume_each_other() {
+        let mut filter = Filter::single_any("env", ["prod", "staging"]);
+        filter.absent.insert("debug".to_owned());
+        filter
+            .matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        assert!(filter.subsumes(&filter.clone()));
+    }
+
+    #[test]
+    fn other_constraints() {
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("env".to_owned(), Default::default());
+        assert!(presence.subsumes(&Filter::single("env", "prod")));
+        assert!(!Filter::single("env", "prod").subsumes(&presence));
+
+        let mut no_debug = Filter::EMPTY;
+        no_debug.absent.insert("debug".to_owned());
+        let mut excludes_debug = Filter::EMPTY;
+        excludes_debug
+            .exclude
+            .insert("debug".to_owned(), ["true".to_owned()].into());
+        assert!(excludes_debug.subsumes(&no_debug));
+        assert!(!no_debug.subsumes(&excludes_debug));
+
+        let mut prefixed = Filter::EMPTY;
+        prefixed
+            .matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        assert!(prefixed.subsumes(&Filter::single_any("svc", ["api.users", "api.billing"])));
+        assert!(!prefixed.subsumes(&Filter::single_any("svc", ["api.users", "web"])));
+    }
+
+    #[test]
+    fn key_prefixes_subsume() {
+        let http = Filter {
+            key_prefixes: BTreeSet::from(["http.".to_owned()]),
+            ..Filter::EMPTY
+        };
+        let http_req = Filter {
+            key_prefixes: BTreeSet::from(["http.req.".to_owned()]),
+            ..Filter::EMPTY
+        };
+        assert!(http.subsumes(&http_req));
+        assert!(!http_req.subsumes(&http));
+        assert!(http.subsumes(&Filter::single("http.method", "GET")));
+        assert!(!http.subsumes(&Filter::single("grpc.method", "Get")));
+        assert!(!Filter::single("http.method", "GET").subsumes(&http));
+
+        let both = http.intersect(&Filter::single("env", "prod")).unwrap();
+        assert_eq!(both.key_prefixes, http.key_prefixes);
+        assert_eq!(http.union(&Filter::single("env", "prod")), None);
+    }
+
+    #[test]
+    fn intersect_disjoint_keys() {
+        let env = Filter::single("env", "prod");
+        let region = Filter::single_any("region", ["us", "eu"]);
+        let both = env.intersect(&region).unwrap();
+        let expected = Filter::builder()
+            .eq("env", "prod")
+            .any_of("region", ["us", "eu"])
+            .build();
+        assert_eq!(both, expected);
+        assert!(both.matches(
+            &Event::builder()
+                .tag("env", "prod")
+                .tag("region", "eu")
+                .build()
+        ));
+        assert!(!both.matches(&Event::single("env", "prod")));
+    }
+
+    #[test]
+    fn intersect_overlapping_values() {
+        let a = Filter::single_any("env", ["prod", "staging"]);
+        let b = Filter::single_any("env", ["staging", "dev"]);
+        let both = a.intersect(&b).unwrap();
+        assert_eq!(both, Filter::single("env", "staging"));
+        assert!(both.is_satisfiable());
+
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("env".to_owned(), BTreeSet::new());
+        assert_eq!(presence.intersect(&a).unwrap(), a);
+    }
+
+    #[test]
+    fn intersect_empty_values_matches_nothing() {
+        let prod = Filter::single("env", "prod");
+        let dev = Filter::single("env", "dev");
+        let both = prod.intersect(&dev).unwrap();
+        assert!(!both.is_satisfiable());
+        for evt in [
+            Event::single("env", "prod"),
+            Event::single("env", "dev"),
+            Event::builder().build(),
+        ] {
+            assert!(!both.matches(&evt));
+        }
+    }
+
+    #[test]
+    fn matchers_can_be_unsatisfiable() {
+        let mut prefixed = Filter::EMPTY;
+        prefixed
+            .matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        assert!(prefixed.is_satisfiable());
+        let mut no_svc = Filter::EMPTY;
+        no_svc.absent.insert("svc".to_owned());
+        let both = prefixed.intersect(&no_svc).unwrap();
+        assert!(!both.is_satisfiable());
+        assert!(!both.matches(&Event::single("svc", "api.users")));
+
+        let mut dev = Filter::EMPTY;
+        dev.matchers
+            .insert("env".to_owned(), ValueMatch::Prefix("dev".to_owned()));
+        let prod = Filter::single("env", "prod").intersect(&dev).unwrap();
+        assert!(!prod.is_satisfiable());
+        assert_eq!(
+            prod.validate(),
+            Err(FilterError::Contradiction {
+                tag: "env".to_owned()
+            })
+        );
+        let mut either = Filter::single("env", "prod");
+        either
+            .tags
+            .get_mut("env")
+            .unwrap()
+            .insert("dev1".to_owned());
+        let either = either.intersect(&dev).unwrap();
+        assert!(either.is_satisfiable());
+        assert!(either.matches(&Event::single("env", "dev1")));
+
+        let mut empty_range = Filter::EMPTY;
+        empty_range.matchers.insert(
+            "n".to_owned(),
+            ValueMatch::Range {
+                min: 2.0,
+                max: 1.0,
+                inclusive: true,
+            },
+        );
+        assert!(!empty_range.is_satisfiable());
+    }
+
+    #[test]
+    fn intersect_conflicting_matchers() {
+        let mut a = Filter::EMPTY;
+        a.matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        let mut b = Filter::EMPTY;
+        b.matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("web".to_owned()));
+        assert!(a.intersect(&b).is_none());
+        assert_eq!(a.intersect(&a).unwrap(), a);
+    }
+
+    #[test]
+    fn union_where_feasible() {
+        let prod = Filter::builder()
+            .eq("env", "prod")
+            .eq("region", "us")
+            .build();
+        let dev = Filter::builder()
+            .eq("env", "dev")
+            .eq("region", "us")
+            .build();
+        let expected = Filter::builder()
+            .any_of("env", ["prod", "dev"])
+            .eq("region", "us")
+            .build();
+        assert_eq!(prod.union(&dev).unwrap(), expected);
+
+        let broad = Filter::single("region", "us");
+        assert_eq!(prod.union(&broad).unwrap(), broad);
+
+        // Two tags differ, so no single filter covers exactly both.
+        let other = Filter::builder()
+            .eq("env", "dev")
+            .eq("region", "eu")
+            .build();
+        assert!(prod.union(&other).is_none());
+    }
+
+    #[test]
+    fn validate_accepts_valid_filters() {
+        assert_eq!(Filter::EMPTY.validate(), Ok(()));
+        assert_eq!(Filter::single("env", "prod").validate(), Ok(()));
+
+        // An empty value set requires the tag to be present.
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("env".to_owned(), BTreeSet::new());
+        assert_eq!(presence.validate(), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_mistakes() {
+        let tag = || "env".to_owned();
+
+        let mut empty_exclusion = Filter::EMPTY;
+        empty_exclusion.exclude.insert(tag(), BTreeSet::new());
+        assert_eq!(
+            empty_exclusion.validate(),
+            Err(FilterError::EmptyExclusion { tag: tag() })
+        );
+
+        let contradiction = Filter::single("env", "prod")
+            .intersect(&Filter::single("env", "dev"))
+            .unwrap();
+        assert_eq!(
+            contradiction.validate(),
+            Err(FilterError::Contradiction { tag: tag() })
+        );
+
+        let mut all_excluded = Filter::single("env", "prod");
+        all_excluded
+            .exclude
+            .insert(tag(), BTreeSet::from(["prod".to_owned()]));
+        assert_eq!(
+            all_excluded.validate(),
+            Err(FilterError::AllExcluded { tag: tag() })
+        );
+
+        let mut empty_range = Filter::EMPTY;
+        empty_range.matchers.insert(
+            tag(),
+            ValueMatch::Range {
+                min: 1.0,
+                max: 1.0,
+                inclusive: false,
+            },
+        );
+        assert_eq!(
+            empty_range.validate(),
+            Err(FilterError::EmptyRange { tag: tag() })
+        );
+    }
+
+    #[test]
+    fn subscribe_checked_rejects_invalid_filters() {
+        struct Noop;
+        impl Listener for Noop {
+            fn accept(&mut self, _evt: &Event) {}
+        }
+
+        let mut topic = LinearScan::default();
+        let mut invalid = Filter::EMPTY;
+        invalid.exclude.insert("env".to_owned(), BTreeSet::new());
+        assert!(topic.subscribe_checked(Noop, invalid).is_err());
+        assert!(topic.is_empty());
+        assert!(topic.subscribe_checked(Noop, Filter::EMPTY).is_ok());
+    }
+}
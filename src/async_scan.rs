@@ -0,0 +1,249 @@
+use alloc::vec::Vec;
+use core::future::Future;
+
+use futures::future::join_all;
+
+use crate::{spend, Event, Filter, FilterSet, LinearScan, Metrics, SubscriptionId, Tags, Topic};
+
+/// A listener whose handling of an event is asynchronous, e.g. because it writes to the network.
+///
+/// Implementations can use `async fn accept`.
+pub trait AsyncListener<E = Event> {
+    fn accept(&mut self, evt: &E) -> impl Future<Output = ()>;
+}
+
+/// A [`LinearScan`] whose listeners are [`AsyncListener`]s.
+///
+/// Each listener needs `&mut` access to itself while it handles an event, so an event is delivered
+/// in one of two ways:
+///
+/// - [`AsyncLinearScan::accept`] awaits each listener before moving on to the next, in
+///   subscription order. A listener sees every event in the order it was accepted, but a slow
+///   listener holds up all the listeners after it.
+/// - [`AsyncLinearScan::accept_concurrent`] starts every matching listener and awaits them
+///   together, so one listener's waiting overlaps with another's. They still run on the caller's
+///   task rather than in parallel, and what each does after its first `.await` may interleave with
+///   the others in any order.
+///
+/// Either way, the next event can't be accepted until every listener is done with this one.
+#[derive(Clone, Debug)]
+pub struct AsyncLinearScan<L> {
+    scan: LinearScan<L>,
+}
+impl<L> Default for AsyncLinearScan<L> {
+    fn default() -> Self {
+        Self {
+            scan: LinearScan::default(),
+        }
+    }
+}
+impl<L> AsyncLinearScan<L> {
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.scan.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scan.is_empty()
+    }
+
+    /// What the scan has dispatched so far. See [`LinearScan::metrics`].
+    pub fn metrics(&self) -> &Metrics {
+        self.scan.metrics()
+    }
+
+    /// Delivers `evt` to each matching listener in turn, waiting for one to finish before starting
+    /// the next.
+    pub async fn accept<E: Tags>(&mut self, evt: &E)
+    where
+        L: AsyncListener<E>,
+    {
+        let view = self.scan.view(evt);
+        if !self.scan.may_match(&view) {
+            self.scan.metrics.record(0);
+            return;
+        }
+        let mut dispatch = Dispatch {
+            scan: &mut self.scan,
+            exhausted: false,
+        };
+        let mut count = 0;
+        for entry in dispatch.scan.listeners.iter_mut() {
+            if entry.checked().matches(&view) {
+                count += 1;
+                dispatch.exhausted |= spend(&mut entry.remaining);
+                entry.listener.accept(evt).await;
+            }
+        }
+        dispatch.scan.metrics.record(count);
+    }
+
+    /// Delivers `evt` to every matching listener at once, waiting until they have all finished.
+    pub async fn accept_concurrent<E: Tags>(&mut self, evt: &E)
+    where
+        L: AsyncListener<E>,
+    {
+        let view = self.scan.view(evt);
+        if !self.scan.may_match(&view) {
+            self.scan.metrics.record(0);
+            return;
+        }
+        let mut dispatch = Dispatch {
+            scan: &mut self.scan,
+            exhausted: false,
+        };
+        let mut pending = Vec::new();
+        for entry in dispatch.scan.listeners.iter_mut() {
+            if entry.checked().matches(&view) {
+                dispatch.exhausted |= spend(&mut entry.remaining);
+                pending.push(entry.listener.accept(evt));
+            }
+        }
+        let count = pending.len();
+        join_all(pending).await;
+        dispatch.scan.metrics.record(count);
+    }
+}
+
+// Removes the subscriptions an event used up once it's been dispatched. Doing so on drop covers a
+// dispatch whose future is dropped partway through, e.g. by a timeout: otherwise a used-up
+// subscription would stay behind and be delivered to again.
+struct Dispatch<'a, L> {
+    scan: &'a mut LinearScan<L>,
+    exhausted: bool,
+}
+impl<L> Drop for Dispatch<'_, L> {
+    fn drop(&mut self) {
+        if self.exhausted {
+            self.scan.remove_exhausted();
+        }
+    }
+}
+impl<L> Topic<L> for AsyncLinearScan<L> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.scan.subscribe(listener, filter)
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.scan.subscribe_any(listener, filters)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.scan.subscribe_limited(listener, filter, max)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.scan.unsubscribe(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::{Cell, RefCell},
+        rc::Rc,
+    };
+
+    use futures::FutureExt;
+
+    use super::*;
+
+    // Logs when it starts and finishes each event, yielding to the runtime in between.
+    struct Slow(&'static str, Rc<RefCell<Vec<String>>>);
+    impl AsyncListener for Slow {
+        async fn accept(&mut self, evt: &Event) {
+            self.1
+                .borrow_mut()
+                .push(format!("{} start {}", self.0, &evt["n"]));
+            tokio::task::yield_now().await;
+            self.1
+                .borrow_mut()
+                .push(format!("{} end {}", self.0, &evt["n"]));
+        }
+    }
+
+    fn topic(log: &Rc<RefCell<Vec<String>>>) -> AsyncLinearScan<Slow> {
+        let mut topic = AsyncLinearScan::default();
+        topic.subscribe(Slow("a", log.clone()), Filter::EMPTY);
+        topic.subscribe(Slow("b", log.clone()), Filter::single("n", "1"));
+        topic
+    }
+
+    #[tokio::test]
+    async fn accept_awaits_listeners_in_order() {
+        let log = Rc::default();
+        let mut topic = topic(&log);
+        topic.accept(&Event::single("n", "1")).await;
+        topic.accept(&Event::single("n", "2")).await;
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                "a start 1",
+                "a end 1",
+                "b start 1",
+                "b end 1",
+                "a start 2",
+                "a end 2"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn accept_concurrent_overlaps_listeners() {
+        let log = Rc::default();
+        let mut topic = topic(&log);
+        topic.accept_concurrent(&Event::single("n", "1")).await;
+        let log = log.borrow();
+        assert_eq!(log.len(), 4);
+        // Both listeners started before either finished.
+        assert!(log[..2].iter().all(|line| line.contains("start")));
+    }
+
+    #[tokio::test]
+    async fn limited_async_subscription() {
+        struct Count(Rc<Cell<u32>>);
+        impl AsyncListener for Count {
+            async fn accept(&mut self, _evt: &Event) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let count = Rc::default();
+        let mut topic = AsyncLinearScan::default();
+        let id = topic.subscribe_once(Count(Rc::clone(&count)), Filter::EMPTY);
+        topic.accept_concurrent(&Event::single("n", "1")).await;
+        topic.accept(&Event::single("n", "2")).await;
+        assert_eq!(count.get(), 1);
+        assert!(!topic.unsubscribe(id));
+    }
+
+    #[tokio::test]
+    async fn cancelled_accept_still_spends_limits() {
+        // Counts each event, then yields before finishing with it.
+        struct Count(Rc<Cell<u32>>);
+        impl AsyncListener for Count {
+            async fn accept(&mut self, _evt: &Event) {
+                self.0.set(self.0.get() + 1);
+                tokio::task::yield_now().await;
+            }
+        }
+
+        let count = Rc::default();
+        let mut topic = AsyncLinearScan::default();
+        topic.subscribe_once(Count(Rc::clone(&count)), Filter::EMPTY);
+        topic.subscribe_once(Count(Rc::clone(&count)), Filter::EMPTY);
+        // Each is dropped while a listener is still waiting.
+        assert!(topic
+            .accept(&Event::single("n", "1"))
+            .now_or_never()
+            .is_none());
+        assert_eq!(topic.len(), 1);
+        assert!(topic
+            .accept_concurrent(&Event::single("n", "2"))
+            .now_or_never()
+            .is_none());
+        assert_eq!(topic.len(), 0);
+        topic.accept(&Event::single("n", "3")).await;
+        assert_eq!(count.get(), 2);
+    }
+}
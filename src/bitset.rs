@@ -0,0 +1,446 @@
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+
+use crate::{spend, Filter, FilterSet, Listener, Metrics, SubscriptionId, Tags, Topic};
+
+/// A topic that turns each subscription into bitmasks over the `(tag, value)` pairs its filters
+/// mention, so that checking an event against a subscription takes a few bitwise operations
+/// rather than a lookup per constraint.
+///
+/// Every pair that a filter requires, excludes, or requires the absence of is assigned a slot the
+/// first time a subscription mentions it. Each event is translated into the set of slots it
+/// carries once, and then every subscription is checked against that set in turn, in
+/// subscription order. This beats a [`LinearScan`] when there are many subscriptions over tags
+/// with only a few values each, which keeps the masks short. Slots are never reclaimed, so a tag
+/// with an unbounded number of values, like a user id, will make every event more expensive to
+/// translate.
+///
+/// `tags`, `exclude` and `absent` are evaluated bitwise. A filter with `matchers` or
+/// `key_prefixes` is still ruled out bitwise when it can be, and checked in full otherwise.
+///
+/// Like a [`LinearScan`], a scanner is `Send` if its listeners are `Send`, and `Sync` if they
+/// are `Sync`.
+///
+/// [`LinearScan`]: crate::LinearScan
+#[derive(Clone, Debug)]
+pub struct BitsetScanner<L> {
+    // tag -> the slots of the values subscribed for it, and of its mere presence.
+    slots: BTreeMap<String, TagSlots>,
+    slot_count: usize,
+    listeners: BTreeMap<SubscriptionId, BitsetEntry<L>>,
+    next_id: usize,
+    // Set by `set_dead_letter`.
+    dead_letter: Option<L>,
+    metrics: Metrics,
+}
+#[derive(Clone, Debug, Default)]
+struct TagSlots {
+    values: BTreeMap<String, usize>,
+    // For presence-only and `absent` constraints.
+    present: Option<usize>,
+}
+#[derive(Clone, Debug)]
+struct BitsetEntry<L> {
+    listener: L,
+    // A plain subscription is stored as a set of one filter, with one set of masks per filter.
+    filters: FilterSet,
+    masks: Vec<Masks>,
+    // Whether the masks capture every constraint, so that the filters needn't be checked again.
+    exact: bool,
+    // How many more events a limited subscription may receive.
+    remaining: Option<usize>,
+}
+// A filter as bitmasks over slots. Masks are only as long as the slots they mention need, and
+// an event's set of slots is always at least that long.
+#[derive(Clone, Debug, Default)]
+struct Masks {
+    // The event must carry every one of these: single-valued and presence-only tags.
+    all: Vec<u64>,
+    // For each tag that lists several values, the event must carry at least one of them.
+    any: Vec<Vec<u64>>,
+    // The event must carry none of these: excluded values and absent tags.
+    none: Vec<u64>,
+}
+impl Masks {
+    fn matches(&self, carried: &[u64]) -> bool {
+        self.all.iter().zip(carried).all(|(m, c)| m & c == *m)
+            && self
+                .any
+                .iter()
+                .all(|mask| mask.iter().zip(carried).any(|(m, c)| m & c != 0))
+            && self.none.iter().zip(carried).all(|(m, c)| m & c == 0)
+    }
+}
+
+fn set(mask: &mut Vec<u64>, slot: usize) {
+    let word = slot / 64;
+    if mask.len() <= word {
+        mask.resize(word + 1, 0);
+    }
+    mask[word] |= 1 << (slot % 64);
+}
+
+impl<L> Default for BitsetScanner<L> {
+    fn default() -> Self {
+        Self {
+            slots: BTreeMap::new(),
+            slot_count: 0,
+            listeners: BTreeMap::new(),
+            next_id: 0,
+            dead_letter: None,
+            metrics: Metrics::default(),
+        }
+    }
+}
+impl<L> BitsetScanner<L> {
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// How many `(tag, value)` slots have been assigned, including those of subscriptions that
+    /// are gone since.
+    pub fn slot_count(&self) -> usize {
+        self.slot_count
+    }
+
+    /// What the scanner has dispatched so far. See
+    /// [`LinearScan::metrics`](crate::LinearScan::metrics).
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Makes `listener` the dead letter, which receives each event that no subscription's
+    /// listener was delivered to. See
+    /// [`LinearScan::set_dead_letter`](crate::LinearScan::set_dead_letter).
+    pub fn set_dead_letter(&mut self, listener: L) {
+        self.dead_letter = Some(listener);
+    }
+
+    /// The listener set by `set_dead_letter`, if any.
+    pub fn dead_letter(&self) -> Option<&L> {
+        self.dead_letter.as_ref()
+    }
+
+    /// The listener subscribed as `id`, if it's still subscribed.
+    pub fn get(&self, id: SubscriptionId) -> Option<&L> {
+        self.listeners.get(&id).map(|entry| &entry.listener)
+    }
+
+    pub fn get_mut(&mut self, id: SubscriptionId) -> Option<&mut L> {
+        self.listeners.get_mut(&id).map(|entry| &mut entry.listener)
+    }
+
+    /// Delivers `evt` like `accept` does, returning how many listeners it was delivered to.
+    pub fn accept_counted<E: Tags>(&mut self, evt: &E) -> usize
+    where
+        L: Listener<E>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = crate::trace::dispatch_span("BitsetScanner", evt);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let carried = self.carried(evt);
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        for (&id, entry) in self.listeners.iter_mut() {
+            if !entry.matches(&carried, evt) {
+                continue;
+            }
+            if entry.listener.is_expired() {
+                exhausted.push(id);
+                continue;
+            }
+            if spend(&mut entry.remaining) {
+                exhausted.push(id);
+            }
+            count += 1;
+            #[cfg(feature = "tracing")]
+            tracing::debug!(subscription = id.0, "delivering");
+            if entry.listener.accept_flow(evt).is_break() {
+                break;
+            }
+        }
+        for id in exhausted {
+            self.unsubscribe(id);
+        }
+        self.metrics.record(count);
+        if count == 0 {
+            if let Some(listener) = &mut self.dead_letter {
+                listener.accept(evt);
+            }
+        }
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&span, count);
+        count
+    }
+
+    /// The ids of the subscriptions `evt` would be delivered to, in delivery order, without
+    /// invoking any listeners. A listener that stops propagation isn't taken into account.
+    pub fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        let carried = self.carried(evt);
+        self.listeners
+            .iter()
+            .filter(|(_, entry)| entry.matches(&carried, evt))
+            .map(|(&id, _)| id)
+            .collect()
+    }
+
+    // The slots `evt` carries.
+    fn carried<E: Tags>(&self, evt: &E) -> Vec<u64> {
+        let mut carried = vec![0; self.slot_count.div_ceil(64)];
+        for (tag, slots) in &self.slots {
+            let mut values = evt.values(tag).peekable();
+            if values.peek().is_none() {
+                continue;
+            }
+            if let Some(slot) = slots.present {
+                set(&mut carried, slot);
+            }
+            for v in values {
+                if let Some(&slot) = slots.values.get(v) {
+                    set(&mut carried, slot);
+                }
+            }
+        }
+        carried
+    }
+
+    fn slot(&mut self, tag: &str, value: Option<&str>) -> usize {
+        let slots = self.slots.entry(tag.into()).or_default();
+        let slot = match value {
+            Some(v) => slots.values.get(v).copied(),
+            None => slots.present,
+        };
+        if let Some(slot) = slot {
+            return slot;
+        }
+        let slot = self.slot_count;
+        self.slot_count += 1;
+        match value {
+            Some(v) => {
+                slots.values.insert(v.into(), slot);
+            }
+            None => slots.present = Some(slot),
+        }
+        slot
+    }
+
+    fn compile(&mut self, filter: &Filter) -> Masks {
+        let mut masks = Masks::default();
+        for (tag, values) in &filter.tags {
+            match values.len() {
+                0 => set(&mut masks.all, self.slot(tag, None)),
+                1 => {
+                    let v = values.first().unwrap();
+                    set(&mut masks.all, self.slot(tag, Some(v)));
+                }
+                _ => {
+                    let mut any = Vec::new();
+                    for v in values {
+                        set(&mut any, self.slot(tag, Some(v)));
+                    }
+                    masks.any.push(any);
+                }
+            }
+        }
+        for (tag, values) in &filter.exclude {
+            for v in values {
+                set(&mut masks.none, self.slot(tag, Some(v)));
+            }
+        }
+        for tag in &filter.absent {
+            set(&mut masks.none, self.slot(tag, None));
+        }
+        masks
+    }
+
+    fn insert(
+        &mut self,
+        listener: L,
+        filters: FilterSet,
+        remaining: Option<usize>,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        if remaining == Some(0) {
+            return id;
+        }
+        let masks = filters.filters.iter().map(|f| self.compile(f)).collect();
+        let exact = filters
+            .filters
+            .iter()
+            .all(|f| f.matchers.is_empty() && f.key_prefixes.is_empty());
+        self.listeners.insert(
+            id,
+            BitsetEntry {
+                listener,
+                filters,
+                masks,
+                exact,
+                remaining,
+            },
+        );
+        id
+    }
+}
+impl<L> BitsetEntry<L> {
+    fn matches<E: Tags>(&self, carried: &[u64], evt: &E) -> bool {
+        if self.exact {
+            self.masks.iter().any(|m| m.matches(carried))
+        } else {
+            self.masks
+                .iter()
+                .zip(&self.filters.filters)
+                .any(|(m, f)| m.matches(carried) && f.matches(evt))
+        }
+    }
+}
+impl<L> Topic<L> for BitsetScanner<L> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), None)
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.insert(listener, filters, None)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), Some(max))
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.listeners.remove(&id).is_some()
+    }
+}
+impl<E: Tags, L: Listener<E>> Listener<E> for BitsetScanner<L> {
+    fn accept(&mut self, evt: &E) {
+        self.accept_counted(evt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use super::*;
+    use crate::{Event, LinearScan, MultiEvent, ValueMatch};
+
+    const TAGS: [&str; 3] = ["env", "region", "tier"];
+    const VALUES: [&str; 4] = ["a", "b", "c", "d"];
+
+    // A small deterministic generator, so that failures are reproducible.
+    struct Rng(u64);
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+
+        fn values(&mut self) -> BTreeSet<String> {
+            (0..self.below(3))
+                .map(|_| VALUES[self.below(4)].to_owned())
+                .collect()
+        }
+
+        fn filter(&mut self) -> Filter {
+            let mut filter = Filter::EMPTY;
+            for tag in TAGS {
+                match self.below(6) {
+                    0 | 1 => {
+                        filter.tags.insert(tag.to_owned(), self.values());
+                    }
+                    2 => {
+                        filter.exclude.insert(tag.to_owned(), self.values());
+                    }
+                    3 => {
+                        filter.absent.insert(tag.to_owned());
+                    }
+                    _ => {}
+                }
+            }
+            if self.below(8) == 0 {
+                let m = ValueMatch::Prefix(VALUES[self.below(4)].to_owned());
+                filter.matchers.insert(TAGS[self.below(3)].to_owned(), m);
+            }
+            filter
+        }
+
+        fn event(&mut self) -> Event {
+            let mut evt = Event::builder().build();
+            for tag in TAGS {
+                if self.below(4) != 0 {
+                    evt.tags
+                        .insert(tag.to_owned(), VALUES[self.below(4)].to_owned());
+                }
+            }
+            evt
+        }
+    }
+
+    #[derive(Clone, Copy, Default)]
+    struct Noop;
+    impl<E> Listener<E> for Noop {
+        fn accept(&mut self, _evt: &E) {}
+    }
+
+    #[test]
+    fn matches_like_linear_scan() {
+        let mut rng = Rng(7);
+        let mut bitset = BitsetScanner::default();
+        let mut linear = LinearScan::default();
+        for _ in 0..300 {
+            let filter = rng.filter();
+            if rng.below(5) == 0 {
+                let filters = FilterSet::from(vec![filter, rng.filter()]);
+                bitset.subscribe_any(Noop, filters.clone());
+                linear.subscribe_any(Noop, filters);
+            } else {
+                bitset.subscribe(Noop, filter.clone());
+                linear.subscribe(Noop, filter);
+            }
+        }
+        for _ in 0..500 {
+            let evt = rng.event();
+            assert_eq!(
+                bitset.matching_ids(&evt),
+                linear.matching_ids(&evt),
+                "{:?}",
+                evt
+            );
+            assert_eq!(bitset.accept_counted(&evt), linear.accept_counted(&evt));
+        }
+        let multi = MultiEvent {
+            tags: BTreeMap::from([
+                (
+                    "env".to_owned(),
+                    BTreeSet::from(["a".to_owned(), "b".to_owned()]),
+                ),
+                ("region".to_owned(), BTreeSet::from(["c".to_owned()])),
+            ]),
+            data: (),
+        };
+        assert_eq!(bitset.matching_ids(&multi), linear.matching_ids(&multi));
+    }
+
+    #[test]
+    fn limited_and_unsubscribed() {
+        let mut topic = BitsetScanner::default();
+        let once = topic.subscribe_once(Noop, Filter::single("env", "a"));
+        let always = topic.subscribe(Noop, Filter::single("env", "a"));
+        let evt = Event::single("env", "a");
+        assert_eq!(topic.matching_ids(&evt), vec![once, always]);
+        assert_eq!(topic.accept_counted(&evt), 2);
+        assert_eq!(topic.matching_ids(&evt), vec![always]);
+        assert!(topic.unsubscribe(always));
+        assert_eq!(topic.accept_counted(&evt), 0);
+        assert!(topic.is_empty());
+        assert_eq!(topic.slot_count(), 1);
+    }
+}
@@ -0,0 +1,265 @@
+use alloc::{vec, vec::Vec};
+
+use crate::{Filter, Predicate, Tags};
+
+// The fewest slots per pair added before the filter is rebuilt bigger. With three probes per pair
+// this keeps false positives to a few percent.
+const SLOTS_PER_PAIR: usize = 8;
+const MIN_SLOTS: usize = 64;
+const PROBES: usize = 3;
+
+// A counting Bloom filter over the `(tag, value)` pairs that a `LinearScan`'s subscriptions
+// require, for turning away events that can't match any of them without visiting each one.
+//
+// Each subscription adds the values of one tag it requires, since a matching event has to carry
+// one of them. Subscriptions that can match an event without sharing a pair with it, like one that
+// only requires a tag to be present or an `Expr`, can't be ruled out this way: while there are
+// any, every event is a candidate. Keeping counts rather than bits lets subscriptions be taken
+// back out.
+#[derive(Clone)]
+pub(crate) struct Prefilter {
+    counts: Vec<u32>,
+    // How many pairs have been added, for deciding when to grow.
+    pairs: usize,
+    // How many subscriptions can't be ruled out by their pairs.
+    unfiltered: usize,
+}
+// The counts are summarized; there are far too many of them to be worth printing.
+impl core::fmt::Debug for Prefilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Prefilter")
+            .field("slots", &self.counts.len())
+            .field("pairs", &self.pairs)
+            .field("unfiltered", &self.unfiltered)
+            .finish()
+    }
+}
+
+// The pairs an event has to carry at least one of to match `predicate`, or `None` if it can match
+// without any.
+fn required_pairs(predicate: &Predicate) -> Option<Vec<(&str, &str)>> {
+    let filters: &[Filter] = match predicate {
+        Predicate::Filter(filter) => core::slice::from_ref(filter),
+        Predicate::Any(filters) => &filters.filters,
+        Predicate::Expr(_) => return None,
+    };
+    let mut pairs = Vec::new();
+    for filter in filters {
+        let (tag, values) = filter.tags.iter().find(|(_, vs)| !vs.is_empty())?;
+        pairs.extend(values.iter().map(|v| (tag.as_str(), v.as_str())));
+    }
+    Some(pairs)
+}
+
+impl Prefilter {
+    // A filter sized for `predicates` and as many again, with each of them added.
+    pub(crate) fn build<'a>(predicates: impl Iterator<Item = &'a Predicate> + Clone) -> Self {
+        let pairs: usize = predicates
+            .clone()
+            .filter_map(required_pairs)
+            .map(|pairs| pairs.len())
+            .sum();
+        let mut prefilter = Prefilter {
+            counts: vec![0; (2 * pairs * SLOTS_PER_PAIR).max(MIN_SLOTS)],
+            pairs: 0,
+            unfiltered: 0,
+        };
+        for predicate in predicates {
+            prefilter.add(predicate);
+        }
+        prefilter
+    }
+
+    // Whether enough pairs have been added since the filter was sized that it should be rebuilt
+    // bigger. Each rebuild doubles the room, so rebuilds get rarer as subscriptions pile up.
+    pub(crate) fn is_crowded(&self) -> bool {
+        self.pairs * SLOTS_PER_PAIR > self.counts.len()
+    }
+
+    pub(crate) fn add(&mut self, predicate: &Predicate) {
+        let Some(pairs) = required_pairs(predicate) else {
+            self.unfiltered += 1;
+            return;
+        };
+        for (tag, value) in pairs {
+            for slot in self.slots(tag, value) {
+                self.counts[slot] += 1;
+            }
+            self.pairs += 1;
+        }
+    }
+
+    // Takes back a predicate that was added before.
+    pub(crate) fn remove(&mut self, predicate: &Predicate) {
+        let Some(pairs) = required_pairs(predicate) else {
+            self.unfiltered -= 1;
+            return;
+        };
+        for (tag, value) in pairs {
+            for slot in self.slots(tag, value) {
+                self.counts[slot] -= 1;
+            }
+            self.pairs -= 1;
+        }
+    }
+
+    // False only if `evt` can't match any of the predicates added.
+    pub(crate) fn may_match<E: Tags>(&self, evt: &E) -> bool {
+        self.unfiltered > 0
+            || evt.keys().any(|tag| {
+                evt.values(tag).any(|value| {
+                    self.slots(tag, value)
+                        .iter()
+                        .all(|&slot| self.counts[slot] > 0)
+                })
+            })
+    }
+
+    fn slots(&self, tag: &str, value: &str) -> [usize; PROBES] {
+        // FNV-1a. No UTF-8 string contains 0xff, so it keeps e.g. ("ab", "c") apart from
+        // ("a", "bc").
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for b in tag.bytes().chain([0xff]).chain(value.bytes()) {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        // Every probe is derived from the two halves of the one hash; an odd step never cycles
+        // back early.
+        let (h1, h2) = (hash as u32 as usize, (hash >> 32) as usize | 1);
+        let len = self.counts.len();
+        core::array::from_fn(|i| h1.wrapping_add(i.wrapping_mul(h2)) % len)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use crate::{Event, Expr, Filter, FilterSet, LinearScan, Listener, Topic};
+
+    struct Noop;
+    impl Listener for Noop {
+        fn accept(&mut self, _evt: &Event) {}
+    }
+
+    // Every event over a few tags and values, including ones carrying none of them.
+    fn events() -> Vec<Event> {
+        let values = ["", "1", "2", "3"];
+        let mut events = Vec::new();
+        for a in values {
+            for b in values {
+                for c in values {
+                    let tags = [("a", a), ("b", b), ("c", c)];
+                    let tags: Vec<_> = tags.into_iter().filter(|(_, v)| !v.is_empty()).collect();
+                    events.push(tags.iter().copied().collect());
+                }
+            }
+        }
+        events.push(mkevt! {"d" => "1"});
+        events
+    }
+
+    fn assert_same_matches(plain: &LinearScan<Noop>, prefiltered: &LinearScan<Noop>) {
+        for evt in events() {
+            assert_eq!(prefiltered.matching_ids(&evt), plain.matching_ids(&evt));
+        }
+    }
+
+    #[test]
+    fn prefilter_never_drops_a_match() {
+        let mut plain = LinearScan::default();
+        let mut prefiltered = LinearScan::default();
+        // Enabled part way through, so it's built from existing subscriptions and then kept up.
+        let subscribe = |topic: &mut LinearScan<Noop>, i: usize| {
+            let v = (1 + i % 3).to_string();
+            match i % 4 {
+                0 => topic.subscribe(Noop, mkfilter! { "a" => [&v] }),
+                1 => topic.subscribe(Noop, mkfilter! { "a" => ["1", "2"], "b" => [&v] }),
+                2 => topic.subscribe_any(
+                    Noop,
+                    FilterSet::from(vec![mkfilter! { "b" => [&v] }, mkfilter! { "c" => ["3"] }]),
+                ),
+                _ => topic.subscribe_limited(Noop, mkfilter! { "c" => [&v] }, 1),
+            }
+        };
+        let mut ids = Vec::new();
+        for i in 0..100 {
+            if i == 10 {
+                prefiltered.enable_prefilter();
+            }
+            ids.push(subscribe(&mut plain, i));
+            assert_eq!(subscribe(&mut prefiltered, i), *ids.last().unwrap());
+        }
+        assert_same_matches(&plain, &prefiltered);
+        // Nothing requires `d`.
+        assert!(!prefiltered.may_match(&mkevt! {"d" => "1"}));
+
+        for id in ids.iter().step_by(3) {
+            plain.unsubscribe(*id);
+            prefiltered.unsubscribe(*id);
+        }
+        assert_same_matches(&plain, &prefiltered);
+        for evt in events() {
+            plain.accept(&evt);
+            prefiltered.accept(&evt);
+        }
+        assert_eq!(prefiltered.len(), plain.len());
+        assert_same_matches(&plain, &prefiltered);
+    }
+
+    #[test]
+    fn subscriptions_without_required_values_disable_the_prefilter() {
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("d".to_owned(), BTreeSet::new());
+        let mut excludes = Filter::EMPTY;
+        excludes
+            .exclude
+            .insert("a".to_owned(), BTreeSet::from(["1".to_owned()]));
+        let not_a = Expr::Not(Box::new(Expr::Tag {
+            key: "a".to_owned(),
+            values: BTreeSet::new(),
+        }));
+        let anys = [
+            FilterSet::from(vec![presence]),
+            FilterSet::from(vec![mkfilter! { "a" => ["1"] }, excludes.clone()]),
+            FilterSet::from(vec![Filter::EMPTY]),
+        ];
+        for any in anys {
+            let mut plain = LinearScan::default();
+            let mut prefiltered = LinearScan::default();
+            prefiltered.enable_prefilter();
+            for topic in [&mut plain, &mut prefiltered] {
+                topic.subscribe(Noop, mkfilter! { "a" => ["1"] });
+                topic.subscribe_any(Noop, any.clone());
+            }
+            assert_same_matches(&plain, &prefiltered);
+        }
+
+        let mut topic = LinearScan::default();
+        topic.enable_prefilter();
+        let id = topic.subscribe_expr(Noop, not_a);
+        assert!(topic.may_match(&mkevt! {"d" => "1"}));
+        // Once it's gone, events can be ruled out again.
+        topic.unsubscribe(id);
+        topic.subscribe(Noop, mkfilter! { "a" => ["1"] });
+        assert!(!topic.may_match(&mkevt! {"d" => "1"}));
+        assert!(topic.may_match(&mkevt! {"a" => "1"}));
+    }
+
+    #[test]
+    fn prefilter_grows() {
+        let mut topic = LinearScan::default();
+        topic.enable_prefilter();
+        for i in 0..1_000 {
+            topic.subscribe(Noop, mkfilter! { "a" => [&i.to_string()] });
+        }
+        let prefilter = topic.prefilter.as_ref().unwrap();
+        assert!(!prefilter.is_crowded());
+        assert!(prefilter.counts.len() >= 1_000);
+        let false_positives = (1_000..2_000)
+            .filter(|i| topic.may_match(&mkevt! {"a" => &i.to_string()}))
+            .count();
+        // A handful of false positives are expected, but not many.
+        assert!(false_positives < 100, "{} false positives", false_positives);
+    }
+}
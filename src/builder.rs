@@ -0,0 +1,378 @@
+use alloc::{
+    borrow::ToOwned,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+
+use crate::{Event, Filter};
+
+/// Builds an [`Event`] one tag at a time. Created by [`Event::builder`].
+pub struct EventBuilder<T = ()> {
+    tags: BTreeMap<String, String>,
+    data: T,
+}
+impl EventBuilder<()> {
+    /// Attaches a payload, replacing the default `()`.
+    pub fn data<T>(self, data: T) -> EventBuilder<T> {
+        EventBuilder {
+            tags: self.tags,
+            data,
+        }
+    }
+}
+impl<T> EventBuilder<T> {
+    /// Sets `tag` to `value`, overwriting any earlier value for the same tag.
+    pub fn tag(mut self, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(tag.into(), value.into());
+        self
+    }
+
+    pub fn build(self) -> Event<T> {
+        Event {
+            tags: self.tags,
+            data: self.data,
+        }
+    }
+}
+
+impl Event {
+    pub fn builder() -> EventBuilder {
+        EventBuilder {
+            tags: BTreeMap::new(),
+            data: (),
+        }
+    }
+
+    /// An event carrying a single tag.
+    ///
+    /// ```
+    /// use tagsub::Event;
+    ///
+    /// let evt = Event::single("hello", "world");
+    /// assert_eq!(evt.tags["hello"], "world");
+    /// ```
+    pub fn single(tag: impl Into<String>, value: impl Into<String>) -> Event {
+        Event::builder().tag(tag, value).build()
+    }
+}
+
+/// Collects `(tag, value)` pairs into an event with no payload. A tag that appears more than once
+/// keeps its last value.
+///
+/// ```
+/// use tagsub::Event;
+///
+/// let evt: Event = [("env", "prod"), ("region", "us")].into_iter().collect();
+/// assert_eq!(evt.tags["region"], "us");
+/// ```
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Event {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut evt = Event::builder().build();
+        evt.extend(iter);
+        evt
+    }
+}
+/// Sets each tag to its value, overwriting any earlier value for the same tag.
+impl<T, K: Into<String>, V: Into<String>> Extend<(K, V)> for Event<T> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.tags
+            .extend(iter.into_iter().map(|(k, v)| (k.into(), v.into())));
+    }
+}
+
+/// Builds a [`Filter`] one constraint at a time. Created by [`Filter::builder`].
+///
+/// Constraining the same tag more than once accepts any of the values given across all calls.
+pub struct FilterBuilder {
+    filter: Filter,
+}
+impl FilterBuilder {
+    /// Requires `tag` to be set to `value`.
+    pub fn eq(self, tag: impl Into<String>, value: impl Into<String>) -> Self {
+        self.any_of(tag, [value])
+    }
+
+    /// Requires `tag` to be set to one of `values`.
+    pub fn any_of<V: Into<String>>(
+        mut self,
+        tag: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.filter
+            .tags
+            .entry(tag.into())
+            .or_default()
+            .extend(values.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn build(self) -> Filter {
+        self.filter
+    }
+}
+
+impl Filter {
+    pub fn builder() -> FilterBuilder {
+        FilterBuilder {
+            filter: Filter::EMPTY,
+        }
+    }
+
+    /// A filter with no constraints, which matches every event. This is the same as
+    /// [`Filter::EMPTY`].
+    ///
+    /// ```
+    /// use tagsub::Filter;
+    ///
+    /// assert!(Filter::match_all().matches_all());
+    /// ```
+    pub const fn match_all() -> Filter {
+        Filter::EMPTY
+    }
+
+    /// A filter requiring `tag` to be set to `value`.
+    ///
+    /// ```
+    /// use tagsub::Filter;
+    ///
+    /// let filter = Filter::single("hello", "world");
+    /// assert!(filter.tags["hello"].contains("world"));
+    /// ```
+    pub fn single(tag: impl Into<String>, value: impl Into<String>) -> Filter {
+        Filter::builder().eq(tag, value).build()
+    }
+
+    /// A filter requiring `tag` to be set to one of `values`.
+    ///
+    /// ```
+    /// use tagsub::Filter;
+    ///
+    /// let filter = Filter::single_any("color", ["red", "blue"]);
+    /// assert_eq!(filter.tags["color"].len(), 2);
+    /// ```
+    pub fn single_any<V: Into<String>>(
+        tag: impl Into<String>,
+        values: impl IntoIterator<Item = V>,
+    ) -> Filter {
+        Filter::builder().any_of(tag, values).build()
+    }
+
+    /// Reads a value of `*` as "any value": a tag whose values include `*` only needs to be
+    /// present, and an excluded tag whose values include `*` needs to be absent. Write `\*` for a
+    /// literal asterisk. Other values are left as they are.
+    ///
+    /// Without this, `*` is an ordinary value that only matches an event whose value is literally
+    /// `*`. With it, such an event matches `*` like any other value does, and only `\*` requires
+    /// the asterisk itself.
+    ///
+    /// ```
+    /// use tagsub::Filter;
+    ///
+    /// let any_color = Filter::single("color", "*").with_wildcard();
+    /// assert!(any_color.tags["color"].is_empty());
+    ///
+    /// let starred = Filter::single("rating", "\\*").with_wildcard();
+    /// assert_eq!(starred, Filter::single("rating", "*"));
+    /// ```
+    pub fn with_wildcard(mut self) -> Filter {
+        for values in self.tags.values_mut() {
+            if values.contains("*") {
+                values.clear();
+            } else {
+                unescape_asterisk(values);
+            }
+        }
+        let excluded: Vec<String> = self
+            .exclude
+            .iter()
+            .filter(|(_, values)| values.contains("*"))
+            .map(|(tag, _)| tag.clone())
+            .collect();
+        for tag in excluded {
+            self.exclude.remove(&tag);
+            self.absent.insert(tag);
+        }
+        self.exclude.values_mut().for_each(unescape_asterisk);
+        self
+    }
+}
+
+// Replaces an escaped asterisk with the literal value it stands for.
+fn unescape_asterisk(values: &mut BTreeSet<String>) {
+    if values.remove("\\*") {
+        values.insert("*".to_owned());
+    }
+}
+
+/// Collects `(tag, values)` pairs into a filter requiring each tag to be set to one of its values.
+/// Like [`FilterBuilder`], a tag that appears more than once accepts the values from every pair.
+impl<K: Into<String>> FromIterator<(K, BTreeSet<String>)> for Filter {
+    fn from_iter<I: IntoIterator<Item = (K, BTreeSet<String>)>>(iter: I) -> Self {
+        let mut filter = Filter::EMPTY;
+        filter.extend(iter);
+        filter
+    }
+}
+/// Adds each `(tag, values)` pair to the filter's required tags, merging the values of tags it
+/// already requires.
+impl<K: Into<String>> Extend<(K, BTreeSet<String>)> for Filter {
+    fn extend<I: IntoIterator<Item = (K, BTreeSet<String>)>>(&mut self, iter: I) {
+        for (tag, values) in iter {
+            self.tags.entry(tag.into()).or_default().extend(values);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn event_builder_matches_manual_construction() {
+        let mut tags = BTreeMap::new();
+        tags.insert("hello".to_owned(), "world".to_owned());
+        tags.insert("env".to_owned(), "prod".to_owned());
+        let manual = Event { tags, data: () };
+
+        let built = Event::builder()
+            .tag("hello", "world")
+            .tag("env", "dev")
+            .tag("env", "prod")
+            .build();
+        assert_eq!(built, manual);
+
+        let with_data = Event::builder().tag("hello", "world").data(7).build();
+        assert_eq!(with_data.data, 7);
+    }
+
+    #[test]
+    fn filter_builder_matches_manual_construction() {
+        let mut colors = BTreeSet::new();
+        colors.insert("red".to_owned());
+        colors.insert("blue".to_owned());
+        let mut envs = BTreeSet::new();
+        envs.insert("prod".to_owned());
+        let mut tags = BTreeMap::new();
+        tags.insert("color".to_owned(), colors);
+        tags.insert("env".to_owned(), envs);
+        let manual = Filter {
+            tags,
+            ..Filter::EMPTY
+        };
+
+        let built = Filter::builder()
+            .any_of("color", ["red", "blue"])
+            .eq("env", "prod")
+            .build();
+        assert_eq!(built, manual);
+
+        let split = Filter::builder()
+            .eq("color", "red")
+            .eq("env", "prod")
+            .eq("color", "blue")
+            .build();
+        assert_eq!(split, manual);
+
+        assert_eq!(Filter::builder().build(), Filter::EMPTY);
+    }
+
+    #[test]
+    fn single_constructors_match_manual_construction() {
+        let mut tags = BTreeMap::new();
+        tags.insert("hello".to_owned(), "world".to_owned());
+        assert_eq!(Event::single("hello", "world"), Event { tags, data: () });
+
+        let mut values = BTreeSet::new();
+        values.insert("world".to_owned());
+        let mut tags = BTreeMap::new();
+        tags.insert("hello".to_owned(), values.clone());
+        let manual = Filter {
+            tags,
+            ..Filter::EMPTY
+        };
+        assert_eq!(Filter::single("hello", "world"), manual);
+
+        values.insert("there".to_owned());
+        let mut tags = BTreeMap::new();
+        tags.insert("hello".to_owned(), values);
+        let manual = Filter {
+            tags,
+            ..Filter::EMPTY
+        };
+        assert_eq!(Filter::single_any("hello", ["world", "there"]), manual);
+    }
+
+    #[test]
+    fn wildcard_matches_any_value() {
+        let filter = Filter::builder()
+            .eq("color", "*")
+            .eq("env", "prod")
+            .build()
+            .with_wildcard();
+        assert!(filter.tags["color"].is_empty());
+        for color in ["red", "blue", "*"] {
+            let evt = Event::builder()
+                .tag("color", color)
+                .tag("env", "prod")
+                .build();
+            assert!(filter.matches(&evt), "{}", color);
+        }
+        assert!(!filter.matches(&Event::single("env", "prod")));
+
+        // Without the wildcard mode, `*` is just a value.
+        let plain = Filter::single("color", "*");
+        assert!(!plain.matches(&Event::single("color", "red")));
+        assert!(plain.matches(&Event::single("color", "*")));
+    }
+
+    #[test]
+    fn wildcard_escaped_asterisk_is_literal() {
+        let filter = Filter::single_any("rating", ["\\*", "5"]).with_wildcard();
+        assert_eq!(filter, Filter::single_any("rating", ["*", "5"]));
+        assert!(filter.matches(&Event::single("rating", "*")));
+        assert!(!filter.matches(&Event::single("rating", "4")));
+    }
+
+    #[test]
+    fn wildcard_exclusion_requires_absence() {
+        let mut filter = Filter::EMPTY;
+        filter
+            .exclude
+            .insert("debug".to_owned(), BTreeSet::from(["*".to_owned()]));
+        filter
+            .exclude
+            .insert("env".to_owned(), BTreeSet::from(["\\*".to_owned()]));
+        let filter = filter.with_wildcard();
+        assert!(filter.absent.contains("debug"));
+        assert_eq!(filter.exclude["env"], BTreeSet::from(["*".to_owned()]));
+        assert!(!filter.matches(&Event::single("debug", "0")));
+        assert!(!filter.matches(&Event::single("env", "*")));
+        assert!(filter.matches(&Event::single("env", "prod")));
+    }
+
+    #[test]
+    fn collect_and_extend() {
+        let mut evt: Event = [("hello", "world"), ("env", "dev")].into_iter().collect();
+        evt.extend([("env", "prod")]);
+        assert_eq!(
+            evt,
+            Event::builder()
+                .tag("hello", "world")
+                .tag("env", "prod")
+                .build()
+        );
+
+        let colors: BTreeSet<String> = ["red".to_owned()].into();
+        let mut filter: Filter = [("color", colors)].into_iter().collect();
+        filter.extend([
+            ("color", BTreeSet::from(["blue".to_owned()])),
+            ("env", BTreeSet::from(["prod".to_owned()])),
+        ]);
+        let manual = Filter::builder()
+            .any_of("color", ["red", "blue"])
+            .eq("env", "prod")
+            .build();
+        assert_eq!(filter, manual);
+    }
+}
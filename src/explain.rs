@@ -0,0 +1,239 @@
+use alloc::{collections::BTreeSet, string::String, vec::Vec};
+use core::fmt;
+
+use crate::{Event, Filter};
+
+/// Why an event did or didn't match a filter, as reported by [`explain`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MatchExplanation {
+    /// The tags whose constraints the event satisfied.
+    pub satisfied: BTreeSet<String>,
+    /// Every constraint the event failed. The event matched if this is empty.
+    pub failures: Vec<MatchFailure>,
+}
+impl MatchExplanation {
+    pub fn is_match(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// A single constraint that an event failed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchFailure {
+    /// The filter requires the tag, but the event doesn't carry it.
+    Missing { tag: String },
+    /// The event's value isn't one of the values `Filter::tags` requires.
+    Mismatch {
+        tag: String,
+        required: BTreeSet<String>,
+        found: String,
+    },
+    /// The event's value is one of the values `Filter::exclude` rules out.
+    Excluded { tag: String, found: String },
+    /// The event carries a tag that `Filter::absent` rules out.
+    Present { tag: String, found: String },
+    /// The event's value is rejected by the tag's entry in `Filter::matchers`.
+    Rejected { tag: String, found: String },
+    /// None of the event's tags has a key starting with a prefix from `Filter::key_prefixes`.
+    NoKeyWithPrefix { prefix: String },
+}
+impl fmt::Display for MatchFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MatchFailure::Missing { tag } => write!(f, "tag `{}` missing from event", tag),
+            MatchFailure::Mismatch {
+                tag,
+                required,
+                found,
+            } => {
+                let required: Vec<&str> = required.iter().map(String::as_str).collect();
+                write!(
+                    f,
+                    "tag `{}` required {{{}}} but event had `{}`",
+                    tag,
+                    required.join(", "),
+                    found
+                )
+            }
+            MatchFailure::Excluded { tag, found } => {
+                write!(f, "tag `{}` excludes `{}`, which the event had", tag, found)
+            }
+            MatchFailure::Present { tag, found } => {
+                write!(f, "tag `{}` must be absent but event had `{}`", tag, found)
+            }
+            MatchFailure::Rejected { tag, found } => {
+                write!(f, "tag `{}` matcher rejected `{}`", tag, found)
+            }
+            MatchFailure::NoKeyWithPrefix { prefix } => {
+                write!(f, "event has no tag starting with `{}`", prefix)
+            }
+        }
+    }
+}
+
+/// Checks `evt` against every constraint in `filter`, rather than stopping at the first failure
+/// the way delivery does.
+pub fn explain<T>(filter: &Filter, evt: &Event<T>) -> MatchExplanation {
+    let mut satisfied = BTreeSet::new();
+    let mut failures = Vec::new();
+    let mut check = |tag: &String, failure: Option<MatchFailure>| match failure {
+        Some(failure) => failures.push(failure),
+        None => {
+            satisfied.insert(tag.clone());
+        }
+    };
+    for (tag, values) in &filter.tags {
+        let failure = match evt.tags.get(tag) {
+            None => Some(MatchFailure::Missing { tag: tag.clone() }),
+            Some(v) if !values.is_empty() && !values.contains(v) => Some(MatchFailure::Mismatch {
+                tag: tag.clone(),
+                required: values.clone(),
+                found: v.clone(),
+            }),
+            Some(_) => None,
+        };
+        check(tag, failure);
+    }
+    for (tag, values) in &filter.exclude {
+        let failure =
+            evt.tags
+                .get(tag)
+                .filter(|v| values.contains(*v))
+                .map(|v| MatchFailure::Excluded {
+                    tag: tag.clone(),
+                    found: v.clone(),
+                });
+        check(tag, failure);
+    }
+    for tag in &filter.absent {
+        let failure = evt.tags.get(tag).map(|v| MatchFailure::Present {
+            tag: tag.clone(),
+            found: v.clone(),
+        });
+        check(tag, failure);
+    }
+    for (tag, m) in &filter.matchers {
+        let failure = match evt.tags.get(tag) {
+            None => Some(MatchFailure::Missing { tag: tag.clone() }),
+            Some(v) if !m.matches(v) => Some(MatchFailure::Rejected {
+                tag: tag.clone(),
+                found: v.clone(),
+            }),
+            Some(_) => None,
+        };
+        check(tag, failure);
+    }
+    // A satisfied prefix is reported by the first tag that satisfies it.
+    for prefix in &filter.key_prefixes {
+        match evt.tags.keys().find(|key| key.starts_with(prefix.as_str())) {
+            Some(key) => check(key, None),
+            None => check(
+                prefix,
+                Some(MatchFailure::NoKeyWithPrefix {
+                    prefix: prefix.clone(),
+                }),
+            ),
+        }
+    }
+    MatchExplanation {
+        satisfied,
+        failures,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ValueMatch;
+
+    fn prod_us() -> Filter {
+        Filter::builder()
+            .eq("env", "prod")
+            .any_of("region", ["us", "eu"])
+            .build()
+    }
+
+    #[test]
+    fn full_match() {
+        let evt = Event::builder()
+            .tag("env", "prod")
+            .tag("region", "us")
+            .build();
+        let explanation = explain(&prod_us(), &evt);
+        assert!(explanation.is_match());
+        assert_eq!(
+            explanation.satisfied,
+            BTreeSet::from(["env".to_owned(), "region".to_owned()])
+        );
+    }
+
+    #[test]
+    fn value_mismatch() {
+        let evt = Event::builder()
+            .tag("env", "staging")
+            .tag("region", "us")
+            .build();
+        let explanation = explain(&prod_us(), &evt);
+        assert_eq!(
+            explanation.failures,
+            vec![MatchFailure::Mismatch {
+                tag: "env".to_owned(),
+                required: BTreeSet::from(["prod".to_owned()]),
+                found: "staging".to_owned(),
+            }]
+        );
+        assert_eq!(
+            explanation.failures[0].to_string(),
+            "tag `env` required {prod} but event had `staging`"
+        );
+        assert_eq!(explanation.satisfied, BTreeSet::from(["region".to_owned()]));
+    }
+
+    #[test]
+    fn missing_tag() {
+        let explanation = explain(&prod_us(), &Event::single("env", "prod"));
+        assert_eq!(
+            explanation.failures,
+            vec![MatchFailure::Missing {
+                tag: "region".to_owned()
+            }]
+        );
+        assert_eq!(
+            explanation.failures[0].to_string(),
+            "tag `region` missing from event"
+        );
+    }
+
+    #[test]
+    fn agrees_with_matches() {
+        let mut filter = prod_us();
+        filter
+            .exclude
+            .insert("tier".to_owned(), BTreeSet::from(["free".to_owned()]));
+        filter.absent.insert("deprecated".to_owned());
+        filter
+            .matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        filter.key_prefixes.insert("k8s.".to_owned());
+        let events = [
+            Event::builder()
+                .tag("env", "prod")
+                .tag("region", "eu")
+                .tag("svc", "api.users")
+                .tag("k8s.pod", "api-0")
+                .build(),
+            Event::builder()
+                .tag("env", "prod")
+                .tag("region", "eu")
+                .tag("svc", "web")
+                .tag("tier", "free")
+                .tag("deprecated", "yes")
+                .build(),
+        ];
+        for evt in &events {
+            assert_eq!(explain(&filter, evt).is_match(), filter.matches(evt));
+        }
+        assert_eq!(explain(&filter, &events[1]).failures.len(), 4);
+        assert!(explain(&filter, &events[0]).satisfied.contains("k8s.pod"));
+    }
+}
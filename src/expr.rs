@@ -0,0 +1,82 @@
+use alloc::{boxed::Box, collections::BTreeSet, string::String, vec::Vec};
+
+use crate::Tags;
+
+/// An arbitrary boolean combination of tag constraints, for subscriptions that a flat [`Filter`]
+/// can't express. Only `LinearScan` accepts these, via [`LinearScan::subscribe_expr`].
+///
+/// [`Filter`]: crate::Filter
+/// [`LinearScan::subscribe_expr`]: crate::LinearScan::subscribe_expr
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Expr {
+    /// Every sub-expression matches. An empty `And` matches everything.
+    And(Vec<Expr>),
+    /// At least one sub-expression matches. An empty `Or` matches nothing.
+    Or(Vec<Expr>),
+    /// The sub-expression doesn't match.
+    Not(Box<Expr>),
+    /// The event carries `key`, set to one of `values`. Empty `values` only require the tag to be
+    /// present, as in `Filter::tags`.
+    Tag {
+        key: String,
+        values: BTreeSet<String>,
+    },
+}
+impl Expr {
+    pub fn matches<E: Tags>(&self, evt: &E) -> bool {
+        match self {
+            Expr::And(exprs) => exprs.iter().all(|e| e.matches(evt)),
+            Expr::Or(exprs) => exprs.iter().any(|e| e.matches(evt)),
+            Expr::Not(expr) => !expr.matches(evt),
+            Expr::Tag { key, values } if values.is_empty() => evt.has_tag(key),
+            Expr::Tag { key, values } => evt.values(key).any(|v| values.contains(v)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Event;
+
+    fn tag(key: &str, values: &[&str]) -> Expr {
+        Expr::Tag {
+            key: key.to_owned(),
+            values: values.iter().map(|v| v.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn leaves_and_identities() {
+        let evt = Event::single("env", "prod");
+        assert!(tag("env", &["prod", "dev"]).matches(&evt));
+        assert!(tag("env", &[]).matches(&evt));
+        assert!(!tag("env", &["dev"]).matches(&evt));
+        assert!(!tag("region", &[]).matches(&evt));
+        assert!(Expr::And(Vec::new()).matches(&evt));
+        assert!(!Expr::Or(Vec::new()).matches(&evt));
+    }
+
+    #[test]
+    fn nested_or_inside_and_inside_not() {
+        // Anything except prod traffic from the US or EU.
+        let expr = Expr::Not(Box::new(Expr::And(vec![
+            tag("env", &["prod"]),
+            Expr::Or(vec![tag("region", &["us"]), tag("region", &["eu"])]),
+        ])));
+
+        let evt = |env: &str, region: &str| {
+            Event::builder()
+                .tag("env", env)
+                .tag("region", region)
+                .build()
+        };
+        assert!(!expr.matches(&evt("prod", "us")));
+        assert!(!expr.matches(&evt("prod", "eu")));
+        assert!(expr.matches(&evt("prod", "ap")));
+        assert!(expr.matches(&evt("dev", "us")));
+        assert!(expr.matches(&Event::single("env", "prod")));
+    }
+}
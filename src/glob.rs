@@ -0,0 +1,152 @@
+use alloc::{string::String, vec::Vec};
+
+/// A compiled glob pattern for [`ValueMatch::Glob`](crate::ValueMatch::Glob).
+///
+/// `*` matches any run of characters, including an empty one, and `?` matches exactly one
+/// character. A backslash makes the character after it literal, so `\*` matches a `*`.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(from = "String", into = "String"))]
+pub struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+}
+
+#[derive(Clone, Debug)]
+enum Token {
+    Literal(String),
+    AnyChar,
+    AnyRun,
+}
+
+impl Glob {
+    pub fn new(pattern: impl Into<String>) -> Self {
+        let pattern = pattern.into();
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.chars();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '*' => Token::AnyRun,
+                '?' => Token::AnyChar,
+                // A trailing backslash has nothing to escape, so it stands for itself.
+                '\\' => {
+                    literal.push(chars.next().unwrap_or('\\'));
+                    continue;
+                }
+                c => {
+                    literal.push(c);
+                    continue;
+                }
+            };
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(core::mem::take(&mut literal)));
+            }
+            tokens.push(token);
+        }
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+        Self { pattern, tokens }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        let (mut t, mut v) = (0, 0);
+        // Where to resume if the tokens after the most recent `*` fail to match: that `*` then
+        // swallows one more character.
+        let mut retry: Option<(usize, usize)> = None;
+        loop {
+            let rest = &value[v..];
+            let advanced = match self.tokens.get(t) {
+                None if rest.is_empty() => return true,
+                None => None,
+                Some(Token::Literal(lit)) => rest.starts_with(lit.as_str()).then_some(lit.len()),
+                Some(Token::AnyChar) => rest.chars().next().map(char::len_utf8),
+                Some(Token::AnyRun) => {
+                    retry = Some((t + 1, v));
+                    Some(0)
+                }
+            };
+            if let Some(len) = advanced {
+                t += 1;
+                v += len;
+                continue;
+            }
+            let Some((rt, rv)) = retry else {
+                return false;
+            };
+            let Some(c) = value[rv..].chars().next() else {
+                return false;
+            };
+            retry = Some((rt, rv + c.len_utf8()));
+            t = rt;
+            v = rv + c.len_utf8();
+        }
+    }
+}
+
+impl PartialEq for Glob {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern
+    }
+}
+
+impl From<String> for Glob {
+    fn from(pattern: String) -> Self {
+        Glob::new(pattern)
+    }
+}
+
+impl From<Glob> for String {
+    fn from(glob: Glob) -> Self {
+        glob.pattern
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn star_spans_segments() {
+        let g = Glob::new("/api/*/42");
+        assert!(g.matches("/api/users/42"));
+        assert!(g.matches("/api/users/v2/42"));
+        assert!(g.matches("/api//42"));
+        assert!(!g.matches("/api/users/43"));
+        assert!(!g.matches("/api/users/42/comments"));
+
+        let g = Glob::new("*.json");
+        assert!(g.matches(".json"));
+        assert!(g.matches("a.json.json"));
+        assert!(!g.matches("a.json.bak"));
+        assert!(Glob::new("*").matches(""));
+        assert!(Glob::new("a*b*c").matches("aXbYbZc"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_char() {
+        let g = Glob::new("v?");
+        assert!(g.matches("v1"));
+        assert!(g.matches("vé"));
+        assert!(!g.matches("v"));
+        assert!(!g.matches("v10"));
+        assert!(Glob::new("??*").matches("ab"));
+        assert!(!Glob::new("??*").matches("a"));
+    }
+
+    #[test]
+    fn escaped_wildcards_are_literal() {
+        let g = Glob::new(r"\*\?");
+        assert!(g.matches("*?"));
+        assert!(!g.matches("ab"));
+        assert!(Glob::new(r"a\\b").matches(r"a\b"));
+        assert!(Glob::new(r"trailing\").matches(r"trailing\"));
+        assert!(Glob::new("").matches(""));
+        assert!(!Glob::new("").matches("x"));
+    }
+}
@@ -0,0 +1,130 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+
+use crate::{
+    BitsetScanner, InvertedIndex, LinearScan, Listener, SubscriptionId, Tags, TreeScanner,
+};
+
+/// A topic that can tell which subscriptions an event would be delivered to without delivering it.
+pub trait MatchingIds {
+    /// The ids of the subscriptions `evt` would be delivered to, in delivery order.
+    fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId>;
+}
+impl<L> MatchingIds for LinearScan<L> {
+    fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        LinearScan::matching_ids(self, evt)
+    }
+}
+impl<L> MatchingIds for TreeScanner<L> {
+    fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        TreeScanner::matching_ids(self, evt)
+    }
+}
+impl<L> MatchingIds for InvertedIndex<L> {
+    fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        InvertedIndex::matching_ids(self, evt)
+    }
+}
+impl<L> MatchingIds for BitsetScanner<L> {
+    fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        BitsetScanner::matching_ids(self, evt)
+    }
+}
+
+/// Tallies how many events each of a topic's subscriptions matches, instead of delivering them.
+///
+/// The wrapped topic's listeners are never invoked, so a limited subscription is counted for
+/// every event it matches without ever being used up. Subscribe through
+/// [`MatchHistogram::topic_mut`].
+#[derive(Clone, Debug, Default)]
+pub struct MatchHistogram<T> {
+    topic: T,
+    counts: BTreeMap<SubscriptionId, u64>,
+}
+impl<T: MatchingIds> MatchHistogram<T> {
+    pub fn new(topic: T) -> Self {
+        Self {
+            topic,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    pub fn topic(&self) -> &T {
+        &self.topic
+    }
+
+    pub fn topic_mut(&mut self) -> &mut T {
+        &mut self.topic
+    }
+
+    pub fn into_inner(self) -> T {
+        self.topic
+    }
+
+    /// Counts a match for each subscription `evt` matches.
+    pub fn accept<E: Tags>(&mut self, evt: &E) {
+        for id in self.topic.matching_ids(evt) {
+            *self.counts.entry(id).or_default() += 1;
+        }
+    }
+
+    /// Counts the matches of each of `evts` in turn.
+    pub fn accept_batch<E: Tags>(&mut self, evts: &[E]) {
+        for evt in evts {
+            self.accept(evt);
+        }
+    }
+
+    /// How many events each subscription has matched so far. Subscriptions that haven't matched
+    /// any are left out.
+    pub fn counts(&self) -> &BTreeMap<SubscriptionId, u64> {
+        &self.counts
+    }
+
+    /// Starts counting again from zero.
+    pub fn reset(&mut self) {
+        self.counts.clear();
+    }
+}
+impl<E: Tags, T: MatchingIds> Listener<E> for MatchHistogram<T> {
+    fn accept(&mut self, evt: &E) {
+        MatchHistogram::accept(self, evt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Event, Filter, Topic};
+
+    struct Unreachable;
+    impl Listener for Unreachable {
+        fn accept(&mut self, _evt: &Event) {
+            panic!("a histogram doesn't deliver events");
+        }
+    }
+
+    #[test]
+    fn histogram_counts_matches_per_subscription() {
+        let mut histogram = MatchHistogram::new(TreeScanner::default());
+        let topic = histogram.topic_mut();
+        let prod = topic.subscribe(Unreachable, Filter::single("env", "prod"));
+        let us = topic.subscribe(Unreachable, Filter::single("region", "us"));
+        let once = topic.subscribe_once(Unreachable, Filter::single("env", "prod"));
+        let never = topic.subscribe(Unreachable, Filter::single("env", "staging"));
+
+        histogram.accept_batch(&[
+            mkevt! {"env" => "prod", "region" => "us"},
+            mkevt! {"env" => "prod", "region" => "eu"},
+            mkevt! {"env" => "dev", "region" => "us"},
+            mkevt! {"env" => "prod"},
+        ]);
+        assert_eq!(
+            *histogram.counts(),
+            BTreeMap::from([(prod, 3), (us, 2), (once, 3)])
+        );
+        assert!(!histogram.counts().contains_key(&never));
+
+        histogram.reset();
+        assert!(histogram.counts().is_empty());
+    }
+}
@@ -0,0 +1,206 @@
+use alloc::{
+    borrow::ToOwned,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+
+use crate::{Event, Filter};
+
+/// A string that has been interned into an [`Interner`]. Comparing symbols is a single integer
+/// comparison, no matter how long the strings are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Symbol(u32);
+impl Symbol {
+    /// Stands in for any event value the interner has never seen. Since every filter value is
+    /// interned, such a value can't be equal to any of them.
+    pub const UNKNOWN: Symbol = Symbol(u32::MAX);
+}
+
+/// The table that maps strings to symbols. Filters and events must be interned through the same
+/// table for their symbols to be comparable.
+#[derive(Default)]
+pub struct Interner {
+    symbols: BTreeMap<String, Symbol>,
+    strings: Vec<String>,
+}
+impl Interner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn intern(&mut self, s: &str) -> Symbol {
+        if let Some(&sym) = self.symbols.get(s) {
+            return sym;
+        }
+        let sym = Symbol(self.strings.len() as u32);
+        assert!(sym != Symbol::UNKNOWN, "interner is full");
+        self.strings.push(s.to_owned());
+        self.symbols.insert(s.to_owned(), sym);
+        sym
+    }
+
+    /// Looks up a string without interning it.
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        self.symbols.get(s).copied()
+    }
+
+    pub fn resolve(&self, sym: Symbol) -> Option<&str> {
+        self.strings.get(sym.0 as usize).map(String::as_str)
+    }
+
+    /// Interns every tag and value in `filter`. Returns `None` if the filter uses
+    /// `Filter::matchers` or `Filter::key_prefixes`, which need the original strings to be
+    /// evaluated.
+    pub fn intern_filter(&mut self, filter: &Filter) -> Option<InternedFilter> {
+        if !filter.matchers.is_empty() || !filter.key_prefixes.is_empty() {
+            return None;
+        }
+        let mut intern_map = |m: &BTreeMap<String, BTreeSet<String>>| {
+            m.iter()
+                .map(|(tag, values)| {
+                    let values = values.iter().map(|v| self.intern(v)).collect();
+                    (self.intern(tag), values)
+                })
+                .collect()
+        };
+        let tags = intern_map(&filter.tags);
+        let exclude = intern_map(&filter.exclude);
+        let absent = filter.absent.iter().map(|tag| self.intern(tag)).collect();
+        Some(InternedFilter {
+            tags,
+            exclude,
+            absent,
+        })
+    }
+
+    /// Translates `evt` into symbols without growing the table. Tags that no filter mentions are
+    /// dropped, and values no filter mentions become [`Symbol::UNKNOWN`].
+    pub fn lookup_event<T>(&self, evt: &Event<T>) -> InternedEvent {
+        let mut interned = InternedEvent::default();
+        self.lookup_event_into(evt, &mut interned);
+        interned
+    }
+
+    /// Like `lookup_event`, but translates `evt` into `out`, replacing whatever it held. `out`
+    /// keeps its storage, so translating a stream of events into the same `out` only allocates
+    /// when an event carries more known tags than any before it.
+    pub fn lookup_event_into<T>(&self, evt: &Event<T>, out: &mut InternedEvent) {
+        out.tags.clear();
+        out.tags.extend(evt.tags.iter().filter_map(|(tag, value)| {
+            let value = self.get(value).unwrap_or(Symbol::UNKNOWN);
+            Some((self.get(tag)?, value))
+        }));
+        out.tags.sort_unstable_by_key(|&(tag, _)| tag);
+    }
+}
+
+/// The tags of an [`Event`], translated by [`Interner::lookup_event`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct InternedEvent {
+    // Sorted by tag, which each appear once.
+    tags: Vec<(Symbol, Symbol)>,
+}
+impl InternedEvent {
+    /// The value carried for `tag`, if any.
+    pub fn get(&self, tag: Symbol) -> Option<Symbol> {
+        self.tags
+            .binary_search_by_key(&tag, |&(tag, _)| tag)
+            .ok()
+            .map(|i| self.tags[i].1)
+    }
+
+    fn has_tag(&self, tag: Symbol) -> bool {
+        self.get(tag).is_some()
+    }
+}
+
+/// A [`Filter`] translated by [`Interner::intern_filter`]. It has the same semantics as the
+/// original filter, but matching only compares symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InternedFilter {
+    pub tags: BTreeMap<Symbol, BTreeSet<Symbol>>,
+    pub exclude: BTreeMap<Symbol, BTreeSet<Symbol>>,
+    pub absent: BTreeSet<Symbol>,
+}
+impl InternedFilter {
+    pub fn matches(&self, evt: &InternedEvent) -> bool {
+        self.tags.iter().all(|(&tag, values)| {
+            evt.get(tag)
+                .map(|v| values.is_empty() || values.contains(&v))
+                .unwrap_or(false)
+        }) && !self
+            .exclude
+            .iter()
+            .any(|(&tag, values)| evt.get(tag).map(|v| values.contains(&v)).unwrap_or(false))
+            && !self.absent.iter().any(|&tag| evt.has_tag(tag))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ValueMatch;
+
+    #[test]
+    fn interner_round_trip() {
+        let mut interner = Interner::new();
+        let a = interner.intern("a");
+        let b = interner.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(interner.intern("a"), a);
+        assert_eq!(interner.get("b"), Some(b));
+        assert_eq!(interner.get("c"), None);
+        assert_eq!(interner.resolve(a), Some("a"));
+        assert_eq!(interner.resolve(Symbol::UNKNOWN), None);
+    }
+
+    #[test]
+    fn interned_matching_agrees_with_strings() {
+        let mut excluding = mkfilter! { "region" => ["us"] };
+        excluding.exclude = mkfilter! { "env" => ["prod"] }.tags;
+        let mut absent = mkfilter! { "env" => ["prod", "dev"] };
+        absent.absent.insert("deprecated".to_owned());
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("trace".to_owned(), BTreeSet::new());
+        let filters = [
+            Filter::EMPTY,
+            mkfilter! { "env" => ["prod"] },
+            mkfilter! { "env" => ["prod", "dev"], "region" => ["us"] },
+            excluding,
+            absent,
+            presence,
+        ];
+        let events = [
+            mkevt! {},
+            mkevt! {"env" => "prod"},
+            mkevt! {"env" => "staging"},
+            mkevt! {"env" => "dev", "region" => "us"},
+            mkevt! {"env" => "prod", "region" => "us", "deprecated" => "yes"},
+            mkevt! {"region" => "us", "trace" => "abc", "unrelated" => "x"},
+        ];
+
+        let mut interner = Interner::new();
+        let interned: Vec<InternedFilter> = filters
+            .iter()
+            .map(|f| interner.intern_filter(f).unwrap())
+            .collect();
+        let mut reused = InternedEvent::default();
+        for e in &events {
+            let ie = interner.lookup_event(e);
+            interner.lookup_event_into(e, &mut reused);
+            assert_eq!(reused, ie);
+            for (f, inf) in filters.iter().zip(&interned) {
+                assert_eq!(f.matches(e), inf.matches(&ie));
+            }
+        }
+    }
+
+    #[test]
+    fn intern_filter_rejects_matchers() {
+        let mut f = Filter::EMPTY;
+        f.matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        assert_eq!(Interner::new().intern_filter(&f), None);
+    }
+}
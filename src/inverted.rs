@@ -0,0 +1,665 @@
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec,
+    vec::Vec,
+};
+
+#[cfg(feature = "std")]
+use crate::{isolate, ListenerPanic};
+use crate::{
+    spend, Filter, FilterSet, Listener, Metrics, SubscriptionId, Tags, Topic, TryListener,
+};
+
+/// A topic that indexes subscriptions by the tag values they require.
+///
+/// Each `(tag, value)` pair maps to a posting list of the subscriptions that accept that value for
+/// that tag. A subscription matches an event if it shows up in the posting lists for the event's
+/// value of every tag it constrains, so finding matches is a matter of intersecting a few posting
+/// lists rather than visiting each subscription. This is a good fit for many selective
+/// subscriptions over high-cardinality tags, where most subscriptions share nothing with a given
+/// event.
+///
+/// With the `roaring` feature, posting lists are roaring bitmaps, so intersecting them is a matter
+/// of bitwise operations. This pays off once posting lists run to thousands of subscriptions.
+/// The bitmaps hold a 32-bit slot per live subscription rather than its id, and slots are reused
+/// once their subscriptions are gone, so ids can keep being handed out indefinitely.
+///
+/// Like a [`LinearScan`](crate::LinearScan), an index is `Send` if its listeners are `Send`, and
+/// `Sync` if they are `Sync`.
+#[derive(Clone, Debug)]
+pub struct InvertedIndex<L> {
+    // Subscriptions grouped by the set of tags they require values for, so that every posting list
+    // within a group is intersected the same way.
+    groups: BTreeMap<BTreeSet<String>, Postings>,
+    // Subscriptions that don't require any particular tag value, so every event is a candidate.
+    always: IdSet,
+    listeners: BTreeMap<SubscriptionId, IndexEntry<L>>,
+    next_id: usize,
+    #[cfg(feature = "roaring")]
+    slots: Slots,
+    // Set by `set_dead_letter`.
+    dead_letter: Option<L>,
+    metrics: Metrics,
+}
+// tag -> value -> subscriptions that accept that value for that tag.
+type Postings = BTreeMap<String, BTreeMap<String, IdSet>>;
+
+// What posting lists hold for each subscription.
+#[cfg(not(feature = "roaring"))]
+type Member = SubscriptionId;
+#[cfg(feature = "roaring")]
+type Member = u32;
+
+#[cfg(not(feature = "roaring"))]
+type IdSet = BTreeSet<SubscriptionId>;
+#[cfg(feature = "roaring")]
+type IdSet = roaring::RoaringBitmap;
+
+// Adding and removing subscriptions, whichever kind of posting list is in use.
+trait PostingList {
+    fn add(&mut self, member: Member);
+    fn discard(&mut self, member: Member);
+}
+#[cfg(not(feature = "roaring"))]
+impl PostingList for IdSet {
+    fn add(&mut self, member: Member) {
+        self.insert(member);
+    }
+
+    fn discard(&mut self, member: Member) {
+        self.remove(&member);
+    }
+}
+#[cfg(feature = "roaring")]
+impl PostingList for IdSet {
+    fn add(&mut self, member: Member) {
+        self.insert(member);
+    }
+
+    fn discard(&mut self, member: Member) {
+        self.remove(member);
+    }
+}
+
+// Hands out the slots that roaring posting lists hold in place of subscription ids, which would
+// outgrow a `u32` on a long-lived index. A slot is free again once its subscription is gone.
+#[cfg(feature = "roaring")]
+#[derive(Clone, Debug, Default)]
+struct Slots {
+    // slot -> the subscription holding it, if any.
+    ids: Vec<Option<SubscriptionId>>,
+    free: Vec<u32>,
+}
+#[cfg(feature = "roaring")]
+impl Slots {
+    fn assign(&mut self, id: SubscriptionId) -> u32 {
+        if let Some(slot) = self.free.pop() {
+            self.ids[slot as usize] = Some(id);
+            return slot;
+        }
+        let slot = u32::try_from(self.ids.len()).expect("more than 2^32 live subscriptions");
+        self.ids.push(Some(id));
+        slot
+    }
+
+    fn release(&mut self, slot: u32) {
+        self.ids[slot as usize] = None;
+        self.free.push(slot);
+    }
+
+    fn id(&self, slot: u32) -> SubscriptionId {
+        self.ids[slot as usize].expect("posting lists only hold assigned slots")
+    }
+}
+
+#[derive(Clone, Debug)]
+struct IndexEntry<L> {
+    listener: L,
+    member: Member,
+    // A plain subscription is stored as a set of one filter.
+    filters: FilterSet,
+    // The index only looks up `Filter::tags`; filters with any other constraints are checked
+    // again right before delivery.
+    exact: bool,
+    // How many more events a limited subscription may receive.
+    remaining: Option<usize>,
+}
+impl<L> Default for InvertedIndex<L> {
+    fn default() -> Self {
+        Self {
+            groups: BTreeMap::new(),
+            always: IdSet::new(),
+            listeners: BTreeMap::new(),
+            next_id: 0,
+            #[cfg(feature = "roaring")]
+            slots: Slots::default(),
+            dead_letter: None,
+            metrics: Metrics::default(),
+        }
+    }
+}
+
+// The tags of `filter` that require particular values. Presence-only constraints are left to the
+// final check, since they can't be looked up by value.
+fn required(filter: &Filter) -> impl Iterator<Item = (&String, &BTreeSet<String>)> {
+    filter.tags.iter().filter(|(_, vs)| !vs.is_empty())
+}
+
+impl<L> InvertedIndex<L> {
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// What the index has dispatched so far. See
+    /// [`LinearScan::metrics`](crate::LinearScan::metrics).
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Makes `listener` the dead letter, which receives each event that no subscription's
+    /// listener was delivered to. See
+    /// [`LinearScan::set_dead_letter`](crate::LinearScan::set_dead_letter).
+    pub fn set_dead_letter(&mut self, listener: L) {
+        self.dead_letter = Some(listener);
+    }
+
+    /// The listener set by `set_dead_letter`, if any.
+    pub fn dead_letter(&self) -> Option<&L> {
+        self.dead_letter.as_ref()
+    }
+
+    // Hands `evt` to the dead letter, if there is one, once it has reached no listener.
+    fn undelivered<E>(&mut self, evt: &E)
+    where
+        L: Listener<E>,
+    {
+        if let Some(listener) = &mut self.dead_letter {
+            listener.accept(evt);
+        }
+    }
+
+    /// Drops every subscription, along with every posting list. Ids handed out before stay stale
+    /// rather than being reused.
+    pub fn clear(&mut self) {
+        *self = Self {
+            next_id: self.next_id,
+            dead_letter: self.dead_letter.take(),
+            metrics: self.metrics,
+            ..Self::default()
+        };
+    }
+
+    /// Replaces the filter of subscription `id` with `filter`, re-indexing it. The subscription
+    /// keeps its id, its listener, and any deliveries left under a limit. Returns whether `id`
+    /// was subscribed.
+    pub fn update_filter(&mut self, id: SubscriptionId, filter: Filter) -> bool {
+        let filters = FilterSet::from(vec![filter]);
+        let Some(entry) = self.listeners.get_mut(&id) else {
+            return false;
+        };
+        let old = core::mem::replace(&mut entry.filters, filters.clone());
+        entry.exact = filters.filters[0].is_exact();
+        let member = entry.member;
+        for filter in &old.filters {
+            self.unindex(filter, member);
+        }
+        self.index(&filters.filters[0], member);
+        true
+    }
+
+    /// The listener subscribed as `id`, if it's still subscribed.
+    pub fn get(&self, id: SubscriptionId) -> Option<&L> {
+        self.listeners.get(&id).map(|entry| &entry.listener)
+    }
+
+    pub fn get_mut(&mut self, id: SubscriptionId) -> Option<&mut L> {
+        self.listeners.get_mut(&id).map(|entry| &mut entry.listener)
+    }
+
+    /// The filters of every subscription, in subscription order, for restoring them later with
+    /// fresh listeners. See [`TreeScanner::snapshot`](crate::TreeScanner::snapshot).
+    pub fn snapshot(&self) -> Vec<FilterSet> {
+        self.listeners
+            .values()
+            .map(|entry| entry.filters.clone())
+            .collect()
+    }
+
+    /// Delivers `evt` like `accept` does, returning how many listeners it was delivered to.
+    ///
+    /// Listeners are visited in subscription order; if one returns `ControlFlow::Break`, the event
+    /// is not delivered to any of the listeners after it.
+    pub fn accept_counted<E: Tags>(&mut self, evt: &E) -> usize
+    where
+        L: Listener<E>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = crate::trace::dispatch_span("InvertedIndex", evt);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        for id in self.candidates(evt) {
+            let entry = self.listeners.get_mut(&id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                if entry.listener.is_expired() {
+                    exhausted.push(id);
+                    continue;
+                }
+                if spend(&mut entry.remaining) {
+                    exhausted.push(id);
+                }
+                count += 1;
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subscription = id.0, "delivering");
+                if entry.listener.accept_flow(evt).is_break() {
+                    break;
+                }
+            }
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        #[cfg(feature = "tracing")]
+        crate::trace::finish(&span, count);
+        count
+    }
+
+    /// Like `accept_counted`, but a listener that panics doesn't stop the event from reaching the
+    /// listeners after it. Returns every panic that was caught.
+    #[cfg(feature = "std")]
+    pub fn accept_isolated<E: Tags>(&mut self, evt: &E) -> Vec<ListenerPanic>
+    where
+        L: Listener<E>,
+    {
+        let mut panics = Vec::new();
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        for id in self.candidates(evt) {
+            let entry = self.listeners.get_mut(&id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                if entry.listener.is_expired() {
+                    exhausted.push(id);
+                    continue;
+                }
+                count += 1;
+                if spend(&mut entry.remaining) {
+                    exhausted.push(id);
+                }
+                match isolate(id, || entry.listener.accept_flow(evt)) {
+                    Ok(flow) if flow.is_break() => break,
+                    Ok(_) => {}
+                    Err(panic) => panics.push(panic),
+                }
+            }
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        panics
+    }
+
+    /// Delivers `evt` to fallible listeners, returning the error from each listener that failed.
+    /// A failure doesn't stop the event from reaching the listeners after it.
+    pub fn accept_try<E: Tags>(&mut self, evt: &E) -> Vec<(SubscriptionId, L::Error)>
+    where
+        L: TryListener<E>,
+    {
+        let mut errors = Vec::new();
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        for id in self.candidates(evt) {
+            let entry = self.listeners.get_mut(&id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                count += 1;
+                if spend(&mut entry.remaining) {
+                    exhausted.push(id);
+                }
+                if let Err(err) = entry.listener.try_accept(evt) {
+                    errors.push((id, err));
+                }
+            }
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            if let Some(listener) = &mut self.dead_letter {
+                let _ = listener.try_accept(evt);
+            }
+        }
+        errors
+    }
+
+    // Unsubscribes the limited subscriptions that ran out during the last dispatch.
+    fn remove_exhausted(&mut self, exhausted: Vec<SubscriptionId>) {
+        for id in exhausted {
+            self.unsubscribe(id);
+        }
+    }
+
+    /// The ids of the subscriptions `evt` would be delivered to, in delivery order, without
+    /// invoking any listeners.
+    pub fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        let mut matched = self.candidates(evt);
+        matched.retain(|id| {
+            let entry = &self.listeners[id];
+            entry.exact || entry.filters.matches(evt)
+        });
+        matched
+    }
+
+    /// How many listeners `evt` would be delivered to, counted like
+    /// [`InvertedIndex::matching_ids`].
+    pub fn fanout<E: Tags>(&self, evt: &E) -> usize {
+        self.candidates(evt)
+            .iter()
+            .filter(|id| {
+                let entry = &self.listeners[id];
+                entry.exact || entry.filters.matches(evt)
+            })
+            .count()
+    }
+
+    // The subscriptions whose required tag values `evt` has, in subscription order. Inexact
+    // filters still need to be checked.
+    #[cfg(not(feature = "roaring"))]
+    fn candidates<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        let mut matched: Vec<SubscriptionId> = self.always.iter().copied().collect();
+        for (tags, postings) in &self.groups {
+            // For each tag, the posting lists of every value the event carries for it. An event
+            // with several values for a tag is a candidate if any one of them is accepted.
+            let mut lists: Vec<Vec<&BTreeSet<SubscriptionId>>> = tags
+                .iter()
+                .map(|tag| {
+                    let postings = &postings[tag];
+                    evt.values(tag).filter_map(|v| postings.get(v)).collect()
+                })
+                .collect();
+            // If the event lacks one of the tags, or has no value anybody in the group asked for,
+            // the whole group is ruled out.
+            if lists.iter().any(Vec::is_empty) {
+                continue;
+            }
+            lists.sort_unstable_by_key(|ids| ids.iter().map(|ids| ids.len()).sum::<usize>());
+            let (smallest, rest) = lists.split_first().unwrap();
+            matched.extend(smallest.iter().flat_map(|ids| ids.iter()).filter(|id| {
+                rest.iter()
+                    .all(|lists| lists.iter().any(|ids| ids.contains(id)))
+            }));
+        }
+        // A filter set may have put the same subscription in several groups.
+        matched.sort_unstable();
+        matched.dedup();
+        matched
+    }
+
+    #[cfg(feature = "roaring")]
+    fn candidates<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        let mut matched = IdSet::new();
+        'groups: for (tags, postings) in &self.groups {
+            let mut group: Option<IdSet> = None;
+            for tag in tags {
+                let postings = &postings[tag];
+                // An event with several values for a tag is a candidate if any one of them is
+                // accepted.
+                let accepted = evt
+                    .values(tag)
+                    .filter_map(|v| postings.get(v))
+                    .fold(IdSet::new(), |acc, ids| acc | ids);
+                match &mut group {
+                    Some(group) => *group &= accepted,
+                    None => group = Some(accepted),
+                }
+                if group.as_ref().is_some_and(IdSet::is_empty) {
+                    continue 'groups;
+                }
+            }
+            matched |= group.unwrap_or_default();
+        }
+        matched |= &self.always;
+        // Slots are reused, so they aren't in subscription order.
+        let mut matched: Vec<SubscriptionId> =
+            matched.iter().map(|slot| self.slots.id(slot)).collect();
+        matched.sort_unstable();
+        matched
+    }
+
+    /// Delivers each of `evts` in turn.
+    pub fn accept_batch<E: Tags>(&mut self, evts: &[E])
+    where
+        L: Listener<E>,
+    {
+        for evt in evts {
+            self.accept_counted(evt);
+        }
+    }
+
+    /// Delivers each event `evts` yields as soon as it's yielded, so that a lazily produced stream,
+    /// e.g. one parsed from a reader, never has to be held in memory at once. Returns the total
+    /// number of deliveries.
+    pub fn accept_stream<E: Tags, I: IntoIterator<Item = E>>(&mut self, evts: I) -> usize
+    where
+        L: Listener<E>,
+    {
+        evts.into_iter().map(|evt| self.accept_counted(&evt)).sum()
+    }
+
+    fn insert(
+        &mut self,
+        listener: L,
+        filters: FilterSet,
+        remaining: Option<usize>,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        // A subscription limited to no events at all is used up before it starts.
+        if remaining == Some(0) {
+            return id;
+        }
+        #[cfg(not(feature = "roaring"))]
+        let member = id;
+        #[cfg(feature = "roaring")]
+        let member = self.slots.assign(id);
+        for filter in &filters.filters {
+            self.index(filter, member);
+        }
+        // Two filters in the same group share posting lists, so an event could pick up one tag's
+        // value from each of them. Only a lone exact filter can skip the final check.
+        let exact = matches!(filters.filters.as_slice(), [filter] if filter.is_exact());
+        self.listeners.insert(
+            id,
+            IndexEntry {
+                listener,
+                member,
+                filters,
+                exact,
+                remaining,
+            },
+        );
+        id
+    }
+
+    fn index(&mut self, filter: &Filter, member: Member) {
+        let tags: BTreeSet<String> = required(filter).map(|(tag, _)| tag.clone()).collect();
+        if tags.is_empty() {
+            self.always.add(member);
+            return;
+        }
+        let postings = self.groups.entry(tags).or_default();
+        for (tag, values) in required(filter) {
+            let postings = postings.entry(tag.clone()).or_default();
+            for v in values {
+                postings.entry(v.clone()).or_default().add(member);
+            }
+        }
+    }
+
+    // The inverse of `index`. Another filter in the same set may already have removed some of the
+    // same postings.
+    fn unindex(&mut self, filter: &Filter, member: Member) {
+        let tags: BTreeSet<String> = required(filter).map(|(tag, _)| tag.clone()).collect();
+        if tags.is_empty() {
+            self.always.discard(member);
+            return;
+        }
+        let Some(group) = self.groups.get_mut(&tags) else {
+            return;
+        };
+        for (tag, values) in required(filter) {
+            let postings = group.get_mut(tag).unwrap();
+            for v in values {
+                if let Some(ids) = postings.get_mut(v) {
+                    ids.discard(member);
+                    if ids.is_empty() {
+                        postings.remove(v);
+                    }
+                }
+            }
+        }
+        // Every tag in a group is required by every member, so once one tag's postings are empty
+        // the group has no members left.
+        if group.values().any(BTreeMap::is_empty) {
+            self.groups.remove(&tags);
+        }
+    }
+}
+impl<L> Topic<L> for InvertedIndex<L> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.subscribe_any(listener, FilterSet::from(vec![filter]))
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.insert(listener, filters, None)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), Some(max))
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let Some(entry) = self.listeners.remove(&id) else {
+            return false;
+        };
+        for filter in &entry.filters.filters {
+            self.unindex(filter, entry.member);
+        }
+        #[cfg(feature = "roaring")]
+        self.slots.release(entry.member);
+        true
+    }
+}
+impl<E: Tags, L: Listener<E>> Listener<E> for InvertedIndex<L> {
+    fn accept(&mut self, evt: &E) {
+        self.accept_counted(evt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{Event, LinearScan, ValueMatch};
+
+    struct Recorder(usize, Arc<Mutex<Vec<usize>>>);
+    impl Listener for Recorder {
+        fn accept(&mut self, _evt: &Event) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[test]
+    fn inverted_index_matches_linear_scan() {
+        let mut prefixed = mkfilter! { "env" => ["prod"] };
+        prefixed
+            .matchers
+            .insert("svc".to_owned(), ValueMatch::Prefix("api".to_owned()));
+        let mut presence = Filter::EMPTY;
+        presence.tags.insert("trace".to_owned(), BTreeSet::new());
+        let filters = [
+            Filter::EMPTY,
+            mkfilter! { "env" => ["prod"] },
+            mkfilter! { "env" => ["prod", "dev"], "region" => ["us"] },
+            mkfilter! { "region" => ["eu"] },
+            prefixed,
+            presence,
+        ];
+        let events = [
+            mkevt! {"env" => "prod"},
+            mkevt! {"env" => "dev", "region" => "us"},
+            mkevt! {"env" => "prod", "region" => "us", "svc" => "api.users"},
+            mkevt! {"region" => "eu", "trace" => "abc"},
+            mkevt! {},
+        ];
+
+        let linear_log = Arc::new(Mutex::new(Vec::new()));
+        let index_log = Arc::new(Mutex::new(Vec::new()));
+        let mut linear = LinearScan::default();
+        let mut index = InvertedIndex::default();
+        for (i, f) in filters.iter().enumerate() {
+            linear.subscribe(Recorder(i, linear_log.clone()), f.clone());
+            index.subscribe(Recorder(i, index_log.clone()), f.clone());
+        }
+        for e in &events {
+            assert_eq!(index.accept_counted(e), linear.accept_counted(e));
+        }
+        assert_eq!(*index_log.lock().unwrap(), *linear_log.lock().unwrap());
+    }
+
+    #[test]
+    fn inverted_index_unsubscribe() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut index = InvertedIndex::default();
+        let a = index.subscribe(Recorder(0, log.clone()), mkfilter! { "env" => ["prod"] });
+        let b = index.subscribe(Recorder(1, log.clone()), Filter::EMPTY);
+        index.subscribe(Recorder(2, log.clone()), mkfilter! { "env" => ["prod"] });
+
+        assert!(index.unsubscribe(a));
+        assert!(index.unsubscribe(b));
+        assert!(!index.unsubscribe(b));
+        index.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(*log.lock().unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn inverted_index_prunes_postings() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut index = InvertedIndex::default();
+        let id = index.subscribe(
+            Recorder(0, log.clone()),
+            mkfilter! { "env" => ["prod", "dev"], "region" => ["us"] },
+        );
+        assert_eq!(index.groups.len(), 1);
+        index.unsubscribe(id);
+        assert!(index.groups.is_empty());
+        assert!(index.listeners.is_empty());
+    }
+
+    #[test]
+    fn inverted_index_outlives_u32_ids() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        // As if billions of subscriptions had come and gone.
+        let mut index = InvertedIndex {
+            next_id: u32::MAX as usize,
+            ..InvertedIndex::default()
+        };
+        let a = index.subscribe(Recorder(0, log.clone()), mkfilter! { "env" => ["prod"] });
+        let b = index.subscribe(Recorder(1, log.clone()), Filter::EMPTY);
+        index.unsubscribe(a);
+        // Takes over whatever `a` held, but is still delivered to after `b`.
+        let c = index.subscribe(Recorder(2, log.clone()), mkfilter! { "env" => ["prod"] });
+        assert_eq!(index.matching_ids(&mkevt! {"env" => "prod"}), vec![b, c]);
+        index.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(*log.lock().unwrap(), vec![1, 2]);
+    }
+}
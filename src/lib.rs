@@ -1,198 +1,5570 @@
-use std::collections::{BTreeMap, BTreeSet};
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-#[derive(Clone, PartialEq, Eq)]
-pub struct Event {
-    pub tags: BTreeMap<String, String>,
+extern crate alloc;
+
+use alloc::{
+    borrow::Borrow,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::{convert::Infallible, ops::ControlFlow};
+
+use bloom::Prefilter;
+use normalize::{normalized, TagView};
+
+#[cfg(test)]
+#[macro_use]
+mod testing;
+
+mod algebra;
+#[cfg(feature = "async")]
+mod async_scan;
+mod bitset;
+mod bloom;
+mod builder;
+mod explain;
+mod expr;
+mod glob;
+mod histogram;
+mod intern;
+mod inverted;
+mod listener;
+mod normalize;
+pub mod prelude;
+pub mod query;
+mod reorder;
+#[cfg(all(feature = "serde", feature = "std"))]
+mod replay;
+#[cfg(feature = "std")]
+mod sync;
+#[cfg(feature = "tracing")]
+mod trace;
+
+#[cfg(feature = "async")]
+pub use async_scan::{AsyncLinearScan, AsyncListener};
+pub use bitset::BitsetScanner;
+pub use builder::{EventBuilder, FilterBuilder};
+pub use explain::{explain, MatchExplanation, MatchFailure};
+pub use expr::Expr;
+pub use glob::Glob;
+pub use histogram::{MatchHistogram, MatchingIds};
+pub use intern::{InternedEvent, InternedFilter, Interner, Symbol};
+pub use inverted::InvertedIndex;
+#[cfg(feature = "crossbeam")]
+pub use listener::CrossbeamListener;
+pub use listener::{
+    AnyBoxListener, AnyListener, BoxListener, FnListener, RecordingListener, StoppableFnListener,
+};
+#[cfg(feature = "std")]
+pub use listener::{ChannelListener, DedupListener, WeakListener};
+pub use query::FilterParseError;
+pub use reorder::ReorderingTopic;
+#[cfg(all(feature = "serde", feature = "std"))]
+pub use replay::replay;
+#[cfg(feature = "std")]
+pub use sync::SyncTopic;
+
+/// Something that happened. Topics route events by their `tags`; the `data` payload is passed
+/// along to listeners untouched.
+///
+/// Tag keys and values are strings unless `K` and `V` say otherwise, e.g. `Event<(), u32, u32>`
+/// for tags that have already been mapped to integers. Topics only route string tags; an event
+/// with other tag types can be checked against a [`Filter`] of the same types with
+/// [`Filter::matches_event`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "T: serde::Deserialize<'de>, \
+        K: Ord + serde::Deserialize<'de>, V: serde::Deserialize<'de>"))
+)]
+pub struct Event<T = (), K = String, V = String> {
+    pub tags: BTreeMap<K, V>,
+    pub data: T,
+}
+
+impl<T> Event<T> {
+    /// The value of `key`, if the event carries that tag.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
 }
+impl<T, K: Ord, V> Event<T, K, V> {
+    pub fn contains_tag<Q: ?Sized + Ord>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+    {
+        self.tags.contains_key(key)
+    }
 
-pub trait Listener {
-    fn accept(&mut self, evt: &Event);
+    /// The number of tags the event carries.
+    pub fn tag_count(&self) -> usize {
+        self.tags.len()
+    }
 }
+/// Looks up a tag's value like a map does.
+///
+/// # Panics
+///
+/// If the event doesn't carry `key`. Use [`Event::get`] to handle that case.
+impl<T> core::ops::Index<&str> for Event<T> {
+    type Output = str;
 
-pub trait Topic<L: Listener> {
-    fn subscribe(&mut self, listener: L, filter: Filter);
+    fn index(&self, key: &str) -> &str {
+        self.get(key)
+            .unwrap_or_else(|| panic!("event has no tag `{}`", key))
+    }
 }
 
-#[derive(Clone)]
-pub struct Filter {
+/// An event that carries nothing but its tags.
+pub type PlainEvent = Event<()>;
+
+/// An event whose tags may each carry several values, e.g. `label=urgent` and `label=billing` at
+/// once. A filter value matches if it is any one of the event's values for that tag.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MultiEvent<T = ()> {
+    /// A tag mapped to an empty set is treated as if the event didn't carry it.
     pub tags: BTreeMap<String, BTreeSet<String>>,
+    pub data: T,
+}
+impl<T> From<Event<T>> for MultiEvent<T> {
+    fn from(evt: Event<T>) -> Self {
+        Self {
+            tags: evt
+                .tags
+                .into_iter()
+                .map(|(tag, value)| (tag, BTreeSet::from([value])))
+                .collect(),
+            data: evt.data,
+        }
+    }
+}
+
+/// An event whose tags are kept in a `HashMap` rather than a `BTreeMap`, so that looking up a
+/// tag costs a hash of its key instead of a string comparison per level of the tree. Whether that
+/// pays off depends on how many tags events carry and how long their keys are: with the default
+/// hasher, [`Event`] still comes out ahead at fifty short keys in the `wide` benchmark. Measure
+/// before switching.
+///
+/// With the `serde` feature, tags are serialized in no particular order, which can differ from
+/// one run to the next. Convert to an [`Event`] first where output needs to be stable, e.g. for
+/// golden tests.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HashEvent<T = ()> {
+    pub tags: std::collections::HashMap<String, String>,
+    pub data: T,
+}
+#[cfg(feature = "std")]
+impl<T> HashEvent<T> {
+    /// The value of `key`, if the event carries that tag.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags.get(key).map(String::as_str)
+    }
+
+    pub fn contains_tag(&self, key: &str) -> bool {
+        self.tags.contains_key(key)
+    }
+
+    /// The number of tags the event carries.
+    pub fn tag_count(&self) -> usize {
+        self.tags.len()
+    }
+}
+/// Looks up a tag's value like a map does.
+///
+/// # Panics
+///
+/// If the event doesn't carry `key`. Use [`HashEvent::get`] to handle that case.
+#[cfg(feature = "std")]
+impl<T> core::ops::Index<&str> for HashEvent<T> {
+    type Output = str;
+
+    fn index(&self, key: &str) -> &str {
+        self.get(key)
+            .unwrap_or_else(|| panic!("event has no tag `{}`", key))
+    }
+}
+#[cfg(feature = "std")]
+impl<T> From<Event<T>> for HashEvent<T> {
+    fn from(evt: Event<T>) -> Self {
+        Self {
+            tags: evt.tags.into_iter().collect(),
+            data: evt.data,
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl<T> From<HashEvent<T>> for Event<T> {
+    fn from(evt: HashEvent<T>) -> Self {
+        Self {
+            tags: evt.tags.into_iter().collect(),
+            data: evt.data,
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for HashEvent {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self {
+            tags: iter
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+            data: (),
+        }
+    }
+}
+
+/// The tags of something that can be routed through a topic. Implemented by [`Event`],
+/// [`MultiEvent`], and [`HashEvent`].
+pub trait Tags {
+    /// The values carried for `tag`, which is empty if the tag isn't carried at all.
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str>;
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.values(tag).next().is_some()
+    }
+
+    /// Every tag carried, each once.
+    fn keys(&self) -> impl Iterator<Item = &str>;
+
+    /// Whether any tag carried has a key starting with `prefix`.
+    fn has_tag_prefix(&self, prefix: &str) -> bool {
+        self.keys().any(|key| key.starts_with(prefix))
+    }
+}
+impl<T> Tags for Event<T> {
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+        self.tags.get(tag).map(String::as_str).into_iter()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains_key(tag)
+    }
+}
+#[cfg(feature = "std")]
+impl<T> Tags for HashEvent<T> {
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+        self.tags.get(tag).map(String::as_str).into_iter()
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.tags.keys().map(String::as_str)
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains_key(tag)
+    }
+}
+impl<T> Tags for MultiEvent<T> {
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+        self.tags.get(tag).into_iter().flatten().map(String::as_str)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.tags
+            .iter()
+            .filter(|(_, values)| !values.is_empty())
+            .map(|(key, _)| key.as_str())
+    }
+}
+
+pub trait Listener<E = Event> {
+    fn accept(&mut self, evt: &E);
+
+    /// Like `accept`, but the listener may claim the event by returning `ControlFlow::Break`, which
+    /// prevents it from being delivered to any listeners that would otherwise have received it
+    /// afterwards. By default listeners always let the event continue.
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        self.accept(evt);
+        ControlFlow::Continue(())
+    }
+
+    /// Whether the listener is gone for good, e.g. because it only held a [`WeakListener`]'s
+    /// reference to a listener that has since been dropped. When an event matches an expired
+    /// listener, a topic's `Listener`-based dispatch methods skip it and unsubscribe it once the
+    /// event has been dispatched; `accept_try` delivers to it as usual. By default listeners
+    /// never expire.
+    ///
+    /// [`WeakListener`]: crate::WeakListener
+    fn is_expired(&self) -> bool {
+        false
+    }
+
+    /// Identifies the listener across subscriptions, so that under
+    /// [`DeliveryPolicy::OncePerListener`] subscriptions whose listeners share a key receive each
+    /// event only once between them. By default a listener has no key, and each of its
+    /// subscriptions is delivered to separately.
+    fn listener_key(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// A listener that can take ownership of the events it's given, for the `accept_owned` methods of
+/// topics. A listener that would otherwise clone each event to keep it, like [`ChannelListener`],
+/// can skip the clone when it's the last to receive one.
+///
+/// The default just borrows the event for `accept`, so any listener can opt in with an empty impl.
+///
+/// [`ChannelListener`]: crate::ChannelListener
+pub trait ConsumingListener<E = Event>: Listener<E> {
+    fn accept_owned(&mut self, evt: E) {
+        self.accept(&evt);
+    }
+}
+
+/// A listener whose deliveries can fail, e.g. because handling an event involves I/O.
+///
+/// Every [`Listener`] is also a `TryListener` that never fails, so topics of ordinary listeners
+/// support [`LinearScan::accept_try`] and friends as well.
+pub trait TryListener<E = Event> {
+    type Error;
+
+    fn try_accept(&mut self, evt: &E) -> Result<(), Self::Error>;
+
+    /// Identifies the listener across subscriptions. See [`Listener::listener_key`].
+    fn listener_key(&self) -> Option<u64> {
+        None
+    }
+}
+impl<E, L: Listener<E>> TryListener<E> for L {
+    type Error = Infallible;
+
+    fn try_accept(&mut self, evt: &E) -> Result<(), Infallible> {
+        self.accept(evt);
+        Ok(())
+    }
+
+    fn listener_key(&self) -> Option<u64> {
+        Listener::listener_key(self)
+    }
+}
+
+/// A set of subscriptions that events can be delivered to.
+///
+/// When an event matches several subscriptions, their listeners are invoked in the order they
+/// subscribed, regardless of the backend.
+pub trait Topic<L> {
+    /// Registers `listener` to receive every event matching `filter`. The returned id identifies
+    /// this subscription for as long as it is live.
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId;
+
+    /// Like `subscribe`, but `listener` receives every event matching any of `filters`. An event
+    /// that matches several of them is still only delivered once.
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId;
+
+    /// Like `subscribe`, but the subscription removes itself once it has been delivered its first
+    /// event. Its id becomes stale at that point, as if it had been unsubscribed.
+    fn subscribe_once(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.subscribe_limited(listener, filter, 1)
+    }
+
+    /// Like `subscribe_once`, but the subscription is delivered up to `max` events before it
+    /// removes itself. With a `max` of zero nothing is ever delivered, and the returned id is
+    /// stale from the start.
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId;
+
+    /// Like `subscribe`, but first checks `filter` with [`Filter::validate`] and refuses it
+    /// instead of subscribing a listener that could never be delivered anything. Backends that
+    /// refuse some matchers report that here too, so this never panics.
+    fn subscribe_checked(
+        &mut self,
+        listener: L,
+        filter: Filter,
+    ) -> Result<SubscriptionId, FilterError> {
+        filter.validate()?;
+        Ok(self.subscribe(listener, filter))
+    }
+
+    /// Like `subscribe`, but the topic only holds a [`WeakListener`] to `listener`. Once every
+    /// other `Arc` to it is dropped, the subscription stops receiving events, and is unsubscribed
+    /// the next time an event matches it.
+    #[cfg(feature = "std")]
+    fn subscribe_weak<M>(
+        &mut self,
+        listener: &std::sync::Arc<std::sync::Mutex<M>>,
+        filter: Filter,
+    ) -> SubscriptionId
+    where
+        L: From<WeakListener<M>>,
+    {
+        self.subscribe(WeakListener::new(listener).into(), filter)
+    }
+
+    /// Removes a subscription, returning whether it was still live.
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool;
+}
+
+/// A handle to a single subscription within a topic. Ids are never reused, so a stale id can't
+/// accidentally refer to a newer subscription.
+///
+/// Cloning a topic keeps the ids of its subscriptions: an id from the original refers to the
+/// corresponding subscription in the clone, and both go on to hand out the same new ids.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriptionId(usize);
+
+// The optional constraints are left out of the serialized form when empty, so a filter that only
+// uses `tags` looks the same as it always has.
+//
+// Like `Event`, a filter's tag keys and values are strings unless `K` and `V` say otherwise.
+// `matchers` and `key_prefixes` only make sense for strings, so they're only evaluated by
+// `Filter::matches`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "K: Ord + serde::Deserialize<'de>, \
+        V: Ord + serde::Deserialize<'de>"))
+)]
+pub struct Filter<K = String, V = String> {
+    /// The event must carry each of these tags, set to one of the listed values. An empty value set
+    /// only requires the tag to be present, whatever its value.
+    pub tags: BTreeMap<K, BTreeSet<V>>,
+    /// The event must not carry any of these tags set to one of the listed values. An event that
+    /// lacks the tag entirely is not excluded.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub exclude: BTreeMap<K, BTreeSet<V>>,
+    /// The event must not carry any of these tags, whatever their value.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeSet::is_empty")
+    )]
+    pub absent: BTreeSet<K>,
+    /// The event must carry each of these tags, with a value accepted by the matcher. Exact matches
+    /// belong in `tags`, which is cheaper to evaluate.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeMap::is_empty")
+    )]
+    pub matchers: BTreeMap<K, ValueMatch>,
+    /// For each of these prefixes, the event must carry some tag whose key starts with it, e.g.
+    /// `http.` for any of `http.method` or `http.status`.
+    ///
+    /// Like every other constraint, these are ANDed with the rest of the filter, and the tag that
+    /// satisfies a prefix needn't be one the filter constrains elsewhere: a prefix of `http.`
+    /// alongside `env=prod` matches events with `env=prod` and any `http.` tag at all. To
+    /// constrain the values of namespaced tags, name them in `tags` or `matchers`.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "BTreeSet::is_empty")
+    )]
+    pub key_prefixes: BTreeSet<K>,
 }
 impl Filter {
+    /// The filter with no constraints, which matches every event. See [`Filter::match_all`].
     pub const EMPTY: Filter = Filter {
         tags: BTreeMap::new(),
+        exclude: BTreeMap::new(),
+        absent: BTreeSet::new(),
+        matchers: BTreeMap::new(),
+        key_prefixes: BTreeSet::new(),
     };
+
+    /// Whether `evt` satisfies every constraint of the filter, exactly as a topic decides whether
+    /// to deliver it. A filter with no constraints matches every event.
+    ///
+    /// An event carrying several values for a tag satisfies a constraint on it if any one of them
+    /// does, and is excluded if any one of them is.
+    ///
+    /// ```
+    /// use tagsub::{Event, Filter};
+    ///
+    /// let filter = Filter::single("env", "prod");
+    /// assert!(filter.matches(&Event::single("env", "prod")));
+    /// assert!(!filter.matches(&Event::single("env", "dev")));
+    /// ```
+    pub fn matches<E: Tags>(&self, evt: &E) -> bool {
+        // Each constraint is checked in turn, stopping at the first that fails. An event lacking a
+        // required tag has no values for it, so it fails whether or not the tag lists any.
+        self.tags.iter().all(|(tag, values)| {
+            if values.is_empty() {
+                evt.has_tag(tag)
+            } else {
+                evt.values(tag).any(|v| values.contains(v))
+            }
+        }) && !self
+            .exclude
+            .iter()
+            .any(|(tag, values)| evt.values(tag).any(|v| values.contains(v)))
+            && !self.absent.iter().any(|tag| evt.has_tag(tag))
+            && self
+                .matchers
+                .iter()
+                .all(|(tag, m)| evt.values(tag).any(|v| m.matches(v)))
+            && self.key_prefixes.iter().all(|p| evt.has_tag_prefix(p))
+    }
+
+    // Whether `tags` is the only constraint, which is all that `TreeScanner` knows how to route on.
+    fn is_exact(&self) -> bool {
+        self.tags.values().all(|vs| !vs.is_empty())
+            && self.exclude.is_empty()
+            && self.absent.is_empty()
+            && self.matchers.is_empty()
+            && self.key_prefixes.is_empty()
+    }
+}
+impl<K, V> Default for Filter<K, V> {
+    fn default() -> Self {
+        Self {
+            tags: BTreeMap::new(),
+            exclude: BTreeMap::new(),
+            absent: BTreeSet::new(),
+            matchers: BTreeMap::new(),
+            key_prefixes: BTreeSet::new(),
+        }
+    }
+}
+impl<K: Ord, V: Ord> Filter<K, V> {
+    /// Whether `evt`, whose tags have the same types as the filter's, satisfies the filter's
+    /// `tags`, `exclude` and `absent` constraints. Those are evaluated just as
+    /// [`Filter::matches`] evaluates them.
+    ///
+    /// `matchers` and `key_prefixes` need string values, so a filter that uses either of them
+    /// never matches here; string filters should use [`Filter::matches`].
+    ///
+    /// ```
+    /// use std::collections::{BTreeMap, BTreeSet};
+    /// use tagsub::{Event, Filter};
+    ///
+    /// let filter: Filter<u32, u32> = Filter {
+    ///     tags: BTreeMap::from([(1, BTreeSet::from([10, 11]))]),
+    ///     ..Filter::default()
+    /// };
+    /// let evt = |value| Event { tags: BTreeMap::from([(1, value)]), data: () };
+    /// assert!(filter.matches_event(&evt(11)));
+    /// assert!(!filter.matches_event(&evt(12)));
+    /// ```
+    pub fn matches_event<T>(&self, evt: &Event<T, K, V>) -> bool {
+        self.matchers.is_empty()
+            && self.key_prefixes.is_empty()
+            && self
+                .tags
+                .iter()
+                .all(|(tag, values)| match evt.tags.get(tag) {
+                    Some(v) => values.is_empty() || values.contains(v),
+                    None => false,
+                })
+            && !self
+                .exclude
+                .iter()
+                .any(|(tag, values)| evt.tags.get(tag).is_some_and(|v| values.contains(v)))
+            && !self.absent.iter().any(|tag| evt.tags.contains_key(tag))
+    }
 }
 
-#[derive(Default)]
-pub struct LinearScan<L: Listener> {
-    pub listeners: Vec<(L, Filter)>,
+/// A disjunction of filters: an event matches the set if it matches any of them. An empty set
+/// matches nothing.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FilterSet {
+    pub filters: Vec<Filter>,
 }
-impl<L: Listener> Topic<L> for LinearScan<L> {
-    fn subscribe(&mut self, listener: L, filter: Filter) {
-        self.listeners.push((listener, filter));
+impl FilterSet {
+    /// Whether `evt` matches any of the filters.
+    pub fn matches<E: Tags>(&self, evt: &E) -> bool {
+        self.filters.iter().any(|f| f.matches(evt))
     }
 }
-impl<T: Listener> Listener for LinearScan<T> {
-    fn accept(&mut self, evt: &Event) {
-        for (listener, filter) in self.listeners.iter_mut() {
-            if filter.tags.iter().all(|(tag, values)| {
-                evt.tags
-                    .get(tag)
-                    .map(|v| values.contains(v))
-                    .unwrap_or(false)
-            }) {
-                listener.accept(evt);
-            }
+impl From<Vec<Filter>> for FilterSet {
+    fn from(filters: Vec<Filter>) -> Self {
+        Self { filters }
+    }
+}
+impl FromIterator<Filter> for FilterSet {
+    fn from_iter<I: IntoIterator<Item = Filter>>(iter: I) -> Self {
+        Self {
+            filters: iter.into_iter().collect(),
         }
     }
 }
 
-#[derive(Default)]
-pub struct TreeScanner<L: Listener> {
-    // The listeners that want to know about all events at this level.
-    pipeline: Vec<String>,
-    root: TagTree<L>,
+/// A predicate on a single tag value, for constraints that can't be expressed as a set of exact
+/// values.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ValueMatch {
+    /// The value starts with the given string.
+    Prefix(String),
+    /// The value matches the given glob pattern.
+    Glob(Glob),
+    /// The value equals the given string once both are lowercased.
+    /// [`TreeScanner::try_subscribe`] refuses this matcher.
+    ExactIgnoreCase(String),
+    /// The value contains the given string anywhere in it. An empty string matches every value.
+    /// [`TreeScanner::try_subscribe`] refuses this matcher.
+    Contains(String),
+    /// The value contains a match for the given regex. Anchor the pattern with `^` and `$` to
+    /// match the whole value. [`TreeScanner::try_subscribe`] refuses this matcher.
+    #[cfg(feature = "regex")]
+    #[cfg_attr(feature = "serde", serde(with = "regex_serde"))]
+    Regex(regex::Regex),
+    /// The value is a number between `min` and `max`, which are included in the range if
+    /// `inclusive` is set. Values that don't parse as numbers never match.
+    /// [`TreeScanner::try_subscribe`] refuses this matcher.
+    Range { min: f64, max: f64, inclusive: bool },
 }
-#[derive(Default)]
-struct TagTree<L: Listener> {
-    // Listeners that are interested in any event that makes it this far into the pipeline.
-    interested: Vec<L>,
-    // Listeners that do not care about this particular tag in the pipeline, but want to be filtered on the subsequent ones.
-    passthrough: Option<Box<TagTree<L>>>,
-    // Otherwise, keep proceeding down the tag pipeline.
-    children: BTreeMap<String, TagTree<L>>,
+impl ValueMatch {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            ValueMatch::Prefix(prefix) => value.starts_with(prefix.as_str()),
+            ValueMatch::Glob(glob) => glob.matches(value),
+            ValueMatch::ExactIgnoreCase(expected) => value
+                .chars()
+                .flat_map(char::to_lowercase)
+                .eq(expected.chars().flat_map(char::to_lowercase)),
+            ValueMatch::Contains(needle) => value.contains(needle.as_str()),
+            #[cfg(feature = "regex")]
+            ValueMatch::Regex(re) => re.is_match(value),
+            ValueMatch::Range {
+                min,
+                max,
+                inclusive,
+            } => match value.parse::<f64>() {
+                Ok(n) if *inclusive => *min <= n && n <= *max,
+                Ok(n) => *min < n && n < *max,
+                Err(_) => false,
+            },
+        }
+    }
+
+    // Whether `TreeScanner::try_subscribe` refuses this matcher.
+    fn linear_only(&self) -> bool {
+        match self {
+            ValueMatch::Prefix(_) | ValueMatch::Glob(_) => false,
+            ValueMatch::ExactIgnoreCase(_) | ValueMatch::Contains(_) => true,
+            #[cfg(feature = "regex")]
+            ValueMatch::Regex(_) => true,
+            ValueMatch::Range { .. } => true,
+        }
+    }
 }
-impl<L: Listener> TagTree<L> {
-    fn new() -> Self {
-        Self {
-            interested: Vec::new(),
-            passthrough: None,
-            children: BTreeMap::new(),
+// Regexes are compared by their source pattern.
+impl PartialEq for ValueMatch {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ValueMatch::Prefix(a), ValueMatch::Prefix(b)) => a == b,
+            (ValueMatch::Glob(a), ValueMatch::Glob(b)) => a == b,
+            (ValueMatch::ExactIgnoreCase(a), ValueMatch::ExactIgnoreCase(b)) => a == b,
+            (ValueMatch::Contains(a), ValueMatch::Contains(b)) => a == b,
+            #[cfg(feature = "regex")]
+            (ValueMatch::Regex(a), ValueMatch::Regex(b)) => a.as_str() == b.as_str(),
+            (
+                ValueMatch::Range {
+                    min: a_min,
+                    max: a_max,
+                    inclusive: a_inclusive,
+                },
+                ValueMatch::Range {
+                    min: b_min,
+                    max: b_max,
+                    inclusive: b_inclusive,
+                },
+            ) => a_min == b_min && a_max == b_max && a_inclusive == b_inclusive,
+            _ => false,
         }
     }
 }
-impl<L: Listener> Topic<L> for TreeScanner<L> {
-    fn subscribe(&mut self, listener: L, filter: Filter)
-    where
-        L: Listener,
-    {
-        let missing: Vec<String> = filter
-            .tags
-            .keys()
-            .filter(|k| !self.pipeline.contains(k))
-            .cloned()
-            .collect();
-        self.pipeline.extend(missing);
 
-        let mut cur = &mut self.root;
-        for key in self.pipeline.iter().take(filter.tags.len()) {
-            let Some(vs) = filter.tags.get(key) else {
-                if cur.passthrough.is_none() {
-                    cur.passthrough = Some(Box::new(TagTree::new()));
-                }
-                cur = cur.passthrough.as_deref_mut().unwrap();
-                continue;
-            };
-            if vs.len() != 1 {
-                todo!("unsupported tag filter: {} = {:?}", key, vs);
+#[cfg(all(feature = "regex", feature = "serde"))]
+mod regex_serde {
+    use regex::Regex;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(re: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(re.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Regex::new(&pattern).map_err(D::Error::custom)
+    }
+}
+
+/// Why [`Topic::subscribe_checked`] refused a filter: either [`Filter::validate`] found a problem
+/// with it, or the topic doesn't support one of its matchers. Each variant names the tag it
+/// concerns.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FilterError {
+    /// The tag is both required (in `tags` or `matchers`) and ruled out by `absent`, or none of
+    /// the values `tags` requires for it pass its matcher, so the filter can never match.
+    Contradiction { tag: String },
+    /// Every value `tags` accepts for the tag is also excluded, so the filter can never match.
+    AllExcluded { tag: String },
+    /// The tag's `exclude` set is empty, which excludes nothing. Ruling the tag out entirely is
+    /// what `absent` is for.
+    EmptyExclusion { tag: String },
+    /// The tag's range matcher can't contain any number.
+    EmptyRange { tag: String },
+    /// The filter is valid, but the topic can't support it.
+    Unsupported(UnsupportedFilter),
+}
+impl From<UnsupportedFilter> for FilterError {
+    fn from(err: UnsupportedFilter) -> Self {
+        FilterError::Unsupported(err)
+    }
+}
+impl core::fmt::Display for FilterError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            FilterError::Contradiction { tag } => {
+                write!(f, "tag `{}` is both required and ruled out", tag)
+            }
+            FilterError::AllExcluded { tag } => {
+                write!(f, "every value required for tag `{}` is excluded", tag)
             }
-            let v = vs.iter().next().unwrap().clone();
-            cur = cur.children.entry(v).or_insert_with(TagTree::new);
+            FilterError::EmptyExclusion { tag } => {
+                write!(f, "tag `{}` has an empty set of excluded values", tag)
+            }
+            FilterError::EmptyRange { tag } => {
+                write!(f, "the range for tag `{}` contains no numbers", tag)
+            }
+            FilterError::Unsupported(err) => err.fmt(f),
         }
-        cur.interested.push(listener);
     }
 }
-impl<T: Listener> Listener for TreeScanner<T> {
-    fn accept(&mut self, evt: &Event) {
-        let mut cur = vec![&mut self.root];
-        for key in &self.pipeline {
-            let mut next = Vec::new();
-            for c in cur {
-                for listener in c.interested.iter_mut() {
-                    listener.accept(evt);
-                }
-                if let Some(passthrough) = c.passthrough.as_deref_mut() {
-                    next.push(passthrough);
-                }
-                if let Some(v) = evt.tags.get(key) {
-                    if let Some(child) = c.children.get_mut(v) {
-                        next.push(child);
-                    }
-                }
-            }
-            cur = next;
+#[cfg(feature = "std")]
+impl std::error::Error for FilterError {}
+
+/// Returned when a topic can't support a filter, naming the tag whose matcher it refused.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnsupportedFilter {
+    pub tag: String,
+}
+impl core::fmt::Display for UnsupportedFilter {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "the matcher on tag `{}` is not supported here", self.tag)
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for UnsupportedFilter {}
+
+/// A listener that panicked during `accept_isolated`.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListenerPanic {
+    pub id: SubscriptionId,
+    /// The panic message, if the payload was a string.
+    pub message: Option<String>,
+}
+
+// Runs a single delivery, catching a panic instead of letting it unwind through the topic. The
+// listener is only ever borrowed by the delivery, so any state it leaves half-updated stays inside
+// the listener itself; the topic's own bookkeeping is untouched.
+#[cfg(feature = "std")]
+pub(crate) fn isolate<R>(
+    id: SubscriptionId,
+    deliver: impl FnOnce() -> R,
+) -> Result<R, ListenerPanic> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(deliver)).map_err(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned());
+        ListenerPanic { id, message }
+    })
+}
+
+/// Counts of what a topic has dispatched, as returned by e.g. [`LinearScan::metrics`]. They
+/// start at zero when the topic is created and are kept up to date by every `accept` method;
+/// read them periodically to forward them to a monitoring system.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Events accepted, whether or not they matched any subscription.
+    pub events: u64,
+    /// Deliveries made, summed across every event and listener.
+    pub deliveries: u64,
+    /// Events that weren't delivered to any listener.
+    pub unmatched: u64,
+}
+impl Metrics {
+    // Counts one accepted event, which was delivered to `deliveries` listeners.
+    pub(crate) fn record(&mut self, deliveries: usize) {
+        self.events += 1;
+        self.deliveries += deliveries as u64;
+        if deliveries == 0 {
+            self.unmatched += 1;
         }
-        for c in cur {
-            for listener in c.interested.iter_mut() {
-                listener.accept(evt);
-            }
+    }
+}
+
+// Counts one delivery against a subscription's remaining deliveries, returning whether it has now
+// used them all up. Unlimited subscriptions never run out.
+pub(crate) fn spend(remaining: &mut Option<usize>) -> bool {
+    match remaining {
+        Some(n) => {
+            *n -= 1;
+            *n == 0
         }
+        None => false,
     }
 }
 
-#[cfg(test)]
-mod test {
-    use std::sync::{
-        atomic::{AtomicU32, Ordering},
-        Arc,
-    };
+/// A topic that checks each event against every subscription in turn. It accepts every kind of
+/// filter, and [`Expr`]s besides.
+///
+/// A filter with no constraints at all, such as [`Filter::match_all`], matches every event; in
+/// particular, an empty `Filter::tags` doesn't mean the filter matches nothing.
+///
+/// A scan is `Send` if its listeners are `Send`, and `Sync` if they are `Sync`, so it can be moved
+/// to another thread or shared behind an `Arc`. Subscribing and accepting from several threads at
+/// once takes a lock, such as the one `SyncTopic` provides.
+#[derive(Clone, Debug)]
+pub struct LinearScan<L> {
+    // Kept in delivery order: by descending priority, then by id, since ids are handed out in
+    // increasing order.
+    listeners: Vec<ScanEntry<L>>,
+    next_id: usize,
+    // Set by `enable_prefilter`, and kept up to date with `listeners` from then on.
+    prefilter: Option<Prefilter>,
+    // Set by `set_value_normalizer`. Every entry in `listeners` keeps a normalized copy of its
+    // predicate to match with.
+    normalizer: Option<fn(&str) -> String>,
+    // Set by `set_dead_letter`.
+    dead_letter: Option<L>,
+    policy: DeliveryPolicy,
+    metrics: Metrics,
+}
+#[derive(Clone, Debug)]
+struct ScanEntry<L> {
+    id: SubscriptionId,
+    listener: L,
+    // As subscribed, which is what `snapshot`, `drain` and friends hand back.
+    predicate: Predicate,
+    // `predicate` with its values normalized, while the scan has a normalizer.
+    normalized: Option<Predicate>,
+    // How many more events a limited subscription may receive.
+    remaining: Option<usize>,
+    priority: i32,
+}
+impl<L> ScanEntry<L> {
+    // What events are checked against.
+    fn checked(&self) -> &Predicate {
+        self.normalized.as_ref().unwrap_or(&self.predicate)
+    }
+}
+/// How [`LinearScan::subscribe_with_mode`] combines a filter's constraints.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MatchMode {
+    /// Every constraint must be satisfied, as for any other subscription.
+    #[default]
+    All,
+    /// At least one constraint must be satisfied. `TreeScanner` routes by descending through
+    /// every required tag, so only `LinearScan` supports this.
+    Any,
+}
 
-    use super::*;
+/// How a topic delivers an event that matches several subscriptions of the same listener, e.g. a
+/// listener subscribed once per filter rather than with `subscribe_any`. Set with
+/// [`LinearScan::set_delivery_policy`] or [`TreeScanner::set_delivery_policy`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DeliveryPolicy {
+    /// Every matching subscription receives the event, as it always has.
+    #[default]
+    PerSubscription,
+    /// Subscriptions whose listeners have the same [`Listener::listener_key`] receive the event
+    /// once between them: the first in delivery order gets it and the rest are skipped, without
+    /// using up their delivery limits. Listeners without a key are delivered to as usual.
+    OncePerListener,
+}
+impl DeliveryPolicy {
+    // Whether a listener with `key` should receive the event, given the keys of the listeners
+    // that already have.
+    fn admits(self, seen: &mut BTreeSet<u64>, key: Option<u64>) -> bool {
+        match (self, key) {
+            (DeliveryPolicy::OncePerListener, Some(key)) => seen.insert(key),
+            _ => true,
+        }
+    }
+}
+
+/// What a `LinearScan` subscription checks each event against, as handed back by
+/// [`LinearScan::drain`] and [`LinearScan::snapshot`].
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Predicate {
+    /// From `subscribe` and `subscribe_limited`.
+    Filter(Filter),
+    /// From `subscribe_any`.
+    Any(FilterSet),
+    /// From [`LinearScan::subscribe_expr`].
+    Expr(Expr),
+}
+impl Predicate {
+    pub fn matches<E: Tags>(&self, evt: &E) -> bool {
+        match self {
+            Predicate::Filter(filter) => filter.matches(evt),
+            Predicate::Any(filters) => filters.matches(evt),
+            Predicate::Expr(expr) => expr.matches(evt),
+        }
+    }
+}
+impl<L> Default for LinearScan<L> {
+    fn default() -> Self {
+        Self {
+            listeners: Vec::new(),
+            next_id: 0,
+            prefilter: None,
+            normalizer: None,
+            dead_letter: None,
+            policy: DeliveryPolicy::default(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+impl<L> LinearScan<L> {
+    /// A scan with room for `capacity` subscriptions before it needs to reallocate.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            listeners: Vec::with_capacity(capacity),
+            next_id: 0,
+            prefilter: None,
+            normalizer: None,
+            dead_letter: None,
+            policy: DeliveryPolicy::default(),
+            metrics: Metrics::default(),
+        }
+    }
 
-    macro_rules! mkset {
-        ($($x:expr),+ $(,)?) => {{
-            let mut s = BTreeSet::new();
-            $( s.insert($x.to_owned());)+
-            s
-        }};
+    /// Keeps a Bloom filter of the tag values subscriptions require, so that an event carrying
+    /// none of them is turned away without checking it against each subscription. This speeds up
+    /// topics where most events match nothing, at the cost of hashing each event's tags and a
+    /// little more work per subscription. It never changes which listeners an event reaches.
+    ///
+    /// Subscriptions that can match an event without it carrying any particular value, such as
+    /// ones that only require a tag to be present, [`Filter::match_all`], or any [`Expr`], leave
+    /// every event a candidate for as long as they are subscribed.
+    pub fn enable_prefilter(&mut self) {
+        self.prefilter = Some(Prefilter::build(
+            self.listeners.iter().map(ScanEntry::checked),
+        ));
     }
-    macro_rules! mkevt {
-        ($($tag:expr => $value:expr),* $(,)?) => {{
-            let mut tags = BTreeMap::new();
-            $( tags.insert($tag.to_owned(), $value.to_owned()); )*
-            Event { tags }
-        }};
+
+    /// Normalizes tag values before they are compared, e.g. to match them case-insensitively with
+    /// `|value| value.to_lowercase()`. Each value an accepted event carries is passed through
+    /// `normalize` before the event is checked against subscriptions, and so is each value that
+    /// subscriptions' filters and [`Expr`]s compare with, including those of subscriptions made
+    /// before the normalizer was set. Values checked by [`ValueMatch`]es are normalized, but the
+    /// patterns themselves are not. Listeners still receive the event as it was accepted.
+    ///
+    /// Subscriptions are still reported as they were made, e.g. by `snapshot` and `drain`, and
+    /// setting another normalizer replaces this one rather than being applied on top of it.
+    ///
+    /// `normalize` is a plain function, so that the scan stays `Clone`, `Send`, and `Sync`; it
+    /// can't capture anything.
+    pub fn set_value_normalizer(&mut self, normalize: fn(&str) -> String) {
+        self.normalizer = Some(normalize);
+        for entry in &mut self.listeners {
+            entry.normalized = Some(normalized(normalize, &entry.predicate));
+        }
+        if self.prefilter.is_some() {
+            self.enable_prefilter();
+        }
     }
-    macro_rules! mkfilter {
-        ($($tag:expr => [$($value:expr),+]),+ $(,)?) => {{
-            let mut tags = BTreeMap::new();
-            $( tags.insert($tag.to_owned(), mkset!($($value)*)); )*
-            Filter { tags }
-        }};
+
+    // `evt` as subscriptions see it, with its values normalized if a normalizer is set.
+    pub(crate) fn view<'a, E: Tags>(&self, evt: &'a E) -> TagView<'a, E> {
+        TagView::new(self.normalizer, evt)
     }
 
-    #[test]
-    fn tree_scanner_smoke_test() {
-        let mut topic = TreeScanner::default();
-        let count = Arc::new(AtomicU32::default());
-        topic.subscribe(Counter(count.clone()), Filter::EMPTY);
-        topic.subscribe(Counter(count.clone()), mkfilter! { "hello" => ["world"] });
+    // Whether the prefilter, if enabled, lets `evt` through to be checked against each
+    // subscription.
+    pub(crate) fn may_match<E: Tags>(&self, evt: &E) -> bool {
+        self.prefilter
+            .as_ref()
+            .is_none_or(|prefilter| prefilter.may_match(evt))
+    }
 
-        let evt = mkevt! {"hello" => "world"};
-        topic.accept(&evt);
-        assert_eq!(count.load(Ordering::SeqCst), 2);
+    /// Makes room for at least `additional` more subscriptions, e.g. before subscribing in bulk.
+    pub fn reserve(&mut self, additional: usize) {
+        self.listeners.reserve(additional);
     }
 
-    #[test]
-    fn tree_scanner_handles_missing_filter_tags() {
-        let mut topic = TreeScanner::default();
-        let count = Arc::new(AtomicU32::default());
-        topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["foo"] });
-        topic.subscribe(Counter(count.clone()), mkfilter! { "b" => ["foo"] });
+    /// How many subscriptions the scan can hold before it needs to reallocate.
+    pub fn capacity(&self) -> usize {
+        self.listeners.capacity()
+    }
 
-        let evt = mkevt! {
-            "a" => "foo",
-            "b" => "foo"
-        };
-        topic.accept(&evt);
-        assert_eq!(count.load(Ordering::SeqCst), 2);
+    /// Shrinks the capacity to the number of live subscriptions, e.g. after a burst of
+    /// unsubscribing. The scan grows again as needed.
+    pub fn shrink_to_fit(&mut self) {
+        self.listeners.shrink_to_fit();
     }
 
-    #[derive(Default)]
-    struct Counter(Arc<AtomicU32>);
-    impl Listener for Counter {
-        fn accept(&mut self, _evt: &Event) {
-            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// What the scan has dispatched so far. `matching_ids` doesn't dispatch, so it isn't counted.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Makes `listener` the dead letter, which receives each event that no subscription's
+    /// listener was delivered to, e.g. to log events that no subscription was set up for. Unlike
+    /// [`Metrics::unmatched`], this hands over the event itself. This replaces any earlier dead
+    /// letter; until one is set, such events are dropped.
+    ///
+    /// Every `accept` method delivers to it the way it delivers to subscriptions: `accept_owned`
+    /// moves the event into it, and `accept_try` calls `try_accept`, discarding any error since
+    /// there's no subscription to report it against. `accept_isolated` doesn't catch a panic from
+    /// it. Deliveries to the dead letter aren't counted in the metrics.
+    pub fn set_dead_letter(&mut self, listener: L) {
+        self.dead_letter = Some(listener);
+    }
+
+    /// The listener set by `set_dead_letter`, if any.
+    pub fn dead_letter(&self) -> Option<&L> {
+        self.dead_letter.as_ref()
+    }
+
+    // Hands `evt` to the dead letter, if there is one, once it has reached no listener.
+    fn undelivered<E>(&mut self, evt: &E)
+    where
+        L: Listener<E>,
+    {
+        if let Some(listener) = &mut self.dead_letter {
+            listener.accept(evt);
         }
     }
+
+    /// Sets how each dispatch method delivers an event to a listener subscribed more than once.
+    /// `accept_parallel` settles which subscriptions are delivered to in delivery order before
+    /// invoking any of them, so it skips the same ones `accept` would.
+    pub fn set_delivery_policy(&mut self, policy: DeliveryPolicy) {
+        self.policy = policy;
+    }
+
+    // The matching subscriptions that the delivery policy keeps `evt` from, worked out in delivery
+    // order, for the dispatch methods that can't skip them as they go.
+    fn withheld<E: Tags>(&self, view: &TagView<'_, E>) -> BTreeSet<SubscriptionId>
+    where
+        L: Listener<E>,
+    {
+        let mut withheld = BTreeSet::new();
+        if self.policy == DeliveryPolicy::PerSubscription {
+            return withheld;
+        }
+        let mut seen = BTreeSet::new();
+        for entry in &self.listeners {
+            if entry.checked().matches(view)
+                && !entry.listener.is_expired()
+                && !self.policy.admits(&mut seen, entry.listener.listener_key())
+            {
+                withheld.insert(entry.id);
+            }
+        }
+        withheld
+    }
+
+    /// Like `subscribe`, but `listener` receives every event matching `expr`.
+    pub fn subscribe_expr(&mut self, listener: L, expr: Expr) -> SubscriptionId {
+        self.insert(listener, Predicate::Expr(expr), None, 0)
+    }
+
+    /// Like `subscribe`, but `listener` is delivered to before every listener with a lower
+    /// priority, whenever they were subscribed. Plain subscriptions have priority 0, and listeners
+    /// with the same priority are delivered to in subscription order.
+    pub fn subscribe_with_priority(
+        &mut self,
+        listener: L,
+        filter: Filter,
+        priority: i32,
+    ) -> SubscriptionId {
+        self.insert(listener, Predicate::Filter(filter), None, priority)
+    }
+
+    /// Like `subscribe`, but with [`MatchMode::Any`] an event only needs to satisfy one of the
+    /// filter's constraints rather than all of them, e.g. `env=prod` or `region=us`.
+    ///
+    /// Each constraint becomes a filter of its own, and the subscription is stored and
+    /// snapshotted as the resulting [`Predicate::Any`]. A filter with no constraints at all has
+    /// nothing to satisfy, so in this mode it matches no events.
+    pub fn subscribe_with_mode(
+        &mut self,
+        listener: L,
+        filter: Filter,
+        mode: MatchMode,
+    ) -> SubscriptionId {
+        let predicate = match mode {
+            MatchMode::All => Predicate::Filter(filter),
+            MatchMode::Any => Predicate::Any(Self::split_constraints(filter)),
+        };
+        self.insert(listener, predicate, None, 0)
+    }
+
+    // One filter per constraint of `filter`.
+    fn split_constraints(filter: Filter) -> FilterSet {
+        let Filter {
+            tags,
+            exclude,
+            absent,
+            matchers,
+            key_prefixes,
+        } = filter;
+        let tags = tags.into_iter().map(|(tag, values)| Filter {
+            tags: BTreeMap::from([(tag, values)]),
+            ..Filter::EMPTY
+        });
+        let exclude = exclude.into_iter().map(|(tag, values)| Filter {
+            exclude: BTreeMap::from([(tag, values)]),
+            ..Filter::EMPTY
+        });
+        let absent = absent.into_iter().map(|tag| Filter {
+            absent: BTreeSet::from([tag]),
+            ..Filter::EMPTY
+        });
+        let matchers = matchers.into_iter().map(|(tag, m)| Filter {
+            matchers: BTreeMap::from([(tag, m)]),
+            ..Filter::EMPTY
+        });
+        let key_prefixes = key_prefixes.into_iter().map(|prefix| Filter {
+            key_prefixes: BTreeSet::from([prefix]),
+            ..Filter::EMPTY
+        });
+        tags.chain(exclude)
+            .chain(absent)
+            .chain(matchers)
+            .chain(key_prefixes)
+            .collect()
+    }
+
+    fn insert(
+        &mut self,
+        listener: L,
+        predicate: Predicate,
+        remaining: Option<usize>,
+        priority: i32,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        // A subscription limited to no events at all is used up before it starts.
+        if remaining == Some(0) {
+            return id;
+        }
+        let normalized = self
+            .normalizer
+            .map(|normalize| normalized(normalize, &predicate));
+        if let Some(prefilter) = &mut self.prefilter {
+            prefilter.add(normalized.as_ref().unwrap_or(&predicate));
+        }
+        // The new id is the largest yet, so it goes after every entry of the same priority. That's
+        // the end of `listeners` unless priorities are in use.
+        let idx = self
+            .listeners
+            .partition_point(|entry| entry.priority >= priority);
+        self.listeners.insert(
+            idx,
+            ScanEntry {
+                id,
+                listener,
+                predicate,
+                normalized,
+                remaining,
+                priority,
+            },
+        );
+        if self.prefilter.as_ref().is_some_and(Prefilter::is_crowded) {
+            self.enable_prefilter();
+        }
+        id
+    }
+
+    // Drops the subscriptions that have received as many events as they were limited to. This
+    // waits until an event has been fully dispatched, so that delivery never has to step around a
+    // hole in `listeners`.
+    fn remove_exhausted(&mut self) {
+        let prefilter = &mut self.prefilter;
+        self.listeners.retain(|entry| {
+            if entry.remaining != Some(0) {
+                return true;
+            }
+            if let Some(prefilter) = prefilter {
+                prefilter.remove(entry.checked());
+            }
+            false
+        });
+    }
+
+    /// Replaces what subscription `id` is checked against with `filter`. The subscription keeps
+    /// its id, its listener, its place in delivery order, and any deliveries left under a limit.
+    /// Returns whether `id` was subscribed.
+    pub fn update_filter(&mut self, id: SubscriptionId, filter: Filter) -> bool {
+        let Some(entry) = self.listeners.iter_mut().find(|entry| entry.id == id) else {
+            return false;
+        };
+        let predicate = Predicate::Filter(filter);
+        if let Some(prefilter) = &mut self.prefilter {
+            prefilter.remove(entry.checked());
+        }
+        entry.normalized = self
+            .normalizer
+            .map(|normalize| normalized(normalize, &predicate));
+        entry.predicate = predicate;
+        if let Some(prefilter) = &mut self.prefilter {
+            prefilter.add(entry.checked());
+        }
+        if self.prefilter.as_ref().is_some_and(Prefilter::is_crowded) {
+            self.enable_prefilter();
+        }
+        true
+    }
+
+    /// Drops every subscription. Ids handed out before stay stale rather than being reused.
+    pub fn clear(&mut self) {
+        self.listeners = Vec::new();
+        if self.prefilter.is_some() {
+            self.enable_prefilter();
+        }
+    }
+
+    /// Removes every subscription, handing back its listener and what it was subscribed with, in
+    /// delivery order. Priorities and delivery limits are not carried over, so a listener
+    /// resubscribed with its predicate receives every matching event.
+    pub fn drain(&mut self) -> Vec<(L, Predicate)> {
+        let drained = core::mem::take(&mut self.listeners)
+            .into_iter()
+            .map(|entry| (entry.listener, entry.predicate))
+            .collect();
+        if self.prefilter.is_some() {
+            self.enable_prefilter();
+        }
+        drained
+    }
+
+    /// The listener subscribed as `id`, if it's still subscribed.
+    pub fn get(&self, id: SubscriptionId) -> Option<&L> {
+        self.listeners
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| &entry.listener)
+    }
+
+    pub fn get_mut(&mut self, id: SubscriptionId) -> Option<&mut L> {
+        self.listeners
+            .iter_mut()
+            .find(|entry| entry.id == id)
+            .map(|entry| &mut entry.listener)
+    }
+
+    /// The distinct values that subscriptions' filters accept for `tag`, across every filter of
+    /// every subscription. Subscribed [`Expr`]s aren't taken into account.
+    pub fn values_for(&self, tag: &str) -> BTreeSet<&str> {
+        self.predicates()
+            .flat_map(|predicate| match predicate {
+                Predicate::Filter(filter) => core::slice::from_ref(filter),
+                Predicate::Any(filters) => &filters.filters[..],
+                Predicate::Expr(_) => &[],
+            })
+            .filter_map(|filter| filter.tags.get(tag))
+            .flatten()
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Every live subscription and what it's subscribed with, in delivery order.
+    pub fn iter(&self) -> impl Iterator<Item = (SubscriptionId, &Predicate)> {
+        self.listeners
+            .iter()
+            .map(|entry| (entry.id, &entry.predicate))
+    }
+
+    /// What every live subscription is subscribed with, in delivery order.
+    pub fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        self.listeners.iter().map(|entry| &entry.predicate)
+    }
+
+    /// What every subscription is subscribed with, in delivery order, e.g. to persist the routing
+    /// configuration with the `serde` feature. Listeners can't be saved; to restore, make fresh
+    /// ones and subscribe each with its predicate, via `subscribe`, `subscribe_any` or
+    /// `subscribe_expr` depending on the variant. Priorities and delivery limits aren't included.
+    pub fn snapshot(&self) -> Vec<Predicate> {
+        self.predicates().cloned().collect()
+    }
+
+    /// Moves every subscription out of `other` and into `self`, after the existing ones of the same
+    /// priority and in the same relative order, so they are delivered to in that order too.
+    ///
+    /// The moved subscriptions are given fresh ids in `self`. Returns, for each id they had in
+    /// `other`, the id they have now.
+    pub fn merge(&mut self, other: LinearScan<L>) -> BTreeMap<SubscriptionId, SubscriptionId> {
+        let mut ids = BTreeMap::new();
+        for entry in other.listeners {
+            let id = self.insert(
+                entry.listener,
+                entry.predicate,
+                entry.remaining,
+                entry.priority,
+            );
+            ids.insert(entry.id, id);
+        }
+        ids
+    }
+
+    /// Delivers `evt` like `accept` does, returning how many listeners it was delivered to.
+    ///
+    /// Listeners are visited by descending priority, then in subscription order; if one returns
+    /// `ControlFlow::Break`, the event is not delivered to any of the listeners after it.
+    ///
+    /// With the `tracing` feature, each call runs in an info-level `dispatch` span that records
+    /// the topic, the event's tags, and how many listeners it was delivered to, with a debug-level
+    /// event naming the subscription of each delivery. `TreeScanner` and `InvertedIndex` do the
+    /// same.
+    pub fn accept_counted<E: Tags>(&mut self, evt: &E) -> usize
+    where
+        L: Listener<E>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = trace::dispatch_span("LinearScan", evt);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            self.metrics.record(0);
+            self.undelivered(evt);
+            #[cfg(feature = "tracing")]
+            trace::finish(&span, 0);
+            return 0;
+        }
+        let mut count = 0;
+        let mut exhausted = false;
+        let mut seen = BTreeSet::new();
+        for entry in self.listeners.iter_mut() {
+            if entry.checked().matches(&view) {
+                if entry.listener.is_expired() {
+                    entry.remaining = Some(0);
+                    exhausted = true;
+                    continue;
+                }
+                if !self.policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                count += 1;
+                exhausted |= spend(&mut entry.remaining);
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subscription = entry.id.0, "delivering");
+                if entry.listener.accept_flow(evt).is_break() {
+                    break;
+                }
+            }
+        }
+        if exhausted {
+            self.remove_exhausted();
+        }
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        #[cfg(feature = "tracing")]
+        trace::finish(&span, count);
+        count
+    }
+
+    /// Like `accept_counted`, but takes ownership of `evt` and moves it into the last listener it's
+    /// delivered to rather than dropping it, which saves a clone if that listener needs to keep the
+    /// event. Every other listener borrows it as usual.
+    ///
+    /// This helps when an event fans out to few listeners, most often just one, and the caller
+    /// has no further use for it. When the caller needs the event afterwards, cloning it to call
+    /// this gains nothing over `accept`.
+    pub fn accept_owned<E: Tags>(&mut self, evt: E) -> usize
+    where
+        L: ConsumingListener<E>,
+    {
+        let view = self.view(&evt);
+        if !self.may_match(&view) {
+            self.metrics.record(0);
+            if let Some(listener) = &mut self.dead_letter {
+                listener.accept_owned(evt);
+            }
+            return 0;
+        }
+        let withheld = self.withheld(&view);
+        let last = self.listeners.iter().rposition(|entry| {
+            entry.checked().matches(&view)
+                && !entry.listener.is_expired()
+                && !withheld.contains(&entry.id)
+        });
+        // Any listener matching after the last one delivered to has expired or is withheld.
+        // Delivery stops short of them, so the expired ones are marked for removal here.
+        let mut exhausted = false;
+        for entry in &mut self.listeners[last.map_or(0, |last| last + 1)..] {
+            if entry.checked().matches(&view) && entry.listener.is_expired() {
+                entry.remaining = Some(0);
+                exhausted = true;
+            }
+        }
+        let Some(last) = last else {
+            if exhausted {
+                self.remove_exhausted();
+            }
+            self.metrics.record(0);
+            if let Some(listener) = &mut self.dead_letter {
+                listener.accept_owned(evt);
+            }
+            return 0;
+        };
+        let (before, rest) = self.listeners.split_at_mut(last);
+        let mut count = 0;
+        let mut stopped = false;
+        for entry in before {
+            if entry.checked().matches(&view) {
+                if entry.listener.is_expired() {
+                    entry.remaining = Some(0);
+                    exhausted = true;
+                    continue;
+                }
+                if withheld.contains(&entry.id) {
+                    continue;
+                }
+                count += 1;
+                exhausted |= spend(&mut entry.remaining);
+                if entry.listener.accept_flow(&evt).is_break() {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+        if !stopped {
+            let entry = &mut rest[0];
+            count += 1;
+            exhausted |= spend(&mut entry.remaining);
+            entry.listener.accept_owned(evt);
+        }
+        if exhausted {
+            self.remove_exhausted();
+        }
+        self.metrics.record(count);
+        count
+    }
+
+    /// Delivers each of `evts` in turn.
+    pub fn accept_batch<E: Tags>(&mut self, evts: &[E])
+    where
+        L: Listener<E>,
+    {
+        for evt in evts {
+            self.accept_counted(evt);
+        }
+    }
+
+    /// Delivers each event `evts` yields as soon as it's yielded, so that a lazily produced stream,
+    /// e.g. one parsed from a reader, never has to be held in memory at once. Returns the total
+    /// number of deliveries.
+    pub fn accept_stream<E: Tags, I: IntoIterator<Item = E>>(&mut self, evts: I) -> usize
+    where
+        L: Listener<E>,
+    {
+        evts.into_iter().map(|evt| self.accept_counted(&evt)).sum()
+    }
+
+    /// Like `accept_counted`, but a listener that panics doesn't stop the event from reaching the
+    /// listeners after it. Returns every panic that was caught.
+    #[cfg(feature = "std")]
+    pub fn accept_isolated<E: Tags>(&mut self, evt: &E) -> Vec<ListenerPanic>
+    where
+        L: Listener<E>,
+    {
+        let mut panics = Vec::new();
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            self.metrics.record(0);
+            self.undelivered(evt);
+            return panics;
+        }
+        let mut count = 0;
+        let mut exhausted = false;
+        let mut seen = BTreeSet::new();
+        for entry in self.listeners.iter_mut() {
+            if entry.checked().matches(&view) {
+                if entry.listener.is_expired() {
+                    entry.remaining = Some(0);
+                    exhausted = true;
+                    continue;
+                }
+                if !self.policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                count += 1;
+                exhausted |= spend(&mut entry.remaining);
+                match isolate(entry.id, || entry.listener.accept_flow(evt)) {
+                    Ok(flow) if flow.is_break() => break,
+                    Ok(_) => {}
+                    Err(panic) => panics.push(panic),
+                }
+            }
+        }
+        if exhausted {
+            self.remove_exhausted();
+        }
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        panics
+    }
+
+    /// Delivers `evt` to fallible listeners, returning the error from each listener that failed.
+    /// A failure doesn't stop the event from reaching the listeners after it.
+    pub fn accept_try<E: Tags>(&mut self, evt: &E) -> Vec<(SubscriptionId, L::Error)>
+    where
+        L: TryListener<E>,
+    {
+        let mut errors = Vec::new();
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            self.metrics.record(0);
+            if let Some(listener) = &mut self.dead_letter {
+                let _ = listener.try_accept(evt);
+            }
+            return errors;
+        }
+        let mut count = 0;
+        let mut exhausted = false;
+        let mut seen = BTreeSet::new();
+        for entry in self.listeners.iter_mut() {
+            if entry.checked().matches(&view) {
+                if !self.policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                count += 1;
+                exhausted |= spend(&mut entry.remaining);
+                if let Err(err) = entry.listener.try_accept(evt) {
+                    errors.push((entry.id, err));
+                }
+            }
+        }
+        if exhausted {
+            self.remove_exhausted();
+        }
+        self.metrics.record(count);
+        if count == 0 {
+            if let Some(listener) = &mut self.dead_letter {
+                let _ = listener.try_accept(evt);
+            }
+        }
+        errors
+    }
+
+    /// The ids of the subscriptions `evt` would be delivered to, in delivery order, without
+    /// invoking any listeners. A listener that stops propagation isn't taken into account.
+    pub fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            return Vec::new();
+        }
+        self.listeners
+            .iter()
+            .filter(|entry| entry.checked().matches(&view))
+            .map(|entry| entry.id)
+            .collect()
+    }
+
+    /// The listeners `evt` would be delivered to, in delivery order, for the caller to invoke
+    /// however it likes, e.g. each on a thread pool. Like `matching_ids`, this doesn't dispatch:
+    /// delivery limits aren't spent, expired listeners aren't skipped, and nothing is counted in
+    /// the metrics.
+    pub fn matches_mut<E: Tags>(&mut self, evt: &E) -> impl Iterator<Item = &mut L> {
+        let view = self.view(evt);
+        let matched: Vec<&mut L> = if self.may_match(&view) {
+            self.listeners
+                .iter_mut()
+                .filter(|entry| entry.checked().matches(&view))
+                .map(|entry| &mut entry.listener)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        matched.into_iter()
+    }
+
+    /// How many listeners `evt` would be delivered to, counted like [`LinearScan::matching_ids`]
+    /// but without collecting their ids.
+    pub fn fanout<E: Tags>(&self, evt: &E) -> usize {
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            return 0;
+        }
+        self.listeners
+            .iter()
+            .filter(|entry| entry.checked().matches(&view))
+            .count()
+    }
+
+    /// Like `accept`, but checks filters and invokes listeners across the rayon thread pool. This
+    /// pays off when there are many listeners doing CPU-bound work; listeners are invoked in no
+    /// particular order.
+    #[cfg(feature = "rayon")]
+    pub fn accept_parallel<E: Tags + Sync>(&mut self, evt: &E)
+    where
+        L: Listener<E> + Send,
+    {
+        use rayon::prelude::*;
+
+        let view = self.view(evt);
+        if !self.may_match(&view) {
+            self.metrics.record(0);
+            self.undelivered(evt);
+            return;
+        }
+        let withheld = self.withheld(&view);
+        // How many listeners each thread delivered to, and whether it used up any of them.
+        let (count, exhausted) = self
+            .listeners
+            .par_iter_mut()
+            .map(|entry| {
+                if !entry.checked().matches(&view) || withheld.contains(&entry.id) {
+                    return (0, false);
+                }
+                if entry.listener.is_expired() {
+                    entry.remaining = Some(0);
+                    return (0, true);
+                }
+                entry.listener.accept(evt);
+                (1, spend(&mut entry.remaining))
+            })
+            .reduce(|| (0, false), |a, b| (a.0 + b.0, a.1 || b.1));
+        if exhausted {
+            self.remove_exhausted();
+        }
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+    }
+}
+impl<L> Topic<L> for LinearScan<L> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.insert(listener, Predicate::Filter(filter), None, 0)
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.insert(listener, Predicate::Any(filters), None, 0)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.insert(listener, Predicate::Filter(filter), Some(max), 0)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        match self.listeners.iter().position(|entry| entry.id == id) {
+            Some(idx) => {
+                let entry = self.listeners.remove(idx);
+                if let Some(prefilter) = &mut self.prefilter {
+                    prefilter.remove(entry.checked());
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+impl<E: Tags, L: Listener<E>> Listener<E> for LinearScan<L> {
+    fn accept(&mut self, evt: &E) {
+        self.accept_counted(evt);
+    }
+}
+
+/// A topic that routes each event down a tree with one level per tag, so that an event only
+/// visits the subscriptions whose required tag values it carries.
+///
+/// A filter with no constraints at all, such as [`Filter::match_all`], is kept at the root of the
+/// tree and receives every event; in particular, an empty `Filter::tags` doesn't mean the filter
+/// matches nothing.
+///
+/// Like a [`LinearScan`], a scanner is `Send` if its listeners are `Send`, and `Sync` if they
+/// are `Sync`.
+#[derive(Clone)]
+pub struct TreeScanner<L> {
+    // The order in which tags are routed on, one level of the tree per tag.
+    pipeline: Vec<String>,
+    // Whether the pipeline was set explicitly, rather than grown from the filters seen so far.
+    fixed: bool,
+    tree: TagTree,
+    scratch: Scratch,
+    // Every subscribed listener. Tree nodes refer to these by id, which lets a single listener
+    // live in several branches at once (e.g. for a multi-valued filter).
+    listeners: BTreeMap<SubscriptionId, TreeEntry<L>>,
+    next_id: usize,
+    // How many listeners have a priority other than 0. While there are none, routed ids are
+    // already in delivery order.
+    prioritized: usize,
+    on_change: ChangeHook,
+    // Set by `set_dead_letter`.
+    dead_letter: Option<L>,
+    policy: DeliveryPolicy,
+    metrics: Metrics,
+}
+#[derive(Clone, Debug)]
+struct TreeEntry<L> {
+    listener: L,
+    // A plain subscription is stored as a set of one filter. Each filter is routed separately.
+    filters: FilterSet,
+    // The tree only routes on `Filter::tags`; filters with any other constraints are checked
+    // again right before delivery.
+    exact: bool,
+    // How many more events a limited subscription may receive.
+    remaining: Option<usize>,
+    priority: i32,
+}
+impl<L> Default for TreeScanner<L> {
+    fn default() -> Self {
+        Self {
+            pipeline: Vec::new(),
+            fixed: false,
+            tree: TagTree::new(),
+            scratch: Scratch::default(),
+            listeners: BTreeMap::new(),
+            next_id: 0,
+            prioritized: 0,
+            on_change: ChangeHook::default(),
+            dead_letter: None,
+            policy: DeliveryPolicy::default(),
+            metrics: Metrics::default(),
+        }
+    }
+}
+// The tree itself is summarized rather than printed node by node; `to_dot` renders all of it.
+impl<L: core::fmt::Debug> core::fmt::Debug for TreeScanner<L> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("TreeScanner")
+            .field("pipeline", &self.pipeline)
+            .field("fixed", &self.fixed)
+            .field("nodes", &self.tree.node_count())
+            .field("max_depth", &self.tree.max_depth())
+            .field("listeners", &self.listeners)
+            .finish()
+    }
+}
+/// A change to a topic's subscriptions, as reported to the hook set by [`TreeScanner::on_change`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopicEvent {
+    Subscribed(SubscriptionId),
+    /// Reported whether the subscription was unsubscribed, used up its delivery limit, or was
+    /// dropped by `clear`.
+    Unsubscribed(SubscriptionId),
+}
+// The hook set by `TreeScanner::on_change`, if any. A closure can't be cloned, so a clone of the
+// scanner starts out without one.
+#[derive(Default)]
+struct ChangeHook(Option<Box<dyn FnMut(TopicEvent) + Send + Sync>>);
+impl Clone for ChangeHook {
+    fn clone(&self) -> Self {
+        ChangeHook(None)
+    }
+}
+impl ChangeHook {
+    fn notify(&mut self, event: TopicEvent) {
+        if let Some(hook) = &mut self.0 {
+            hook(event);
+        }
+    }
+}
+
+/// A summary of a `TreeScanner`'s shape, for sizing topics.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TopicStats {
+    /// The number of live subscriptions.
+    pub subscriptions: usize,
+    /// The number of distinct tags the scanner routes on. Tags stay in the pipeline even after
+    /// every subscription that used them is gone.
+    pub pipeline_tags: usize,
+    /// The number of nodes in the routing tree, including the root.
+    pub nodes: usize,
+    /// The length of the longest path from the root to any node.
+    pub max_depth: usize,
+    /// The number of nodes the tree has room for, counting those left empty by unsubscribing,
+    /// which are reused before the tree grows again. See [`TreeScanner::shrink_to_fit`].
+    pub node_slots: usize,
+}
+
+impl<L> TreeScanner<L> {
+    /// A scanner with room for `tags` distinct tags in its pipeline before it needs to reallocate.
+    /// The tree and its listeners grow node by node, so there is nothing to reserve for them.
+    pub fn with_pipeline_capacity(tags: usize) -> Self {
+        Self {
+            pipeline: Vec::with_capacity(tags),
+            ..Self::default()
+        }
+    }
+
+    /// A scanner that routes on exactly `tags`, in the given order, rather than growing its
+    /// pipeline as filters arrive. See [`TreeScanner::set_pipeline`].
+    pub fn with_pipeline(tags: Vec<String>) -> Self {
+        let mut scanner = Self::default();
+        scanner.set_pipeline(tags);
+        scanner
+    }
+
+    /// Fixes the order in which tags are routed on, e.g. to put the most selective tag first, and
+    /// re-routes every existing subscription accordingly. Duplicate tags are ignored.
+    ///
+    /// Once the pipeline is fixed, later subscriptions no longer extend it. A filter may still
+    /// constrain tags outside the pipeline, but those constraints aren't routed on: they are
+    /// checked against each event that reaches the listener through the other tags, which is no
+    /// faster than a linear scan if the filter has no pipeline tags at all.
+    pub fn set_pipeline(&mut self, tags: Vec<String>) {
+        let mut pipeline: Vec<String> = Vec::with_capacity(tags.len());
+        for tag in tags {
+            if !pipeline.contains(&tag) {
+                pipeline.push(tag);
+            }
+        }
+        self.pipeline = pipeline;
+        self.fixed = true;
+        self.rebuild();
+    }
+
+    /// The tags events are routed on, in the order they're checked. Unless the pipeline was fixed
+    /// with [`TreeScanner::set_pipeline`], this is the order subscriptions introduced the tags in.
+    pub fn pipeline(&self) -> &[String] {
+        &self.pipeline
+    }
+
+    /// The listener subscribed as `id`, if it's still subscribed.
+    pub fn get(&self, id: SubscriptionId) -> Option<&L> {
+        self.listeners.get(&id).map(|entry| &entry.listener)
+    }
+
+    pub fn get_mut(&mut self, id: SubscriptionId) -> Option<&mut L> {
+        self.listeners.get_mut(&id).map(|entry| &mut entry.listener)
+    }
+
+    /// The distinct values that subscriptions are routed on for `tag`, which is empty if `tag`
+    /// isn't in the pipeline. Tags filters only require to be present aren't routed on any value,
+    /// and neither are tags outside a fixed pipeline.
+    pub fn values_for(&self, tag: &str) -> BTreeSet<&str> {
+        match self.pipeline.iter().position(|k| k == tag) {
+            Some(depth) => self.tree.values_at(depth),
+            None => BTreeSet::new(),
+        }
+    }
+
+    /// Reorders the pipeline so that tags with more distinct values across the current
+    /// subscriptions are routed on first, and re-routes every subscription accordingly. Ids and
+    /// listeners are unaffected.
+    ///
+    /// A tag that many subscriptions constrain to many different values splits the tree into many
+    /// small branches, so checking it early keeps events from visiting the branches that don't
+    /// concern them.
+    pub fn optimize(&mut self) {
+        let mut values: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+        for entry in self.listeners.values() {
+            for (tag, vs) in entry.filters.filters.iter().flat_map(|f| &f.tags) {
+                values
+                    .entry(tag)
+                    .or_default()
+                    .extend(vs.iter().map(String::as_str));
+            }
+        }
+        let mut pipeline = self.pipeline.clone();
+        // The sort is stable, so tags that tie keep their current order.
+        pipeline.sort_by_key(|tag| {
+            core::cmp::Reverse(values.get(tag.as_str()).map_or(0, BTreeSet::len))
+        });
+        self.pipeline = pipeline;
+        self.rebuild();
+    }
+
+    /// How many tree nodes routing `evt` visits, for comparing pipeline orders.
+    pub fn nodes_visited<E: Tags>(&self, evt: &E) -> usize {
+        let mut scratch = Scratch::default();
+        self.tree.collect(&self.pipeline, evt, &mut scratch);
+        scratch.visited
+    }
+
+    // Routes every subscription through a fresh tree, for after the pipeline has changed.
+    fn rebuild(&mut self) {
+        self.tree = TagTree::new();
+        for (&id, entry) in self.listeners.iter_mut() {
+            for filter in &entry.filters.filters {
+                let keys = Self::route(&self.pipeline, filter);
+                self.tree.insert(keys, filter, id);
+            }
+            entry.exact = Self::is_routed_exactly(&self.pipeline, &entry.filters);
+        }
+    }
+
+    // Reaching a listener through the tree means one of its filters routed the event there, so if
+    // the tree checked every constraint of every filter there is nothing left to check.
+    fn is_routed_exactly(pipeline: &[String], filters: &FilterSet) -> bool {
+        filters
+            .filters
+            .iter()
+            .all(|f| f.is_exact() && f.tags.keys().all(|k| pipeline.contains(k)))
+    }
+
+    /// The number of live subscriptions.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    pub fn stats(&self) -> TopicStats {
+        TopicStats {
+            subscriptions: self.listeners.len(),
+            pipeline_tags: self.pipeline.len(),
+            nodes: self.tree.node_count(),
+            max_depth: self.tree.max_depth(),
+            node_slots: self.tree.nodes.len(),
+        }
+    }
+
+    /// Releases the memory that subscriptions no longer need: the tree is rebuilt without the
+    /// nodes that unsubscribing left empty, and every buffer is shrunk to what its contents
+    /// need. Routing is unaffected, and the tree grows again as needed.
+    ///
+    /// This is worth calling after a burst of unsubscribing in a long-lived scanner, since the
+    /// tree otherwise keeps its high-water mark.
+    pub fn shrink_to_fit(&mut self) {
+        self.pipeline.shrink_to_fit();
+        self.tree.compact();
+        self.scratch = Scratch::default();
+    }
+
+    /// The length of the longest path from the root of the routing tree to any node, as in
+    /// [`TopicStats::max_depth`]. A path is at most as long as the pipeline; shorter paths mean
+    /// filters that leave the later tags unconstrained.
+    pub fn max_depth(&self) -> usize {
+        self.tree.max_depth()
+    }
+
+    /// The number of nodes in the routing tree that have no children and no passthrough, where
+    /// routing ends. A tree without any subscriptions is a single leaf.
+    pub fn leaf_count(&self) -> usize {
+        self.tree.shape().leaves
+    }
+
+    /// The average number of edges out of the nodes that have any, counting a passthrough as
+    /// an edge. With a high average, each tag an event is routed on rules out most of the
+    /// branches below it; an average close to 1 means long chains that events walk one node at
+    /// a time, which [`TreeScanner::optimize`] may be able to shorten. A tree without any edges
+    /// has an average of 0.
+    pub fn avg_branching(&self) -> f64 {
+        let shape = self.tree.shape();
+        if shape.branches == 0 {
+            return 0.0;
+        }
+        shape.edges as f64 / shape.branches as f64
+    }
+
+    /// What the scanner has dispatched so far. See [`LinearScan::metrics`].
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Makes `listener` the dead letter, which receives each event that no subscription's
+    /// listener was delivered to. See [`LinearScan::set_dead_letter`].
+    pub fn set_dead_letter(&mut self, listener: L) {
+        self.dead_letter = Some(listener);
+    }
+
+    /// The listener set by `set_dead_letter`, if any.
+    pub fn dead_letter(&self) -> Option<&L> {
+        self.dead_letter.as_ref()
+    }
+
+    // Hands `evt` to the dead letter, if there is one, once it has reached no listener.
+    fn undelivered<E>(&mut self, evt: &E)
+    where
+        L: Listener<E>,
+    {
+        if let Some(listener) = &mut self.dead_letter {
+            listener.accept(evt);
+        }
+    }
+
+    /// Sets how each dispatch method delivers an event to a listener subscribed more than once.
+    /// See [`LinearScan::set_delivery_policy`].
+    pub fn set_delivery_policy(&mut self, policy: DeliveryPolicy) {
+        self.policy = policy;
+    }
+
+    /// Renders the routing tree as a Graphviz DOT graph. Each node is labeled with the number of
+    /// listeners interested in it, edges to children are labeled with the tag value they require,
+    /// and edges to passthroughs are dashed.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph {\n");
+        let mut next = 0;
+        self.tree
+            .write_dot(TagTree::ROOT, &self.pipeline, &mut next, &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Drops every subscription and goes back to an empty pipeline that grows as filters arrive,
+    /// freeing the whole tree rather than pruning it node by node. Ids handed out before stay
+    /// stale rather than being reused.
+    pub fn clear(&mut self) {
+        let mut on_change = core::mem::take(&mut self.on_change);
+        for &id in self.listeners.keys() {
+            on_change.notify(TopicEvent::Unsubscribed(id));
+        }
+        *self = Self {
+            next_id: self.next_id,
+            on_change,
+            dead_letter: core::mem::take(&mut self.dead_letter),
+            policy: self.policy,
+            metrics: self.metrics,
+            ..Self::default()
+        };
+    }
+
+    /// The filters of every subscription, in subscription order, e.g. to persist the routing
+    /// configuration with the `serde` feature. A plain subscription's filter is reported as a set
+    /// of one. Listeners can't be saved; to restore, make fresh ones and `subscribe_any` each with
+    /// its filters. Delivery limits aren't included.
+    pub fn snapshot(&self) -> Vec<FilterSet> {
+        self.listeners
+            .values()
+            .map(|entry| entry.filters.clone())
+            .collect()
+    }
+
+    /// Calls `hook` whenever a subscription is added or removed, e.g. to keep a dashboard of live
+    /// subscriptions up to date. This replaces any earlier hook. The hook runs inside the call
+    /// that made the change, before it returns.
+    ///
+    /// A subscription limited to no events at all is never added, so it isn't reported. Clones of
+    /// the scanner start out without a hook.
+    ///
+    /// The hook has to be `Send` and `Sync` so that the scanner stays both whenever its listeners
+    /// are.
+    pub fn on_change(&mut self, hook: impl FnMut(TopicEvent) + Send + Sync + 'static) {
+        self.on_change = ChangeHook(Some(Box::new(hook)));
+    }
+
+    /// The number of nodes in the routing tree, including the root. Nodes that no longer lead to a
+    /// listener are pruned on unsubscribe, so a drained scanner is back to just the root.
+    pub fn node_count(&self) -> usize {
+        self.tree.node_count()
+    }
+
+    /// Groups the subscriptions whose filters are identical, in order of each group's first
+    /// subscription. A plain subscription's filter is reported as a set of one.
+    ///
+    /// Identical filters are already routed to the same tree nodes, so each group costs routing no
+    /// more than a single subscription would; listeners within a group only differ in what they
+    /// do with the event. Large groups are candidates for a single fan-out listener.
+    pub fn dedup_filters(&self) -> Vec<(&FilterSet, Vec<SubscriptionId>)> {
+        let mut groups: Vec<(&FilterSet, Vec<SubscriptionId>)> = Vec::new();
+        for (&id, entry) in &self.listeners {
+            match groups
+                .iter_mut()
+                .find(|(filters, _)| **filters == entry.filters)
+            {
+                Some((_, ids)) => ids.push(id),
+                None => groups.push((&entry.filters, vec![id])),
+            }
+        }
+        groups
+    }
+
+    // The portion of the pipeline that `filter` is routed along: everything up to and including
+    // the last tag it constrains. Tags it doesn't constrain along the way become passthroughs.
+    fn route<'a>(pipeline: &'a [String], filter: &Filter) -> &'a [String] {
+        let depth = pipeline
+            .iter()
+            .rposition(|k| filter.tags.contains_key(k))
+            .map_or(0, |i| i + 1);
+        &pipeline[..depth]
+    }
+}
+// The buffers `TagTree::collect` works in: the frontier of nodes at the current and next levels of
+// the pipeline, and the ids found so far. The scanner keeps them between events so that routing an
+// event doesn't allocate once they've grown large enough.
+#[derive(Clone, Default)]
+struct Scratch {
+    cur: Vec<NodeId>,
+    next: Vec<NodeId>,
+    matched: Vec<SubscriptionId>,
+    // How many nodes the last traversal visited.
+    visited: usize,
+}
+
+// An index into `TagTree::nodes`.
+type NodeId = usize;
+
+// What `TagTree::shape` counts.
+#[derive(Default)]
+struct Shape {
+    max_depth: usize,
+    // Nodes without children or a passthrough.
+    leaves: usize,
+    // Nodes with at least one, and how many they have between them.
+    branches: usize,
+    edges: usize,
+}
+
+// The nodes of the routing tree, which refer to each other by index so that a traversal can be
+// tracked without borrowing the tree. The root is always the first node.
+#[derive(Clone)]
+struct TagTree {
+    nodes: Vec<Node>,
+    // Pruned nodes, which are empty and can be handed out again.
+    free: Vec<NodeId>,
+}
+#[derive(Clone, Default)]
+struct Node {
+    // Listeners that are interested in any event that makes it this far into the pipeline.
+    interested: Vec<SubscriptionId>,
+    // Listeners that do not care about this particular tag in the pipeline, but want to be filtered on the subsequent ones.
+    passthrough: Option<NodeId>,
+    // Otherwise, keep proceeding down the tag pipeline.
+    children: BTreeMap<String, NodeId>,
+}
+impl Node {
+    fn is_empty(&self) -> bool {
+        self.interested.is_empty() && self.passthrough.is_none() && self.children.is_empty()
+    }
+}
+impl TagTree {
+    const ROOT: NodeId = 0;
+
+    fn new() -> Self {
+        Self {
+            nodes: vec![Node::default()],
+            free: Vec::new(),
+        }
+    }
+
+    fn node_count(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
+
+    // Moves the live nodes to the front of the arena, in the order a depth-first walk from the
+    // root reaches them, and frees everything after them.
+    fn compact(&mut self) {
+        let mut order = Vec::with_capacity(self.node_count());
+        let mut renumbered = vec![None; self.nodes.len()];
+        let mut stack = vec![Self::ROOT];
+        while let Some(n) = stack.pop() {
+            renumbered[n] = Some(order.len());
+            order.push(n);
+            let node = &self.nodes[n];
+            stack.extend(node.passthrough);
+            stack.extend(node.children.values().copied());
+        }
+        let mut old = core::mem::take(&mut self.nodes);
+        self.nodes = order
+            .iter()
+            .map(|&n| {
+                let mut node = core::mem::take(&mut old[n]);
+                node.interested.shrink_to_fit();
+                node.passthrough = node.passthrough.and_then(|c| renumbered[c]);
+                for child in node.children.values_mut() {
+                    *child = renumbered[*child].unwrap();
+                }
+                node
+            })
+            .collect();
+        self.free = Vec::new();
+    }
+
+    fn max_depth(&self) -> usize {
+        self.shape().max_depth
+    }
+
+    // Walks the whole tree once, counting what `TreeScanner`'s structure statistics are made of.
+    fn shape(&self) -> Shape {
+        let mut shape = Shape::default();
+        let mut stack = vec![(Self::ROOT, 0)];
+        while let Some((n, depth)) = stack.pop() {
+            shape.max_depth = shape.max_depth.max(depth);
+            let node = &self.nodes[n];
+            let edges = node.children.len() + usize::from(node.passthrough.is_some());
+            if edges == 0 {
+                shape.leaves += 1;
+            } else {
+                shape.branches += 1;
+                shape.edges += edges;
+            }
+            let below = node
+                .passthrough
+                .into_iter()
+                .chain(node.children.values().copied());
+            stack.extend(below.map(|c| (c, depth + 1)));
+        }
+        shape
+    }
+
+    // The values that nodes at `depth` have children for, i.e. the values routed on for the tag at
+    // that position in the pipeline.
+    fn values_at(&self, depth: usize) -> BTreeSet<&str> {
+        let mut level = vec![Self::ROOT];
+        for _ in 0..depth {
+            level = level
+                .iter()
+                .flat_map(|&n| {
+                    let node = &self.nodes[n];
+                    node.passthrough
+                        .into_iter()
+                        .chain(node.children.values().copied())
+                })
+                .collect();
+        }
+        level
+            .iter()
+            .flat_map(|&n| self.nodes[n].children.keys())
+            .map(String::as_str)
+            .collect()
+    }
+
+    // Writes `node` and everything below it, naming nodes in the order they're visited so that the
+    // output doesn't depend on where nodes happen to live in the arena. Returns the name of `node`.
+    fn write_dot(
+        &self,
+        node: NodeId,
+        pipeline: &[String],
+        next: &mut usize,
+        out: &mut String,
+    ) -> usize {
+        use core::fmt::Write;
+
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let name = *next;
+        *next += 1;
+        let n = &self.nodes[node];
+        writeln!(out, "  n{} [label=\"{}\"];", name, n.interested.len()).unwrap();
+        let Some((key, rest)) = pipeline.split_first() else {
+            return name;
+        };
+        for (value, &child) in &n.children {
+            let child = self.write_dot(child, rest, next, out);
+            let label = escape(&format!("{}={}", key, value));
+            writeln!(out, "  n{} -> n{} [label=\"{}\"];", name, child, label).unwrap();
+        }
+        if let Some(passthrough) = n.passthrough {
+            let child = self.write_dot(passthrough, rest, next, out);
+            let label = escape(key);
+            writeln!(
+                out,
+                "  n{} -> n{} [label=\"{}\", style=dashed];",
+                name, child, label
+            )
+            .unwrap();
+        }
+        name
+    }
+
+    fn alloc(&mut self) -> NodeId {
+        self.free.pop().unwrap_or_else(|| {
+            self.nodes.push(Node::default());
+            self.nodes.len() - 1
+        })
+    }
+
+    // Register `id` under every branch that `filter` accepts. A tag with several allowed values
+    // fans out into one child per value.
+    fn insert(&mut self, keys: &[String], filter: &Filter, id: SubscriptionId) {
+        self.insert_at(Self::ROOT, keys, filter, id);
+    }
+
+    fn insert_at(&mut self, node: NodeId, keys: &[String], filter: &Filter, id: SubscriptionId) {
+        let Some((key, rest)) = keys.split_first() else {
+            self.nodes[node].interested.push(id);
+            return;
+        };
+        // Presence-only constraints can't be routed on, so they are treated like unconstrained tags
+        // and checked at delivery time instead.
+        let Some(vs) = filter.tags.get(key).filter(|vs| !vs.is_empty()) else {
+            let child = match self.nodes[node].passthrough {
+                Some(child) => child,
+                None => {
+                    let child = self.alloc();
+                    self.nodes[node].passthrough = Some(child);
+                    child
+                }
+            };
+            self.insert_at(child, rest, filter, id);
+            return;
+        };
+        for v in vs {
+            let child = match self.nodes[node].children.get(v) {
+                Some(&child) => child,
+                None => {
+                    let child = self.alloc();
+                    self.nodes[node].children.insert(v.clone(), child);
+                    child
+                }
+            };
+            self.insert_at(child, rest, filter, id);
+        }
+    }
+
+    // Collects the ids of every node that `evt` reaches into `scratch.matched`, following each
+    // level of the pipeline. Each id appears once, in subscription order.
+    fn collect<E: Tags>(&self, pipeline: &[String], evt: &E, scratch: &mut Scratch) {
+        let Scratch {
+            cur,
+            next,
+            matched,
+            visited,
+        } = scratch;
+        cur.clear();
+        matched.clear();
+        cur.push(Self::ROOT);
+        *visited = 0;
+        for key in pipeline.iter() {
+            next.clear();
+            *visited += cur.len();
+            for c in cur.drain(..) {
+                let c = &self.nodes[c];
+                matched.extend_from_slice(&c.interested);
+                if let Some(passthrough) = c.passthrough {
+                    next.push(passthrough);
+                }
+                // A multi-valued event follows every child it has a value for.
+                for v in evt.values(key) {
+                    if let Some(&child) = c.children.get(v) {
+                        next.push(child);
+                    }
+                }
+            }
+            core::mem::swap(cur, next);
+        }
+        *visited += cur.len();
+        for c in cur.drain(..) {
+            matched.extend_from_slice(&self.nodes[c].interested);
+        }
+        // The same id can be reached along several paths: a multi-valued filter or a filter set
+        // places its listener in several branches, a multi-valued event follows several children,
+        // and an event may reach both a node's passthrough and one of its children. Every caller
+        // relies on getting each id once.
+        matched.sort_unstable();
+        matched.dedup();
+    }
+
+    // The inverse of `insert`: walks the same branches, removing `id` and pruning any node that
+    // no longer leads to a listener.
+    fn remove(&mut self, keys: &[String], filter: &Filter, id: SubscriptionId) {
+        self.remove_at(Self::ROOT, keys, filter, id);
+    }
+
+    fn remove_at(&mut self, node: NodeId, keys: &[String], filter: &Filter, id: SubscriptionId) {
+        let Some((key, rest)) = keys.split_first() else {
+            self.nodes[node].interested.retain(|&i| i != id);
+            return;
+        };
+        let Some(vs) = filter.tags.get(key).filter(|vs| !vs.is_empty()) else {
+            if let Some(passthrough) = self.nodes[node].passthrough {
+                self.remove_at(passthrough, rest, filter, id);
+                if self.nodes[passthrough].is_empty() {
+                    self.nodes[node].passthrough = None;
+                    self.free.push(passthrough);
+                }
+            }
+            return;
+        };
+        for v in vs {
+            if let Some(&child) = self.nodes[node].children.get(v) {
+                self.remove_at(child, rest, filter, id);
+                if self.nodes[child].is_empty() {
+                    self.nodes[node].children.remove(v);
+                    self.free.push(child);
+                }
+            }
+        }
+    }
+}
+impl<L> Topic<L> for TreeScanner<L> {
+    /// A filter with a matcher the tree can't route on, such as a regex or a numeric range, is
+    /// filed under its `tags` like any other and checked in full before each delivery, the way a
+    /// [`LinearScan`] checks it. Use [`TreeScanner::try_subscribe`] to refuse such filters instead.
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), None, 0)
+    }
+
+    /// Filters with matchers the tree can't route on are accepted as for `subscribe`.
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.insert(listener, filters, None, 0)
+    }
+
+    fn subscribe_checked(
+        &mut self,
+        listener: L,
+        filter: Filter,
+    ) -> Result<SubscriptionId, FilterError> {
+        filter.validate()?;
+        Ok(self.try_subscribe(listener, filter)?)
+    }
+
+    /// Filters with matchers the tree can't route on are accepted as for `subscribe`.
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), Some(max), 0)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let Some(entry) = self.listeners.remove(&id) else {
+            return false;
+        };
+        if entry.priority != 0 {
+            self.prioritized -= 1;
+        }
+        // The pipeline only grows at the end, and reordering it rebuilds the tree, so these are the
+        // same routes the filters were inserted along.
+        for filter in &entry.filters.filters {
+            let keys = Self::route(&self.pipeline, filter);
+            self.tree.remove(keys, filter, id);
+        }
+        self.on_change.notify(TopicEvent::Unsubscribed(id));
+        true
+    }
+}
+impl<L> TreeScanner<L> {
+    /// Like `subscribe`, but refuses filters with matchers the tree can't route on instead of
+    /// checking them in full before each delivery.
+    pub fn try_subscribe(
+        &mut self,
+        listener: L,
+        filter: Filter,
+    ) -> Result<SubscriptionId, UnsupportedFilter> {
+        self.try_subscribe_any(listener, FilterSet::from(vec![filter]))
+    }
+
+    /// Like `subscribe_any`, but refuses filters with matchers the tree can't route on, as
+    /// `try_subscribe` does.
+    pub fn try_subscribe_any(
+        &mut self,
+        listener: L,
+        filters: FilterSet,
+    ) -> Result<SubscriptionId, UnsupportedFilter> {
+        Self::check_supported(&filters)?;
+        Ok(self.insert(listener, filters, None, 0))
+    }
+
+    /// Like `subscribe`, but `listener` is delivered to before every listener with a lower
+    /// priority, whenever they were subscribed. Plain subscriptions have priority 0, and listeners
+    /// with the same priority are delivered to in subscription order.
+    pub fn subscribe_with_priority(
+        &mut self,
+        listener: L,
+        filter: Filter,
+        priority: i32,
+    ) -> SubscriptionId {
+        self.insert(listener, FilterSet::from(vec![filter]), None, priority)
+    }
+
+    fn insert(
+        &mut self,
+        listener: L,
+        filters: FilterSet,
+        remaining: Option<usize>,
+        priority: i32,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        // A subscription limited to no events at all is used up before it starts, so it leaves
+        // the pipeline as it was.
+        if remaining == Some(0) {
+            return id;
+        }
+        self.grow_pipeline(&filters);
+        for filter in &filters.filters {
+            let keys = Self::route(&self.pipeline, filter);
+            self.tree.insert(keys, filter, id);
+        }
+        let exact = Self::is_routed_exactly(&self.pipeline, &filters);
+        self.listeners.insert(
+            id,
+            TreeEntry {
+                listener,
+                filters,
+                exact,
+                remaining,
+                priority,
+            },
+        );
+        if priority != 0 {
+            self.prioritized += 1;
+        }
+        self.on_change.notify(TopicEvent::Subscribed(id));
+        id
+    }
+
+    // Refuses filters with matchers the tree can't route on.
+    fn check_supported(filters: &FilterSet) -> Result<(), UnsupportedFilter> {
+        let unsupported = filters
+            .filters
+            .iter()
+            .flat_map(|f| &f.matchers)
+            .find(|(_, m)| m.linear_only());
+        match unsupported {
+            Some((tag, _)) => Err(UnsupportedFilter { tag: tag.clone() }),
+            None => Ok(()),
+        }
+    }
+
+    // Appends the tags of `filters` that the pipeline doesn't route on yet, unless it was fixed.
+    fn grow_pipeline(&mut self, filters: &FilterSet) {
+        if self.fixed {
+            return;
+        }
+        for filter in &filters.filters {
+            let missing: Vec<String> = filter
+                .tags
+                .keys()
+                .filter(|k| !self.pipeline.contains(k))
+                .cloned()
+                .collect();
+            self.pipeline.extend(missing);
+        }
+    }
+
+    /// Replaces the filter of subscription `id` with `filter`, re-routing it through the tree. The
+    /// subscription keeps its id, its listener, its priority, and any deliveries left under a
+    /// limit. Returns whether `id` was subscribed.
+    ///
+    /// Filters with matchers the tree can't route on are accepted as for `subscribe`; use
+    /// [`TreeScanner::try_update_filter`] to refuse them.
+    pub fn update_filter(&mut self, id: SubscriptionId, filter: Filter) -> bool {
+        self.replace_filters(id, FilterSet::from(vec![filter]))
+    }
+
+    /// Like `update_filter`, but refuses filters with matchers the tree can't route on, leaving
+    /// the subscription as it was.
+    pub fn try_update_filter(
+        &mut self,
+        id: SubscriptionId,
+        filter: Filter,
+    ) -> Result<bool, UnsupportedFilter> {
+        let filters = FilterSet::from(vec![filter]);
+        Self::check_supported(&filters)?;
+        Ok(self.replace_filters(id, filters))
+    }
+
+    fn replace_filters(&mut self, id: SubscriptionId, filters: FilterSet) -> bool {
+        if !self.listeners.contains_key(&id) {
+            return false;
+        }
+        // Growing the pipeline doesn't move existing routes, so the old filters can still be
+        // found along the routes they were inserted along.
+        self.grow_pipeline(&filters);
+        let entry = self.listeners.get_mut(&id).unwrap();
+        for filter in &entry.filters.filters {
+            let keys = Self::route(&self.pipeline, filter);
+            self.tree.remove(keys, filter, id);
+        }
+        for filter in &filters.filters {
+            let keys = Self::route(&self.pipeline, filter);
+            self.tree.insert(keys, filter, id);
+        }
+        entry.exact = Self::is_routed_exactly(&self.pipeline, &filters);
+        entry.filters = filters;
+        true
+    }
+
+    /// Delivers `evt` like `accept` does, returning how many listeners it was delivered to.
+    ///
+    /// The whole tree is traversed before any listener is invoked, and the matching listeners are
+    /// then visited by descending priority, then in subscription order, however deep in the tree
+    /// each was found. If one returns
+    /// `ControlFlow::Break`, the event is not delivered to any of the listeners after it.
+    pub fn accept_counted<E: Tags>(&mut self, evt: &E) -> usize
+    where
+        L: Listener<E>,
+    {
+        #[cfg(feature = "tracing")]
+        let span = trace::dispatch_span("TreeScanner", evt);
+        #[cfg(feature = "tracing")]
+        let _entered = span.enter();
+        self.collect(evt);
+        let mut exhausted = Vec::new();
+        let count = Self::deliver(
+            &mut self.listeners,
+            &self.scratch.matched,
+            &mut exhausted,
+            self.policy,
+            evt,
+        );
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        #[cfg(feature = "tracing")]
+        trace::finish(&span, count);
+        count
+    }
+
+    // Unsubscribes the limited subscriptions that ran out during the last dispatch. Pruning the
+    // tree waits until dispatch is over, since `collect` has already handed out the ids it found.
+    fn remove_exhausted(&mut self, exhausted: Vec<SubscriptionId>) {
+        for id in exhausted {
+            self.unsubscribe(id);
+        }
+    }
+
+    /// Like `accept_counted`, but takes ownership of `evt` and moves it into the last listener it's
+    /// delivered to. See [`LinearScan::accept_owned`].
+    pub fn accept_owned<E: Tags>(&mut self, evt: E) -> usize
+    where
+        L: ConsumingListener<E>,
+    {
+        self.collect(&evt);
+        let matched = &self.scratch.matched;
+        let listeners = &mut self.listeners;
+        // The matching subscriptions the delivery policy keeps `evt` from, worked out in delivery
+        // order so the last one delivered to is known up front.
+        let mut withheld = BTreeSet::new();
+        if self.policy != DeliveryPolicy::PerSubscription {
+            let mut seen = BTreeSet::new();
+            for id in matched {
+                let entry = &listeners[id];
+                if (entry.exact || entry.filters.matches(&evt))
+                    && !entry.listener.is_expired()
+                    && !self.policy.admits(&mut seen, entry.listener.listener_key())
+                {
+                    withheld.insert(*id);
+                }
+            }
+        }
+        let last = matched.iter().rposition(|id| {
+            let entry = &listeners[id];
+            (entry.exact || entry.filters.matches(&evt))
+                && !entry.listener.is_expired()
+                && !withheld.contains(id)
+        });
+        // Any listener matching after the last one delivered to has expired or is withheld.
+        // Delivery stops short of them, so the expired ones are queued for removal here.
+        let mut exhausted: Vec<SubscriptionId> = matched[last.map_or(0, |last| last + 1)..]
+            .iter()
+            .filter(|id| {
+                let entry = &listeners[*id];
+                (entry.exact || entry.filters.matches(&evt)) && entry.listener.is_expired()
+            })
+            .copied()
+            .collect();
+        let Some(last) = last else {
+            self.remove_exhausted(exhausted);
+            self.metrics.record(0);
+            if let Some(listener) = &mut self.dead_letter {
+                listener.accept_owned(evt);
+            }
+            return 0;
+        };
+        let mut count = 0;
+        let mut stopped = false;
+        for id in &matched[..last] {
+            let entry = listeners.get_mut(id).unwrap();
+            if entry.exact || entry.filters.matches(&evt) {
+                if entry.listener.is_expired() {
+                    exhausted.push(*id);
+                    continue;
+                }
+                if withheld.contains(id) {
+                    continue;
+                }
+                if spend(&mut entry.remaining) {
+                    exhausted.push(*id);
+                }
+                count += 1;
+                if entry.listener.accept_flow(&evt).is_break() {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+        if !stopped {
+            let id = matched[last];
+            let entry = listeners.get_mut(&id).unwrap();
+            if spend(&mut entry.remaining) {
+                exhausted.push(id);
+            }
+            count += 1;
+            entry.listener.accept_owned(evt);
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        count
+    }
+
+    /// Delivers each of `evts` in turn.
+    pub fn accept_batch<E: Tags>(&mut self, evts: &[E])
+    where
+        L: Listener<E>,
+    {
+        for evt in evts {
+            self.accept_counted(evt);
+        }
+    }
+
+    /// Delivers each event `evts` yields as soon as it's yielded, so that a lazily produced stream,
+    /// e.g. one parsed from a reader, never has to be held in memory at once. Returns the total
+    /// number of deliveries. Routing reuses the same buffers from one event to the next.
+    pub fn accept_stream<E: Tags, I: IntoIterator<Item = E>>(&mut self, evts: I) -> usize
+    where
+        L: Listener<E>,
+    {
+        evts.into_iter().map(|evt| self.accept_counted(&evt)).sum()
+    }
+
+    /// Like `accept_counted`, but a listener that panics doesn't stop the event from reaching the
+    /// listeners after it. Returns every panic that was caught.
+    #[cfg(feature = "std")]
+    pub fn accept_isolated<E: Tags>(&mut self, evt: &E) -> Vec<ListenerPanic>
+    where
+        L: Listener<E>,
+    {
+        self.collect(evt);
+        let matched = &self.scratch.matched;
+        let mut panics = Vec::new();
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        let mut seen = BTreeSet::new();
+        for id in matched.iter() {
+            let entry = self.listeners.get_mut(id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                if entry.listener.is_expired() {
+                    exhausted.push(*id);
+                    continue;
+                }
+                if !self.policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                count += 1;
+                if spend(&mut entry.remaining) {
+                    exhausted.push(*id);
+                }
+                match isolate(*id, || entry.listener.accept_flow(evt)) {
+                    Ok(flow) if flow.is_break() => break,
+                    Ok(_) => {}
+                    Err(panic) => panics.push(panic),
+                }
+            }
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            self.undelivered(evt);
+        }
+        panics
+    }
+
+    /// Delivers `evt` to fallible listeners, returning the error from each listener that failed.
+    /// A failure doesn't stop the event from reaching the listeners after it.
+    pub fn accept_try<E: Tags>(&mut self, evt: &E) -> Vec<(SubscriptionId, L::Error)>
+    where
+        L: TryListener<E>,
+    {
+        self.collect(evt);
+        let mut errors = Vec::new();
+        let mut count = 0;
+        let mut exhausted = Vec::new();
+        let mut seen = BTreeSet::new();
+        for id in self.scratch.matched.iter() {
+            let entry = self.listeners.get_mut(id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                if !self.policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                count += 1;
+                if spend(&mut entry.remaining) {
+                    exhausted.push(*id);
+                }
+                if let Err(err) = entry.listener.try_accept(evt) {
+                    errors.push((*id, err));
+                }
+            }
+        }
+        self.remove_exhausted(exhausted);
+        self.metrics.record(count);
+        if count == 0 {
+            if let Some(listener) = &mut self.dead_letter {
+                let _ = listener.try_accept(evt);
+            }
+        }
+        errors
+    }
+
+    /// The ids of the subscriptions `evt` would be delivered to, in delivery order, without
+    /// invoking any listeners. A listener that stops propagation isn't taken into account.
+    pub fn matching_ids<E: Tags>(&self, evt: &E) -> Vec<SubscriptionId> {
+        // `self.scratch` can't be borrowed mutably here, so this pays for fresh buffers.
+        let mut scratch = Scratch::default();
+        self.tree.collect(&self.pipeline, evt, &mut scratch);
+        let mut matched = scratch.matched;
+        Self::order_by_priority(&self.listeners, self.prioritized, &mut matched);
+        matched.retain(|id| {
+            let entry = &self.listeners[id];
+            entry.exact || entry.filters.matches(evt)
+        });
+        matched
+    }
+
+    /// The listeners `evt` would be delivered to, in delivery order, for the caller to invoke. See
+    /// [`LinearScan::matches_mut`].
+    pub fn matches_mut<E: Tags>(&mut self, evt: &E) -> impl Iterator<Item = &mut L> {
+        // Each id's place in delivery order, sorted by id so that the listeners can be picked
+        // out in a single pass over the map and then put back in order.
+        let mut places: Vec<(SubscriptionId, usize)> = self
+            .matching_ids(evt)
+            .into_iter()
+            .enumerate()
+            .map(|(place, id)| (id, place))
+            .collect();
+        places.sort_unstable();
+        let mut matched: Vec<(usize, &mut L)> = self
+            .listeners
+            .iter_mut()
+            .filter_map(|(id, entry)| {
+                let found = places.binary_search_by_key(id, |&(id, _)| id).ok()?;
+                Some((places[found].1, &mut entry.listener))
+            })
+            .collect();
+        matched.sort_unstable_by_key(|&(place, _)| place);
+        matched.into_iter().map(|(_, listener)| listener)
+    }
+
+    /// How many listeners `evt` would be delivered to, counted like [`TreeScanner::matching_ids`]
+    /// but without putting them in delivery order.
+    pub fn fanout<E: Tags>(&self, evt: &E) -> usize {
+        let mut scratch = Scratch::default();
+        self.tree.collect(&self.pipeline, evt, &mut scratch);
+        scratch
+            .matched
+            .iter()
+            .filter(|id| {
+                let entry = &self.listeners[id];
+                entry.exact || entry.filters.matches(evt)
+            })
+            .count()
+    }
+
+    // Routes `evt` through the tree, leaving the ids it reached in `self.scratch.matched`, in
+    // delivery order.
+    fn collect<E: Tags>(&mut self, evt: &E) {
+        self.tree.collect(&self.pipeline, evt, &mut self.scratch);
+        Self::order_by_priority(&self.listeners, self.prioritized, &mut self.scratch.matched);
+    }
+
+    // `TagTree::collect` sorts ids by subscription order. The sort is stable, so this keeps that
+    // order among listeners of the same priority.
+    fn order_by_priority(
+        listeners: &BTreeMap<SubscriptionId, TreeEntry<L>>,
+        prioritized: usize,
+        matched: &mut [SubscriptionId],
+    ) {
+        if prioritized > 0 {
+            matched.sort_by_key(|id| core::cmp::Reverse(listeners[id].priority));
+        }
+    }
+
+    // Invokes the listeners that `collect` found for `evt`, until one stops propagation, returning
+    // how many it invoked. The ids of limited subscriptions that this used up are added to
+    // `exhausted`.
+    fn deliver<E: Tags>(
+        listeners: &mut BTreeMap<SubscriptionId, TreeEntry<L>>,
+        matched: &[SubscriptionId],
+        exhausted: &mut Vec<SubscriptionId>,
+        policy: DeliveryPolicy,
+        evt: &E,
+    ) -> usize
+    where
+        L: Listener<E>,
+    {
+        let mut count = 0;
+        let mut seen = BTreeSet::new();
+        for id in matched.iter() {
+            let entry = listeners.get_mut(id).unwrap();
+            if entry.exact || entry.filters.matches(evt) {
+                if entry.listener.is_expired() {
+                    exhausted.push(*id);
+                    continue;
+                }
+                if !policy.admits(&mut seen, entry.listener.listener_key()) {
+                    continue;
+                }
+                if spend(&mut entry.remaining) {
+                    exhausted.push(*id);
+                }
+                count += 1;
+                #[cfg(feature = "tracing")]
+                tracing::debug!(subscription = id.0, "delivering");
+                if entry.listener.accept_flow(evt).is_break() {
+                    break;
+                }
+            }
+        }
+        count
+    }
+}
+impl<E: Tags, L: Listener<E>> Listener<E> for TreeScanner<L> {
+    fn accept(&mut self, evt: &E) {
+        self.accept_counted(evt);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    };
+
+    use super::*;
+
+    #[test]
+    fn tree_scanner_smoke_test() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(count.clone()), Filter::EMPTY);
+        topic.subscribe(Counter(count.clone()), mkfilter! { "hello" => ["world"] });
+
+        let evt = mkevt! {"hello" => "world"};
+        topic.accept(&evt);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn tree_scanner_handles_missing_filter_tags() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["foo"] });
+        topic.subscribe(Counter(count.clone()), mkfilter! { "b" => ["foo"] });
+
+        let evt = mkevt! {
+            "a" => "foo",
+            "b" => "foo"
+        };
+        topic.accept(&evt);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn tree_scanner_multi_value_filter() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(
+            Counter(count.clone()),
+            mkfilter! { "color" => ["red", "blue"] },
+        );
+
+        topic.accept(&mkevt! {"color" => "red"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"color" => "blue"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        topic.accept(&mkevt! {"color" => "green"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn tree_scanner_multi_value_filter_delivers_once() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["foo"] });
+        topic.subscribe(
+            Counter(count.clone()),
+            mkfilter! { "a" => ["foo", "bar", "baz"], "b" => ["x", "y"] },
+        );
+
+        let evt = mkevt! {
+            "a" => "bar",
+            "b" => "y"
+        };
+        topic.accept(&evt);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let evt = mkevt! {
+            "a" => "foo",
+            "b" => "x"
+        };
+        topic.accept(&evt);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn linear_scan_exclude_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let filter = Filter {
+            exclude: mkfilter! { "env" => ["prod"] }.tags,
+            ..Filter::EMPTY
+        };
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        topic.accept(&mkevt! {"env" => "staging"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // An event without the excluded tag at all still passes.
+        topic.accept(&mkevt! {"region" => "us"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn linear_scan_exclude_combines_with_required_tags() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let filter = Filter {
+            exclude: mkfilter! { "env" => ["prod"] }.tags,
+            ..mkfilter! { "region" => ["us"] }
+        };
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"region" => "us", "env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        topic.accept(&mkevt! {"region" => "us", "env" => "dev"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"region" => "eu", "env" => "dev"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn tree_scanner_exclude_filter() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let filter = Filter {
+            exclude: mkfilter! { "env" => ["prod"] }.tags,
+            ..mkfilter! { "region" => ["us"] }
+        };
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"region" => "us", "env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+        topic.accept(&mkevt! {"region" => "us"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn linear_scan_presence_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut tags = BTreeMap::new();
+        tags.insert("trace_id".to_owned(), BTreeSet::new());
+        topic.subscribe(
+            Counter(count.clone()),
+            Filter {
+                tags,
+                ..Filter::EMPTY
+            },
+        );
+
+        topic.accept(&mkevt! {"trace_id" => "abc"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"span_id" => "abc"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn tree_scanner_presence_filter() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = mkfilter! { "env" => ["prod"] };
+        filter.tags.insert("trace_id".to_owned(), BTreeSet::new());
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod", "trace_id" => "abc"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "dev", "trace_id" => "abc"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn linear_scan_absent_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = mkfilter! { "env" => ["prod"] };
+        filter.absent.insert("deprecated".to_owned());
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "prod", "deprecated" => "true"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"deprecated" => "true"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn tree_scanner_absent_filter() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter.absent.insert("deprecated".to_owned());
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "prod", "deprecated" => "true"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn linear_scan_prefix_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter.matchers.insert(
+            "service".to_owned(),
+            ValueMatch::Prefix("api.users".to_owned()),
+        );
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"service" => "api.users.v2"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"service" => "api.orders.v2"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"region" => "api.users.v2"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn prefix_match() {
+        let m = ValueMatch::Prefix("api.users".to_owned());
+        assert!(m.matches("api.users"));
+        assert!(m.matches("api.users.v2"));
+        // The prefix must not be longer than the value.
+        assert!(!m.matches("api.user"));
+
+        let m = ValueMatch::Prefix(String::new());
+        assert!(m.matches(""));
+        assert!(m.matches("anything"));
+    }
+
+    #[test]
+    fn linear_scan_glob_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter
+            .matchers
+            .insert("path".to_owned(), ValueMatch::Glob(Glob::new("/api/*/42")));
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"path" => "/api/users/42"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"path" => "/api/users/7"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn regex_match() {
+        let unanchored = ValueMatch::Regex(regex::Regex::new("5[0-9]{2}").unwrap());
+        assert!(unanchored.matches("503"));
+        assert!(unanchored.matches("code 503 returned"));
+        assert!(!unanchored.matches("404"));
+
+        let anchored = ValueMatch::Regex(regex::Regex::new("^5[0-9]{2}$").unwrap());
+        assert!(anchored.matches("503"));
+        assert!(!anchored.matches("code 503 returned"));
+        assert!(!anchored.matches("5030"));
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn linear_scan_regex_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = mkfilter! { "env" => ["prod"] };
+        filter.matchers.insert(
+            "status".to_owned(),
+            ValueMatch::Regex(regex::Regex::new("^5..$").unwrap()),
+        );
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod", "status" => "503"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "prod", "status" => "200"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn tree_scanner_rejects_regex_filter() {
+        let mut topic = TreeScanner::default();
+        let mut filter = mkfilter! { "env" => ["prod"] };
+        filter.matchers.insert(
+            "status".to_owned(),
+            ValueMatch::Regex(regex::Regex::new("^5..$").unwrap()),
+        );
+        let err = topic
+            .try_subscribe(Counter(Arc::default()), filter)
+            .unwrap_err();
+        assert_eq!(err.tag, "status");
+        assert_eq!(topic.node_count(), 1);
+    }
+
+    #[test]
+    fn linear_scan_ignore_case_filter() {
+        let mut topic = LinearScan::default();
+        let insensitive = Arc::new(AtomicU32::default());
+        let sensitive = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter.matchers.insert(
+            "env".to_owned(),
+            ValueMatch::ExactIgnoreCase("prod".to_owned()),
+        );
+        topic.subscribe(Counter(insensitive.clone()), filter.clone());
+        topic.subscribe(Counter(sensitive.clone()), mkfilter! { "env" => ["prod"] });
+
+        for value in ["Prod", "prod", "PROD"] {
+            topic.accept(&mkevt! {"env" => value});
+        }
+        topic.accept(&mkevt! {"env" => "production"});
+        assert_eq!(insensitive.load(Ordering::SeqCst), 3);
+        assert_eq!(sensitive.load(Ordering::SeqCst), 1);
+
+        let mut tree = TreeScanner::default();
+        assert!(tree.try_subscribe(Counter(insensitive), filter).is_err());
+    }
+
+    #[test]
+    fn linear_scan_contains_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter.matchers.insert(
+            "message".to_owned(),
+            ValueMatch::Contains("timeout".to_owned()),
+        );
+        topic.subscribe(Counter(count.clone()), filter.clone());
+
+        topic.accept(&mkevt! {"message" => "upstream timeout after 30s"});
+        topic.accept(&mkevt! {"message" => "connection refused"});
+        topic.accept(&mkevt! {"status" => "timeout"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let mut tree = TreeScanner::default();
+        assert!(tree.try_subscribe(Counter(count), filter).is_err());
+    }
+
+    #[test]
+    fn contains_match() {
+        let timeout = ValueMatch::Contains("timeout".to_owned());
+        assert!(timeout.matches("timeout"));
+        assert!(timeout.matches("read timeout, retrying"));
+        assert!(!timeout.matches("time out"));
+        assert!(!timeout.matches(""));
+
+        let empty = ValueMatch::Contains(String::new());
+        assert!(empty.matches("anything"));
+        assert!(empty.matches(""));
+    }
+
+    #[test]
+    fn range_match() {
+        let inclusive = ValueMatch::Range {
+            min: 500.0,
+            max: 599.0,
+            inclusive: true,
+        };
+        assert!(inclusive.matches("503"));
+        assert!(inclusive.matches("5.5e2"));
+        assert!(!inclusive.matches("404"));
+        assert!(!inclusive.matches("600"));
+        assert!(inclusive.matches("500"));
+        assert!(inclusive.matches("599"));
+
+        let exclusive = ValueMatch::Range {
+            min: 500.0,
+            max: 599.0,
+            inclusive: false,
+        };
+        assert!(exclusive.matches("503"));
+        assert!(!exclusive.matches("500"));
+        assert!(!exclusive.matches("599"));
+
+        assert!(!inclusive.matches("five hundred"));
+        assert!(!inclusive.matches(""));
+        assert!(!inclusive.matches("NaN"));
+    }
+
+    #[test]
+    fn linear_scan_range_filter() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = Filter::EMPTY;
+        filter.matchers.insert(
+            "status".to_owned(),
+            ValueMatch::Range {
+                min: 500.0,
+                max: 599.0,
+                inclusive: true,
+            },
+        );
+        topic.subscribe(Counter(count.clone()), filter.clone());
+
+        topic.accept(&mkevt! {"status" => "503"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"status" => "ok"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        let mut tree = TreeScanner::default();
+        let err = tree
+            .try_subscribe(Counter(count.clone()), filter.clone())
+            .unwrap_err();
+        assert_eq!(err.tag, "status");
+        // The checked variant refuses it the same way.
+        assert_eq!(
+            tree.subscribe_checked(Counter(count.clone()), filter.clone()),
+            Err(FilterError::Unsupported(err))
+        );
+        assert!(tree.is_empty());
+
+        // `subscribe` accepts it, checking the range before each delivery.
+        tree.subscribe(Counter(count.clone()), filter);
+        tree.accept(&mkevt! {"status" => "503"});
+        tree.accept(&mkevt! {"status" => "ok"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn tree_scanner_prefix_filter() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut filter = mkfilter! { "env" => ["prod"] };
+        filter.matchers.insert(
+            "service".to_owned(),
+            ValueMatch::Prefix("api.users".to_owned()),
+        );
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod", "service" => "api.users.v2"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        topic.accept(&mkevt! {"env" => "prod", "service" => "api.orders"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn linear_scan_unsubscribe() {
+        let mut topic = LinearScan::default();
+        let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+        let ids: Vec<SubscriptionId> = counts
+            .iter()
+            .map(|c| topic.subscribe(Counter(c.clone()), mkfilter! { "hello" => ["world"] }))
+            .collect();
+
+        assert!(topic.unsubscribe(ids[1]));
+        assert!(!topic.unsubscribe(ids[1]));
+
+        topic.accept(&mkevt! {"hello" => "world"});
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![1, 0, 1]);
+
+        // The remaining ids still refer to the right subscriptions.
+        assert!(topic.unsubscribe(ids[2]));
+        topic.accept(&mkevt! {"hello" => "world"});
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn tree_scanner_unsubscribe() {
+        let mut topic = TreeScanner::default();
+        let a = Arc::new(AtomicU32::default());
+        let b = Arc::new(AtomicU32::default());
+        let id = topic.subscribe(Counter(a.clone()), mkfilter! { "hello" => ["world"] });
+        topic.subscribe(Counter(b.clone()), mkfilter! { "hello" => ["world"] });
+
+        assert!(topic.unsubscribe(id));
+        assert!(!topic.unsubscribe(id));
+        topic.accept(&mkevt! {"hello" => "world"});
+        assert_eq!(a.load(Ordering::SeqCst), 0);
+        assert_eq!(b.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn tree_scanner_unsubscribe_prunes_nodes() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["foo"] });
+        let baseline = topic.node_count();
+
+        for _ in 0..3 {
+            let id = topic.subscribe(
+                Counter(count.clone()),
+                mkfilter! { "a" => ["foo", "bar"], "b" => ["x"] },
+            );
+            let other = topic.subscribe(Counter(count.clone()), mkfilter! { "b" => ["y"] });
+            assert!(topic.node_count() > baseline);
+            topic.unsubscribe(id);
+            topic.unsubscribe(other);
+            assert_eq!(topic.node_count(), baseline);
+        }
+
+        topic.accept(&mkevt! {"a" => "foo", "b" => "x"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn matching_ids_agrees_with_delivery() {
+        fn check<T: Topic<Recorder> + Listener>(
+            mut topic: T,
+            matching_ids: impl Fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let mut absent = mkfilter! { "a" => ["1"] };
+            absent.absent.insert("c".to_owned());
+            let filters = [
+                mkfilter! { "a" => ["1"] },
+                mkfilter! { "a" => ["1", "2"], "b" => ["2"] },
+                Filter::EMPTY,
+                absent,
+                mkfilter! { "c" => ["3"] },
+            ];
+            let log = Arc::new(Mutex::new(Vec::new()));
+            let ids: Vec<SubscriptionId> = filters
+                .into_iter()
+                .enumerate()
+                .map(|(i, f)| topic.subscribe(Recorder(i, log.clone()), f))
+                .collect();
+
+            for evt in [
+                mkevt! {"a" => "1", "b" => "2"},
+                mkevt! {"a" => "1", "c" => "3"},
+                mkevt! {"b" => "2"},
+            ] {
+                let matched = matching_ids(&topic, &evt);
+                assert!(log.lock().unwrap().is_empty());
+                topic.accept(&evt);
+                let fired: Vec<SubscriptionId> =
+                    log.lock().unwrap().drain(..).map(|i| ids[i]).collect();
+                assert_eq!(matched, fired);
+            }
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), |t, e| t.matching_ids(e))
+        );
+    }
+
+    #[test]
+    fn len_tracks_subscriptions() {
+        let mut linear = LinearScan::default();
+        let mut tree = TreeScanner::default();
+        assert!(linear.is_empty() && tree.is_empty());
+        let mut ids = Vec::new();
+        for filter in [mkfilter! { "a" => ["1"] }, Filter::EMPTY] {
+            ids.push((
+                linear.subscribe(Counter::default(), filter.clone()),
+                tree.subscribe(Counter::default(), filter),
+            ));
+        }
+        assert_eq!((linear.len(), tree.len()), (2, 2));
+
+        let (l, t) = ids[0];
+        assert!(linear.unsubscribe(l) && tree.unsubscribe(t));
+        assert!(!linear.unsubscribe(l) && !tree.unsubscribe(t));
+        assert_eq!((linear.len(), tree.len()), (1, 1));
+        let (l, t) = ids[1];
+        linear.unsubscribe(l);
+        tree.unsubscribe(t);
+        assert!(linear.is_empty() && tree.is_empty());
+    }
+
+    #[test]
+    fn tree_scanner_stats() {
+        let mut topic = TreeScanner::default();
+        assert_eq!(
+            topic.stats(),
+            TopicStats {
+                subscriptions: 0,
+                pipeline_tags: 0,
+                nodes: 1,
+                max_depth: 0,
+                node_slots: 1,
+            }
+        );
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1", "2"] });
+        topic.subscribe(Counter::default(), mkfilter! { "b" => ["x"] });
+        // root -> a=1, a=2 and a passthrough, which leads to b=x.
+        assert_eq!(
+            topic.stats(),
+            TopicStats {
+                subscriptions: 2,
+                pipeline_tags: 2,
+                nodes: 5,
+                max_depth: 2,
+                node_slots: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn tree_scanner_shape() {
+        let mut topic = TreeScanner::default();
+        assert_eq!(
+            (topic.max_depth(), topic.leaf_count(), topic.avg_branching()),
+            (0, 1, 0.0)
+        );
+        // root -> a=1, a=2 and a passthrough, which leads to b=x and b=y.
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1", "2"] });
+        topic.subscribe(Counter::default(), mkfilter! { "b" => ["x", "y"] });
+        assert_eq!(
+            (topic.max_depth(), topic.leaf_count(), topic.avg_branching()),
+            (2, 4, 2.5)
+        );
+        // a=1 grows a chain through a passthrough for b down to c=z.
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"], "c" => ["z"] });
+        assert_eq!(
+            (topic.max_depth(), topic.leaf_count(), topic.avg_branching()),
+            (3, 4, 1.75)
+        );
+        assert_eq!(topic.max_depth(), topic.stats().max_depth);
+    }
+
+    #[test]
+    fn shrink_to_fit_after_unsubscribing() {
+        let mut tree = TreeScanner::default();
+        let mut linear = LinearScan::default();
+        let mut ids = Vec::new();
+        for n in 0..100 {
+            let filter = mkfilter! { "env" => ["prod"], "n" => [n.to_string()] };
+            ids.push((
+                tree.subscribe(Counter::default(), filter.clone()),
+                linear.subscribe(Counter::default(), filter),
+            ));
+        }
+        let kept = tree.subscribe(Counter::default(), mkfilter! { "region" => ["us"] });
+        for (t, l) in ids.drain(1..) {
+            tree.unsubscribe(t);
+            linear.unsubscribe(l);
+        }
+
+        let before = tree.stats();
+        let dot = tree.to_dot();
+        assert_eq!(before.nodes, 6);
+        assert!(before.node_slots > 100);
+        assert!(linear.capacity() >= 100);
+        tree.shrink_to_fit();
+        linear.shrink_to_fit();
+        assert_eq!(
+            tree.stats(),
+            TopicStats {
+                node_slots: 6,
+                ..before
+            }
+        );
+        assert!(linear.capacity() < 100);
+        // Routing is unchanged, and the tree can grow again.
+        assert_eq!(tree.to_dot(), dot);
+        let evt = mkevt! { "env" => "prod", "n" => "0", "region" => "us" };
+        assert_eq!(tree.matching_ids(&evt), vec![ids[0].0, kept]);
+        assert_eq!(linear.matching_ids(&evt), vec![ids[0].1]);
+        let again = tree.subscribe(Counter::default(), mkfilter! { "n" => ["5"] });
+        assert_eq!(tree.matching_ids(&mkevt! { "n" => "5" }), vec![again]);
+    }
+
+    #[test]
+    fn tree_scanner_fixed_pipeline() {
+        let mut topic = TreeScanner::with_pipeline(vec!["b".to_owned(), "a".to_owned()]);
+        let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+        topic.subscribe(
+            Counter(counts[0].clone()),
+            mkfilter! { "a" => ["1"], "b" => ["2"] },
+        );
+        topic.subscribe(Counter(counts[1].clone()), mkfilter! { "a" => ["1"] });
+        // `c` isn't in the pipeline, so it's checked at delivery instead.
+        topic.subscribe(
+            Counter(counts[2].clone()),
+            mkfilter! { "a" => ["1"], "c" => ["3"] },
+        );
+        assert_eq!(topic.stats().pipeline_tags, 2);
+        // root -> b=2 -> a=1, and root -> passthrough for b -> a=1.
+        assert_eq!(topic.node_count(), 5);
+
+        topic.accept(&mkevt! {"a" => "1", "b" => "2"});
+        topic.accept(&mkevt! {"a" => "1", "c" => "3"});
+        topic.accept(&mkevt! {"a" => "1", "b" => "2", "c" => "4"});
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![2, 3, 1]);
+    }
+
+    #[test]
+    fn tree_scanner_set_pipeline_reroutes() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let id = topic.subscribe(
+            Counter(count.clone()),
+            mkfilter! { "a" => ["1"], "b" => ["2"] },
+        );
+        topic.subscribe(Counter(count.clone()), mkfilter! { "b" => ["2"] });
+        topic.set_pipeline(vec!["b".to_owned()]);
+        assert_eq!(topic.stats().pipeline_tags, 1);
+
+        topic.accept(&mkevt! {"a" => "1", "b" => "2"});
+        topic.accept(&mkevt! {"a" => "9", "b" => "2"});
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        assert!(topic.unsubscribe(id));
+        topic.accept(&mkevt! {"a" => "1", "b" => "2"});
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn tree_scanner_optimize() {
+        let mut topic = TreeScanner::default();
+        let counts: Vec<Arc<AtomicU32>> = (0..4).map(|_| Arc::default()).collect();
+        // The first subscription puts the low-cardinality `env` ahead of `user`.
+        let ids = [
+            mkfilter! { "env" => ["prod"], "user" => ["u0"] },
+            mkfilter! { "user" => ["u1"] },
+            mkfilter! { "user" => ["u2"] },
+            mkfilter! { "env" => ["dev"], "user" => ["u3"] },
+        ]
+        .into_iter()
+        .zip(&counts)
+        .map(|(f, c)| topic.subscribe(Counter(c.clone()), f))
+        .collect::<Vec<_>>();
+
+        let evt = mkevt! {"env" => "prod", "user" => "u1"};
+        let before = topic.nodes_visited(&evt);
+        topic.optimize();
+        assert!(topic.nodes_visited(&evt) < before);
+
+        topic.accept(&evt);
+        topic.accept(&mkevt! {"env" => "prod", "user" => "u0"});
+        topic.accept(&mkevt! {"env" => "prod", "user" => "u3"});
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![1, 1, 0, 0]);
+        assert!(topic.unsubscribe(ids[1]));
+        topic.accept(&evt);
+        assert_eq!(counts[1].load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn tree_scanner_to_dot() {
+        let mut topic = TreeScanner::default();
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1", "2"] });
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"], "b" => ["x"] });
+        topic.subscribe(Counter::default(), mkfilter! { "b" => ["\"quoted\""] });
+        topic.subscribe(Counter::default(), Filter::EMPTY);
+        assert_eq!(
+            topic.to_dot(),
+            r#"digraph {
+  n0 [label="1"];
+  n1 [label="1"];
+  n2 [label="1"];
+  n1 -> n2 [label="b=x"];
+  n0 -> n1 [label="a=1"];
+  n3 [label="1"];
+  n0 -> n3 [label="a=2"];
+  n4 [label="0"];
+  n5 [label="1"];
+  n4 -> n5 [label="b=\"quoted\""];
+  n0 -> n4 [label="a", style=dashed];
+}
+"#
+        );
+    }
+
+    #[test]
+    fn tree_scanner_drains_to_root() {
+        let mut topic = TreeScanner::default();
+        let count = Arc::new(AtomicU32::default());
+        let mut presence = mkfilter! { "c" => ["z"] };
+        presence.tags.insert("b".to_owned(), BTreeSet::new());
+        let filters = [
+            mkfilter! { "a" => ["1"] },
+            mkfilter! { "a" => ["1", "2"], "b" => ["x"] },
+            // Routed through the passthrough for `a`, which the next filter also hangs below.
+            mkfilter! { "b" => ["y"] },
+            mkfilter! { "c" => ["z"] },
+            presence,
+            Filter::EMPTY,
+        ];
+        let ids: Vec<SubscriptionId> = filters
+            .iter()
+            .map(|f| topic.subscribe(Counter(count.clone()), f.clone()))
+            .collect();
+        assert!(topic.node_count() > 1);
+
+        // Draining the passthrough's first listener must leave the rest of it reachable.
+        topic.unsubscribe(ids[2]);
+        topic.accept(&mkevt! {"c" => "z"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        for id in ids {
+            topic.unsubscribe(id);
+        }
+        assert_eq!(topic.node_count(), 1);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn linear_scan_accept_parallel() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        for _ in 0..100 {
+            topic.subscribe(Counter(count.clone()), mkfilter! { "hello" => ["world"] });
+            topic.subscribe(Counter(count.clone()), mkfilter! { "hello" => ["there"] });
+        }
+
+        topic.accept_parallel(&mkevt! {"hello" => "world"});
+        assert_eq!(count.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn event_payload() {
+        fn check<T: Topic<FnListener<F, Event<String>>> + Listener<Event<String>>, F>(
+            mut topic: T,
+            listener: F,
+        ) where
+            F: FnMut(&Event<String>),
+        {
+            topic.subscribe(
+                FnListener::new(listener),
+                mkfilter! { "kind" => ["greeting"] },
+            );
+            for (kind, body) in [
+                ("greeting", "hello"),
+                ("farewell", "bye"),
+                ("greeting", "hi"),
+            ] {
+                let evt = Event {
+                    tags: mkevt! {"kind" => kind}.tags,
+                    data: body.to_owned(),
+                };
+                topic.accept(&evt);
+            }
+        }
+
+        for_each_backend!(T in [LinearScan, TreeScanner] => {
+            let mut bodies = Vec::new();
+            check(T::default(), |e| bodies.push(e.data.clone()));
+            assert_eq!(bodies, vec!["hello", "hi"]);
+        });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn filter_serde_round_trip() {
+        let mut filter = mkfilter! {
+            "env" => ["prod", "staging"],
+            "region" => ["us"],
+        };
+        filter.absent.insert("deprecated".to_owned());
+        filter
+            .matchers
+            .insert("service".to_owned(), ValueMatch::Prefix("api.".to_owned()));
+
+        let json = serde_json::to_string(&filter).unwrap();
+        assert_eq!(
+            json,
+            r#"{"tags":{"env":["prod","staging"],"region":["us"]},"absent":["deprecated"],"matchers":{"service":{"prefix":"api."}}}"#
+        );
+        assert_eq!(serde_json::from_str::<Filter>(&json).unwrap(), filter);
+
+        // Filters serialized before the optional constraints existed still deserialize.
+        let filter: Filter = serde_json::from_str(r#"{"tags":{"env":["prod"]}}"#).unwrap();
+        assert_eq!(filter, mkfilter! { "env" => ["prod"] });
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn event_serde_round_trip() {
+        let evt = Event {
+            tags: mkevt! {"b" => "2", "a" => "1", "c" => "3"}.tags,
+            data: "body".to_owned(),
+        };
+        let json = serde_json::to_string(&evt).unwrap();
+        assert_eq!(json, r#"{"tags":{"a":"1","b":"2","c":"3"},"data":"body"}"#);
+        assert_eq!(serde_json::from_str::<Event<String>>(&json).unwrap(), evt);
+    }
+
+    #[test]
+    fn accept_counted() {
+        let count = Arc::new(AtomicU32::default());
+        let filters = || {
+            [
+                Filter::EMPTY,
+                mkfilter! { "env" => ["prod"] },
+                mkfilter! { "env" => ["prod", "dev"] },
+                mkfilter! { "env" => ["dev"] },
+                mkfilter! { "env" => ["prod"], "region" => ["us"] },
+            ]
+        };
+        let mut linear = LinearScan::default();
+        let mut tree = TreeScanner::default();
+        for filter in filters() {
+            linear.subscribe(Counter(count.clone()), filter.clone());
+            tree.subscribe(Counter(count.clone()), filter);
+        }
+
+        for (evt, expected) in [
+            (mkevt! {"env" => "prod"}, 3),
+            (mkevt! {"env" => "prod", "region" => "us"}, 4),
+            (mkevt! {"env" => "dev"}, 3),
+            (mkevt! {"region" => "us"}, 1),
+        ] {
+            assert_eq!(linear.accept_counted(&evt), expected);
+            assert_eq!(tree.accept_counted(&evt), expected);
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2 * (3 + 4 + 3 + 1));
+    }
+
+    #[test]
+    fn stop_propagation_across_backends() {
+        fn listener(count: &Arc<AtomicU32>, flow: ControlFlow<()>) -> BoxListener {
+            let count = count.clone();
+            Box::new(StoppableFnListener::new(move |_: &Event| {
+                count.fetch_add(1, Ordering::SeqCst);
+                flow
+            }))
+        }
+
+        fn check<T: Topic<BoxListener>>(mut topic: T, accept: fn(&mut T, &Event) -> usize) {
+            let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+            topic.subscribe(
+                listener(&counts[0], ControlFlow::Continue(())),
+                Filter::EMPTY,
+            );
+            topic.subscribe(
+                listener(&counts[1], ControlFlow::Break(())),
+                mkfilter! { "hello" => ["world"] },
+            );
+            topic.subscribe(
+                listener(&counts[2], ControlFlow::Continue(())),
+                Filter::EMPTY,
+            );
+
+            assert_eq!(accept(&mut topic, &mkevt! {"hello" => "world"}), 2);
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![1, 1, 0]);
+
+            // The stopping listener only stops events it actually matched.
+            assert_eq!(accept(&mut topic, &mkevt! {"hello" => "there"}), 2);
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![2, 1, 1]);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex, BitsetScanner] =>
+            check(T::default(), T::accept_counted)
+        );
+    }
+
+    #[test]
+    fn accept_owned_stop_propagation() {
+        struct Claims(Arc<AtomicU32>, ControlFlow<()>);
+        impl Listener for Claims {
+            fn accept(&mut self, _evt: &Event) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn accept_flow(&mut self, evt: &Event) -> ControlFlow<()> {
+                self.accept(evt);
+                self.1
+            }
+        }
+        impl ConsumingListener for Claims {}
+
+        fn check<T: Topic<Claims>>(mut topic: T, accept_owned: impl Fn(&mut T, Event) -> usize) {
+            let count = Arc::new(AtomicU32::default());
+            topic.subscribe_once(
+                Claims(count.clone(), ControlFlow::Break(())),
+                mkfilter! { "a" => ["1"] },
+            );
+            topic.subscribe(
+                Claims(count.clone(), ControlFlow::Continue(())),
+                Filter::EMPTY,
+            );
+            assert_eq!(accept_owned(&mut topic, mkevt! {"a" => "1"}), 1);
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+            // The limited listener is used up, so the other one gets the next event to keep.
+            assert_eq!(accept_owned(&mut topic, mkevt! {"a" => "1"}), 1);
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner] => check(T::default(), T::accept_owned));
+    }
+
+    #[test]
+    fn accept_owned_prunes_expired_final_matches() {
+        use std::sync::atomic::AtomicBool;
+
+        struct Expiring(Arc<AtomicBool>, Arc<AtomicU32>);
+        impl Listener for Expiring {
+            fn accept(&mut self, _evt: &Event) {
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn is_expired(&self) -> bool {
+                self.0.load(Ordering::SeqCst)
+            }
+        }
+        impl ConsumingListener for Expiring {}
+
+        fn check<T: Topic<Expiring>>(
+            mut topic: T,
+            accept_owned: fn(&mut T, Event) -> usize,
+            len: fn(&T) -> usize,
+        ) {
+            let (live, expired) = (
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(true)),
+            );
+            let count = Arc::new(AtomicU32::default());
+            topic.subscribe(Expiring(live, count.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Expiring(expired.clone(), count.clone()), Filter::EMPTY);
+            topic.subscribe(Expiring(expired, count.clone()), mkfilter! { "b" => ["1"] });
+            // The expired listener is the final match, after the one the event is moved into.
+            assert_eq!(accept_owned(&mut topic, mkevt! {"a" => "1"}), 1);
+            assert_eq!(len(&topic), 2);
+            // Only expired listeners match, so nothing is delivered, but they're still pruned.
+            assert_eq!(accept_owned(&mut topic, mkevt! {"b" => "1"}), 0);
+            assert_eq!(len(&topic), 1);
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner] =>
+            check(T::default(), T::accept_owned, T::len)
+        );
+    }
+
+    #[test]
+    fn tree_scanner_stop_propagation() {
+        fn listener(count: &Arc<AtomicU32>, flow: ControlFlow<()>) -> BoxListener {
+            let count = count.clone();
+            Box::new(StoppableFnListener::new(move |_: &Event| {
+                count.fetch_add(1, Ordering::SeqCst);
+                flow
+            }))
+        }
+
+        let mut topic = TreeScanner::default();
+        let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+        // Matched at the first level of the tree...
+        topic.subscribe(
+            listener(&counts[0], ControlFlow::Break(())),
+            mkfilter! { "a" => ["1"] },
+        );
+        // ...which keeps the event from listeners matched deeper down, or not routed at all.
+        topic.subscribe(
+            listener(&counts[1], ControlFlow::Continue(())),
+            mkfilter! { "a" => ["1"], "b" => ["2"] },
+        );
+        topic.subscribe(
+            listener(&counts[2], ControlFlow::Continue(())),
+            Filter::EMPTY,
+        );
+
+        assert_eq!(topic.accept_counted(&mkevt! {"a" => "1", "b" => "2"}), 1);
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![1, 0, 0]);
+
+        assert_eq!(topic.accept_counted(&mkevt! {"a" => "2", "b" => "2"}), 1);
+        let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+        assert_eq!(loaded, vec![1, 0, 1]);
+
+        #[cfg(feature = "std")]
+        {
+            assert!(topic
+                .accept_isolated(&mkevt! {"a" => "1", "b" => "2"})
+                .is_empty());
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![2, 0, 1]);
+        }
+    }
+
+    #[test]
+    fn tree_scanner_handles_filter_tags_late_in_pipeline() {
+        let mut topic = TreeScanner::default();
+        let a = Arc::new(AtomicU32::default());
+        let b = Arc::new(AtomicU32::default());
+        let ac = Arc::new(AtomicU32::default());
+        // Builds up a pipeline of [a, b, c], so neither later filter is a prefix of it.
+        topic.subscribe(Counter(a.clone()), mkfilter! { "a" => ["1"] });
+        topic.subscribe(Counter(b.clone()), mkfilter! { "b" => ["2"] });
+        topic.subscribe(
+            Counter(ac.clone()),
+            mkfilter! { "a" => ["1"], "c" => ["3"] },
+        );
+
+        topic.accept(&mkevt! {"a" => "9", "b" => "9", "c" => "9"});
+        topic.accept(&mkevt! {"a" => "1", "c" => "9"});
+        assert_eq!(a.load(Ordering::SeqCst), 1);
+        assert_eq!(b.load(Ordering::SeqCst), 0);
+        assert_eq!(ac.load(Ordering::SeqCst), 0);
+
+        topic.accept(&mkevt! {"b" => "2"});
+        topic.accept(&mkevt! {"a" => "1", "b" => "2", "c" => "3"});
+        assert_eq!(a.load(Ordering::SeqCst), 2);
+        assert_eq!(b.load(Ordering::SeqCst), 2);
+        assert_eq!(ac.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn dispatch_order_matches_across_backends() {
+        let filters = [
+            mkfilter! { "b" => ["2"] },
+            mkfilter! { "a" => ["1"], "b" => ["1", "2"] },
+            Filter::EMPTY,
+            mkfilter! { "a" => ["1"] },
+            mkfilter! { "b" => ["2"], "c" => ["3"] },
+            mkfilter! { "a" => ["1", "2"] },
+        ];
+        let linear_log = Arc::new(Mutex::new(Vec::new()));
+        let tree_log = Arc::new(Mutex::new(Vec::new()));
+        let mut linear = LinearScan::default();
+        let mut tree = TreeScanner::default();
+        for (i, filter) in filters.iter().enumerate() {
+            linear.subscribe(Recorder(i, linear_log.clone()), filter.clone());
+            tree.subscribe(Recorder(i, tree_log.clone()), filter.clone());
+        }
+
+        let evt = mkevt! {"a" => "1", "b" => "2", "c" => "3"};
+        linear.accept(&evt);
+        tree.accept(&evt);
+        assert_eq!(*linear_log.lock().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(*tree_log.lock().unwrap(), vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn multi_valued_events() {
+        fn check<T: Topic<Counter> + Listener<MultiEvent>>(mut topic: T) {
+            let counts: Vec<Arc<AtomicU32>> = (0..4).map(|_| Arc::default()).collect();
+            topic.subscribe(
+                Counter(counts[0].clone()),
+                mkfilter! { "label" => ["billing"] },
+            );
+            topic.subscribe(
+                Counter(counts[1].clone()),
+                mkfilter! { "label" => ["urgent", "spam"], "env" => ["prod"] },
+            );
+            topic.subscribe(
+                Counter(counts[2].clone()),
+                mkfilter! { "label" => ["spam"] },
+            );
+            topic.subscribe(
+                Counter(counts[3].clone()),
+                mkfilter! { "label" => ["urgent"], "env" => ["dev"] },
+            );
+
+            let evt = MultiEvent {
+                tags: BTreeMap::from([
+                    ("label".to_owned(), mkset!("urgent", "billing")),
+                    ("env".to_owned(), mkset!("prod")),
+                ]),
+                data: (),
+            };
+            topic.accept(&evt);
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![1, 1, 0, 0]);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hash_events_route_like_events() {
+        fn check<T: Topic<Counter> + Listener + Listener<HashEvent>>(mut topic: T) {
+            let counter = Counter::default();
+            topic.subscribe(counter.clone(), mkfilter! { "env" => ["prod"] });
+            topic.subscribe(
+                counter.clone(),
+                mkfilter! { "env" => ["prod"], "region" => ["us", "eu"] },
+            );
+            topic.subscribe(counter.clone(), mkfilter! { "region" => ["ap"] });
+            let evt = mkevt! { "env" => "prod", "region" => "eu", "host" => "h1" };
+            topic.accept(&evt);
+            let by_event = counter.0.swap(0, Ordering::SeqCst);
+            topic.accept(&HashEvent::from(evt));
+            assert_eq!(counter.0.load(Ordering::SeqCst), by_event);
+            assert_eq!(by_event, 2);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+    }
+
+    #[test]
+    fn multi_valued_exclude_and_absent() {
+        let evt = MultiEvent {
+            tags: BTreeMap::from([
+                ("label".to_owned(), mkset!("urgent", "billing")),
+                ("empty".to_owned(), BTreeSet::new()),
+            ]),
+            data: (),
+        };
+        let mut excluded = Filter::EMPTY;
+        excluded
+            .exclude
+            .insert("label".to_owned(), mkset!("billing"));
+        assert!(!excluded.matches(&evt));
+
+        // A tag with no values counts as absent.
+        let mut absent = Filter::EMPTY;
+        absent.absent.insert("empty".to_owned());
+        assert!(absent.matches(&evt));
+
+        let single: MultiEvent = mkevt! {"label" => "billing"}.into();
+        assert!(mkfilter! { "label" => ["billing"] }.matches(&single));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn accept_isolated_survives_panics() {
+        struct Panicker;
+        impl Listener for Panicker {
+            fn accept(&mut self, _evt: &Event) {
+                panic!("bad listener");
+            }
+        }
+
+        fn check<T: Topic<BoxListener> + Listener>(
+            mut topic: T,
+            accept_isolated: impl Fn(&mut T, &Event) -> Vec<ListenerPanic>,
+        ) {
+            let count = Arc::new(AtomicU32::default());
+            topic.subscribe(Box::new(Counter(count.clone())), Filter::EMPTY);
+            let bad = topic.subscribe(Box::new(Panicker), Filter::EMPTY);
+            topic.subscribe(Box::new(Counter(count.clone())), mkfilter! { "a" => ["1"] });
+
+            let panics = accept_isolated(&mut topic, &mkevt! {"a" => "1"});
+            assert_eq!(
+                panics,
+                vec![ListenerPanic {
+                    id: bad,
+                    message: Some("bad listener".to_owned()),
+                }]
+            );
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+
+            // The topic is still usable afterwards.
+            assert!(topic.unsubscribe(bad));
+            topic.accept(&mkevt! {"a" => "1"});
+            assert_eq!(count.load(Ordering::SeqCst), 4);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), |t, e| t.accept_isolated(e))
+        );
+    }
+
+    #[test]
+    fn accept_batch_matches_accept() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T, batch: impl Fn(&mut T, &[Event])) {
+            let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+            topic.subscribe(Counter(counts[0].clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Counter(counts[1].clone()), mkfilter! { "b" => ["1", "2"] });
+            topic.subscribe(Counter(counts[2].clone()), Filter::EMPTY);
+
+            let evts = [
+                mkevt! {"a" => "1"},
+                mkevt! {"a" => "1", "b" => "2"},
+                mkevt! {"b" => "3"},
+            ];
+            batch(&mut topic, &evts);
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![2, 1, 3]);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), |t, evts| t.accept_batch(evts))
+        );
+    }
+
+    #[test]
+    fn linear_scan_expr_subscription() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let expr = Expr::Or(vec![
+            Expr::Tag {
+                key: "env".to_owned(),
+                values: mkset!("prod"),
+            },
+            Expr::Not(Box::new(Expr::Tag {
+                key: "region".to_owned(),
+                values: BTreeSet::new(),
+            })),
+        ]);
+        let id = topic.subscribe_expr(Counter(count.clone()), expr);
+
+        topic.accept(&mkevt! {"env" => "prod", "region" => "us"});
+        topic.accept(&mkevt! {"env" => "dev"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        topic.accept(&mkevt! {"env" => "dev", "region" => "us"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+
+        assert!(topic.unsubscribe(id));
+        topic.accept(&mkevt! {"env" => "prod"});
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn subscribe_any_delivers_once() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T) {
+            let count = Arc::new(AtomicU32::default());
+            let mut staging = mkfilter! { "env" => ["staging"] };
+            staging.tags.insert("region".to_owned(), BTreeSet::new());
+            let filters = FilterSet::from(vec![
+                mkfilter! { "env" => ["prod"], "region" => ["us"] },
+                staging,
+                mkfilter! { "region" => ["us"] },
+            ]);
+            let id = topic.subscribe_any(Counter(count.clone()), filters);
+
+            // Both the first and the last filter match.
+            topic.accept(&mkevt! {"env" => "prod", "region" => "us"});
+            assert_eq!(count.load(Ordering::SeqCst), 1);
+            topic.accept(&mkevt! {"env" => "staging", "region" => "eu"});
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+            // Each tag matches some filter, but no single filter matches.
+            topic.accept(&mkevt! {"env" => "prod", "region" => "eu"});
+            topic.accept(&mkevt! {"env" => "staging"});
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+
+            assert!(topic.unsubscribe(id));
+            topic.accept(&mkevt! {"env" => "prod", "region" => "us"});
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+    }
+
+    #[test]
+    fn subscribe_once_fires_once() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T) {
+            let once = Arc::new(AtomicU32::default());
+            let always = Arc::new(AtomicU32::default());
+            let id = topic.subscribe_once(Counter(once.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Counter(always.clone()), mkfilter! { "a" => ["1"] });
+
+            topic.accept(&mkevt! {"b" => "1"});
+            topic.accept(&mkevt! {"a" => "1"});
+            topic.accept(&mkevt! {"a" => "1"});
+            assert_eq!(once.load(Ordering::SeqCst), 1);
+            assert_eq!(always.load(Ordering::SeqCst), 2);
+            // The subscription removed itself after the first match.
+            assert!(!topic.unsubscribe(id));
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+    }
+
+    #[test]
+    fn subscribe_limited_delivers_at_most_max() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T) {
+            let counts: Vec<Arc<AtomicU32>> = (0..3).map(|_| Arc::default()).collect();
+            let three = topic.subscribe_limited(Counter(counts[0].clone()), Filter::EMPTY, 3);
+            let none = topic.subscribe_limited(Counter(counts[1].clone()), Filter::EMPTY, 0);
+            topic.subscribe_limited(Counter(counts[2].clone()), mkfilter! { "a" => ["1"] }, 100);
+
+            for _ in 0..5 {
+                topic.accept(&mkevt! {"a" => "1"});
+            }
+            let loaded: Vec<u32> = counts.iter().map(|c| c.load(Ordering::SeqCst)).collect();
+            assert_eq!(loaded, vec![3, 0, 5]);
+            assert!(!topic.unsubscribe(three));
+            assert!(!topic.unsubscribe(none));
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+
+        // A subscription that's never stored doesn't grow the pipeline either.
+        let mut tree = TreeScanner::default();
+        tree.subscribe_limited(Counter::default(), mkfilter! { "a" => ["1"] }, 0);
+        assert!(tree.pipeline().is_empty());
+    }
+
+    #[test]
+    fn debug_output() {
+        let filter = mkfilter! { "env" => ["prod"] };
+        assert_eq!(
+            format!("{:?}", filter),
+            r#"Filter { tags: {"env": {"prod"}}, exclude: {}, absent: {}, matchers: {}, key_prefixes: {} }"#
+        );
+
+        let mut topic = TreeScanner::default();
+        topic.subscribe(Counter::default(), filter);
+        assert_eq!(
+            format!("{:?}", topic),
+            r#"TreeScanner { pipeline: ["env"], fixed: false, nodes: 2, max_depth: 1, listeners: {SubscriptionId(0): TreeEntry { listener: Counter(0), filters: FilterSet { filters: [Filter { tags: {"env": {"prod"}}, exclude: {}, absent: {}, matchers: {}, key_prefixes: {} }] }, exact: true, remaining: None, priority: 0 }} }"#
+        );
+    }
+
+    #[test]
+    fn event_accessors() {
+        let evt = mkevt! {"env" => "prod", "region" => "us"};
+        assert_eq!(evt.get("env"), Some("prod"));
+        assert_eq!(evt.get("missing"), None);
+        assert!(evt.contains_tag("region"));
+        assert!(!evt.contains_tag("missing"));
+        assert_eq!(evt.tag_count(), 2);
+        assert_eq!(&evt["region"], "us");
+    }
+
+    #[test]
+    #[should_panic(expected = "event has no tag `missing`")]
+    fn event_index_panics_on_missing_tag() {
+        let _ = &mkevt! {"env" => "prod"}["missing"];
+    }
+
+    #[test]
+    fn tree_scanner_passthrough_overlap_delivers_once() {
+        let mut topic = TreeScanner::with_pipeline(vec!["a".to_owned(), "b".to_owned()]);
+        let count = Arc::new(AtomicU32::default());
+        // One filter is routed through the `a=1` child and the other through the passthrough that
+        // skips `a`, so the event below reaches the same subscription along both paths.
+        topic.subscribe_any(
+            Counter(count.clone()),
+            FilterSet::from(vec![
+                mkfilter! { "a" => ["1"], "b" => ["2"] },
+                mkfilter! { "b" => ["2"] },
+            ]),
+        );
+        let other = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(other.clone()), mkfilter! { "a" => ["1"] });
+
+        let evt = mkevt! {"a" => "1", "b" => "2"};
+        assert_eq!(topic.matching_ids(&evt).len(), 2);
+        assert_eq!(topic.accept_counted(&evt), 2);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert_eq!(other.load(Ordering::SeqCst), 1);
+
+        // A multi-valued event reaches a multi-valued filter through one child per value.
+        let multi = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(multi.clone()), mkfilter! { "a" => ["1", "3"] });
+        let evt = MultiEvent {
+            tags: BTreeMap::from([("a".to_owned(), mkset!("1", "3"))]),
+            data: (),
+        };
+        topic.accept(&evt);
+        assert_eq!(multi.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn cloned_topic_dispatches_identically() {
+        fn check<T: Topic<Counter> + Listener + Clone>(
+            mut topic: T,
+            ids: impl Fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let count = Arc::new(AtomicU32::default());
+            let a = topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(
+                Counter(count.clone()),
+                mkfilter! { "a" => ["1", "2"], "b" => ["3"] },
+            );
+            topic.subscribe(Counter(count.clone()), Filter::EMPTY);
+
+            let mut clone = topic.clone();
+            let events = [
+                mkevt! {"a" => "1"},
+                mkevt! {"a" => "2", "b" => "3"},
+                mkevt! {"c" => "4"},
+            ];
+            for evt in &events {
+                assert_eq!(ids(&clone, evt), ids(&topic, evt));
+                topic.accept(evt);
+            }
+            let delivered = count.swap(0, Ordering::SeqCst);
+            for evt in &events {
+                clone.accept(evt);
+            }
+            assert_eq!(count.load(Ordering::SeqCst), delivered);
+
+            // The clone's subscriptions are its own, under the same ids.
+            assert!(clone.unsubscribe(a));
+            assert_eq!(ids(&clone, &events[0]).len(), 1);
+            assert_eq!(ids(&topic, &events[0]).len(), 2);
+            let id = clone.subscribe(Counter(count.clone()), Filter::EMPTY);
+            assert_eq!(topic.subscribe(Counter(count.clone()), Filter::EMPTY), id);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::matching_ids)
+        );
+    }
+
+    #[test]
+    fn linear_scan_merge() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut left = LinearScan::default();
+        left.subscribe(Recorder(0, log.clone()), mkfilter! { "a" => ["1"] });
+        left.subscribe(Recorder(1, log.clone()), mkfilter! { "b" => ["1"] });
+        let mut right = LinearScan::default();
+        let moved = right.subscribe(Recorder(2, log.clone()), mkfilter! { "a" => ["1"] });
+        right.subscribe(Recorder(3, log.clone()), Filter::EMPTY);
+
+        let ids = left.merge(right);
+        assert_eq!(left.len(), 4);
+        left.accept(&mkevt! {"a" => "1"});
+        assert_eq!(*log.lock().unwrap(), vec![0, 2, 3]);
+
+        assert!(left.unsubscribe(ids[&moved]));
+        left.accept(&mkevt! {"a" => "1"});
+        assert_eq!(*log.lock().unwrap(), vec![0, 2, 3, 0, 3]);
+    }
+
+    #[test]
+    fn accept_try_collects_errors() {
+        struct Flaky(bool, Arc<AtomicU32>);
+        impl TryListener for Flaky {
+            type Error = String;
+
+            fn try_accept(&mut self, _evt: &Event) -> Result<(), String> {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                if self.0 {
+                    Err("disk full".to_owned())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        fn check<T: Topic<Flaky>>(
+            mut topic: T,
+            accept_try: impl Fn(&mut T, &Event) -> Vec<(SubscriptionId, String)>,
+        ) {
+            let count = Arc::new(AtomicU32::default());
+            topic.subscribe(Flaky(false, count.clone()), Filter::EMPTY);
+            let bad = topic.subscribe(Flaky(true, count.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Flaky(false, count.clone()), mkfilter! { "a" => ["1"] });
+
+            let errors = accept_try(&mut topic, &mkevt! {"a" => "1"});
+            assert_eq!(errors, vec![(bad, "disk full".to_owned())]);
+            assert_eq!(count.load(Ordering::SeqCst), 3);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), |t, e| t.accept_try(e))
+        );
+
+        // Infallible listeners work too.
+        let mut topic = LinearScan::default();
+        topic.subscribe(Counter::default(), Filter::EMPTY);
+        assert!(topic.accept_try(&mkevt! {"a" => "1"}).is_empty());
+    }
+
+    #[test]
+    fn tree_scanner_shares_nodes_between_filters() {
+        let mut topic = TreeScanner::default();
+        let first = mkfilter! { "a" => ["1"], "b" => ["1"], "c" => ["1"] };
+        for _ in 0..1_000 {
+            topic.subscribe(Counter::default(), first.clone());
+        }
+        // Identical filters all end up on the one path: the root plus a node per tag.
+        assert_eq!(topic.node_count(), 4);
+
+        // Filters that only differ in their last value share every node but the last.
+        for i in 0..10 {
+            topic.subscribe(
+                Counter::default(),
+                mkfilter! { "a" => ["1"], "b" => ["1"], "c" => [format!("x{}", i)] },
+            );
+        }
+        // Without sharing, each of the 1,010 subscriptions would need a path of three nodes of its
+        // own, 3,031 nodes in all.
+        assert_eq!(topic.node_count(), 4 + 10);
+
+        let groups = topic.dedup_filters();
+        assert_eq!(groups.len(), 11);
+        assert_eq!(groups[0].0, &FilterSet::from(vec![first]));
+        assert_eq!(groups[0].1.len(), 1_000);
+        assert!(groups[1..].iter().all(|(_, ids)| ids.len() == 1));
+    }
+
+    #[test]
+    fn clear_drops_every_subscription() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T, clear: impl Fn(&mut T)) {
+            let count = Arc::new(AtomicU32::default());
+            let old = topic.subscribe(Counter(count.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Counter(count.clone()), Filter::EMPTY);
+            clear(&mut topic);
+            topic.accept(&mkevt! {"a" => "1"});
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+            assert!(!topic.unsubscribe(old));
+            // Ids from before the clear aren't handed out again.
+            assert_ne!(topic.subscribe(Counter(count.clone()), Filter::EMPTY), old);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::clear)
+        );
+
+        let mut topic = LinearScan::<Counter>::default();
+        topic.subscribe(Counter::default(), Filter::EMPTY);
+        topic.clear();
+        assert_eq!(topic.len(), 0);
+
+        let mut topic = TreeScanner::<Counter>::default();
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"], "b" => ["2"] });
+        topic.clear();
+        assert_eq!(topic.len(), 0);
+        assert_eq!(
+            topic.stats(),
+            TopicStats {
+                subscriptions: 0,
+                pipeline_tags: 0,
+                nodes: 1,
+                max_depth: 0,
+                node_slots: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn priority_ordering() {
+        fn check<T: Topic<Recorder> + Listener>(
+            mut topic: T,
+            subscribe_with_priority: impl Fn(&mut T, Recorder, Filter, i32) -> SubscriptionId,
+            matching_ids: impl Fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            topic.subscribe(Recorder(0, log.clone()), Filter::EMPTY);
+            subscribe_with_priority(&mut topic, Recorder(1, log.clone()), Filter::EMPTY, -1);
+            let high = subscribe_with_priority(
+                &mut topic,
+                Recorder(2, log.clone()),
+                mkfilter! { "a" => ["1"], "b" => ["1"] },
+                10,
+            );
+            topic.subscribe(Recorder(3, log.clone()), mkfilter! { "a" => ["1"] });
+            subscribe_with_priority(&mut topic, Recorder(4, log.clone()), Filter::EMPTY, 10);
+
+            let evt = mkevt! {"a" => "1", "b" => "1"};
+            topic.accept(&evt);
+            // Ties go to whichever was subscribed first.
+            assert_eq!(*log.lock().unwrap(), vec![2, 4, 0, 3, 1]);
+            assert_eq!(matching_ids(&topic, &evt).len(), 5);
+            assert_eq!(matching_ids(&topic, &evt)[0], high);
+
+            assert!(topic.unsubscribe(high));
+            log.lock().unwrap().clear();
+            topic.accept(&evt);
+            assert_eq!(*log.lock().unwrap(), vec![4, 0, 3, 1]);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner] =>
+            check(T::default(), T::subscribe_with_priority, T::matching_ids)
+        );
+    }
+
+    #[test]
+    fn values_for() {
+        fn check<T: Topic<Counter>>(mut topic: T, values_for: impl Fn(&T, &str) -> Vec<String>) {
+            topic.subscribe(Counter::default(), mkfilter! { "env" => ["prod"] });
+            topic.subscribe(
+                Counter::default(),
+                mkfilter! { "env" => ["prod", "staging"], "region" => ["us"] },
+            );
+            topic.subscribe_any(
+                Counter::default(),
+                FilterSet::from(vec![
+                    mkfilter! { "region" => ["eu"] },
+                    mkfilter! { "env" => ["dev"] },
+                ]),
+            );
+            let id = topic.subscribe(Counter::default(), mkfilter! { "env" => ["test"] });
+            topic.unsubscribe(id);
+
+            assert_eq!(values_for(&topic, "env"), ["dev", "prod", "staging"]);
+            assert_eq!(values_for(&topic, "region"), ["eu", "us"]);
+            assert!(values_for(&topic, "zone").is_empty());
+        }
+        fn collect(values: BTreeSet<&str>) -> Vec<String> {
+            values.into_iter().map(str::to_owned).collect()
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner] =>
+            check(T::default(), |t, tag| collect(t.values_for(tag)))
+        );
+    }
+
+    #[test]
+    fn tree_scanner_pipeline() {
+        let mut topic = TreeScanner::default();
+        assert!(topic.pipeline().is_empty());
+        topic.subscribe(Counter::default(), mkfilter! { "region" => ["us"] });
+        let id = topic.subscribe(
+            Counter::default(),
+            mkfilter! { "env" => ["prod"], "app" => ["a"] },
+        );
+        topic.subscribe(
+            Counter::default(),
+            mkfilter! { "region" => ["eu"], "zone" => ["z"] },
+        );
+        // A filter's own tags are added in key order.
+        assert_eq!(topic.pipeline(), ["region", "app", "env", "zone"]);
+
+        // Tags stay put once they're in the pipeline.
+        topic.unsubscribe(id);
+        assert_eq!(topic.pipeline(), ["region", "app", "env", "zone"]);
+
+        topic.set_pipeline(vec!["zone".to_owned(), "region".to_owned()]);
+        assert_eq!(topic.pipeline(), ["zone", "region"]);
+    }
+
+    #[test]
+    fn match_all_filter() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T) {
+            let count = Arc::new(AtomicU32::default());
+            topic.subscribe(Counter(count.clone()), Filter::match_all());
+            topic.accept(&mkevt! {"a" => "1"});
+            topic.accept(&mkevt! {"b" => "2", "c" => "3"});
+            topic.accept(&Event::builder().build());
+            assert_eq!(count.load(Ordering::SeqCst), 3);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+
+        assert!(Filter::match_all().matches_all());
+        assert!(!mkfilter! { "a" => ["1"] }.matches_all());
+        // Constraints other than `tags` count too.
+        let mut absent = Filter::match_all();
+        absent.absent.insert("a".to_owned());
+        assert!(!absent.matches_all());
+        let mut prefixed = Filter::match_all();
+        prefixed.key_prefixes.insert(String::new());
+        assert!(!prefixed.matches_all());
+    }
+
+    #[test]
+    fn linear_scan_iter() {
+        let mut topic = LinearScan::default();
+        let a = topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"] });
+        let b = topic.subscribe_once(Counter::default(), mkfilter! { "b" => ["1"] });
+        let c = topic.subscribe_any(Counter::default(), FilterSet::default());
+        assert!(topic.unsubscribe(b));
+
+        let subscribed: Vec<_> = topic.iter().collect();
+        assert_eq!(
+            subscribed,
+            vec![
+                (a, &Predicate::Filter(mkfilter! { "a" => ["1"] })),
+                (c, &Predicate::Any(FilterSet::default())),
+            ]
+        );
+        assert!(topic.predicates().eq(subscribed.iter().map(|(_, p)| *p)));
+    }
+
+    #[test]
+    fn linear_scan_drain() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut topic = LinearScan::default();
+        topic.subscribe(Recorder(0, log.clone()), mkfilter! { "a" => ["1"] });
+        topic.subscribe_limited(Recorder(1, log.clone()), Filter::EMPTY, 1);
+        topic.subscribe_any(
+            Recorder(2, log.clone()),
+            FilterSet::from(vec![mkfilter! { "b" => ["1"] }]),
+        );
+
+        let drained = topic.drain();
+        assert!(topic.is_empty());
+        assert_eq!(
+            drained.iter().map(|(_, p)| p).collect::<Vec<_>>(),
+            vec![
+                &Predicate::Filter(mkfilter! { "a" => ["1"] }),
+                &Predicate::Filter(Filter::EMPTY),
+                &Predicate::Any(FilterSet::from(vec![mkfilter! { "b" => ["1"] }])),
+            ]
+        );
+
+        // The listeners still work, wherever they end up.
+        let mut other = LinearScan::default();
+        for (listener, predicate) in drained {
+            match predicate {
+                Predicate::Filter(filter) => other.subscribe(listener, filter),
+                Predicate::Any(filters) => other.subscribe_any(listener, filters),
+                Predicate::Expr(expr) => other.subscribe_expr(listener, expr),
+            };
+        }
+        other.accept(&mkevt! {"a" => "1"});
+        other.accept(&mkevt! {"a" => "1"});
+        assert_eq!(*log.lock().unwrap(), vec![0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn linear_scan_capacity() {
+        let mut topic = LinearScan::with_capacity(1_000);
+        assert!(topic.capacity() >= 1_000);
+        for _ in 0..1_000 {
+            topic.subscribe(Counter::default(), Filter::EMPTY);
+        }
+        topic.reserve(500);
+        assert!(topic.capacity() >= 1_500);
+
+        let topic = TreeScanner::<Counter>::with_pipeline_capacity(8);
+        assert!(topic.pipeline.capacity() >= 8);
+    }
+
+    #[test]
+    fn key_prefix_filter() {
+        fn check<T: Topic<Counter> + Listener>(mut topic: T) {
+            let count = Arc::new(AtomicU32::default());
+            let filter = Filter {
+                key_prefixes: BTreeSet::from(["http.".to_owned()]),
+                ..Filter::EMPTY
+            };
+            topic.subscribe(Counter(count.clone()), filter);
+            topic.accept(&mkevt! {"http.method" => "GET"});
+            topic.accept(&mkevt! {"env" => "prod", "http.status" => "200"});
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+            // The prefix is matched against the whole key, not any dotted segment of it.
+            topic.accept(&mkevt! {"http" => "1", "grpc.http.method" => "GET"});
+            topic.accept(&mkevt! {"env" => "prod"});
+            assert_eq!(count.load(Ordering::SeqCst), 2);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner, InvertedIndex] => check(T::default()));
+    }
+
+    #[test]
+    fn key_prefix_combines_with_other_constraints() {
+        let mut topic = LinearScan::default();
+        let count = Arc::new(AtomicU32::default());
+        let filter = Filter {
+            key_prefixes: BTreeSet::from(["http.".to_owned(), "k8s.".to_owned()]),
+            ..mkfilter! { "env" => ["prod"] }
+        };
+        topic.subscribe(Counter(count.clone()), filter);
+
+        topic.accept(&mkevt! {"env" => "prod", "http.method" => "GET", "k8s.pod" => "a"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        // Every prefix has to be satisfied, and so does everything else.
+        topic.accept(&mkevt! {"env" => "prod", "http.method" => "GET"});
+        topic.accept(&mkevt! {"env" => "dev", "http.method" => "GET", "k8s.pod" => "a"});
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // A multi-valued tag with no values isn't carried, so it doesn't satisfy a prefix.
+        let filter = Filter {
+            key_prefixes: BTreeSet::from(["http.".to_owned()]),
+            ..Filter::EMPTY
+        };
+        let evt = MultiEvent {
+            tags: BTreeMap::from([("http.method".to_owned(), BTreeSet::new())]),
+            data: (),
+        };
+        assert!(!filter.matches(&evt));
+    }
+
+    #[test]
+    fn tree_scanner_on_change() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut topic = TreeScanner::default();
+        let first = topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"] });
+        {
+            let log = log.clone();
+            topic.on_change(move |change| log.lock().unwrap().push(change));
+        }
+        let second = topic.subscribe_once(Counter::default(), mkfilter! { "a" => ["1"] });
+        topic.subscribe_limited(Counter::default(), Filter::EMPTY, 0);
+        let third = topic.subscribe(Counter::default(), Filter::EMPTY);
+        assert!(topic.unsubscribe(first));
+        assert!(!topic.unsubscribe(first));
+        topic.accept(&mkevt! {"a" => "1"});
+        topic.clone().clear();
+        topic.clear();
+        assert_eq!(
+            *log.lock().unwrap(),
+            vec![
+                TopicEvent::Subscribed(second),
+                TopicEvent::Subscribed(third),
+                TopicEvent::Unsubscribed(first),
+                TopicEvent::Unsubscribed(second),
+                TopicEvent::Unsubscribed(third),
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_mut_leaves_invocation_to_the_caller() {
+        fn check<T: Topic<Recorder> + Listener>(
+            mut topic: T,
+            subscribe_with_priority: fn(&mut T, Recorder, Filter, i32) -> SubscriptionId,
+            matches_mut: for<'a> fn(&'a mut T, &Event) -> Vec<&'a mut Recorder>,
+            metrics: fn(&T) -> &Metrics,
+        ) {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            topic.subscribe(Recorder(0, log.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Recorder(1, log.clone()), mkfilter! { "b" => ["1"] });
+            subscribe_with_priority(&mut topic, Recorder(2, log.clone()), Filter::EMPTY, 1);
+            let evt = mkevt! {"a" => "1"};
+            let matched = matches_mut(&mut topic, &evt);
+            assert_eq!(matched.len(), 2);
+            for listener in matched {
+                listener.accept(&evt);
+            }
+            assert_eq!(*log.lock().unwrap(), vec![2, 0]);
+            assert_eq!(metrics(&topic).events, 0);
+        }
+
+        for_each_backend!(
+            T in [LinearScan, TreeScanner] =>
+            check(
+                T::default(),
+                T::subscribe_with_priority,
+                |topic, evt| topic.matches_mut(evt).collect(),
+                T::metrics,
+            )
+        );
+    }
+
+    #[test]
+    fn delivery_policies_with_a_double_subscribed_listener() {
+        // Stands in for a handle onto one shared listener, subscribed once per filter.
+        struct Keyed(u64, Arc<AtomicU32>);
+        impl Listener for Keyed {
+            fn accept(&mut self, _evt: &Event) {
+                self.1.fetch_add(1, Ordering::SeqCst);
+            }
+
+            fn listener_key(&self) -> Option<u64> {
+                Some(self.0)
+            }
+        }
+        impl ConsumingListener for Keyed {}
+
+        fn check<T: Topic<Keyed>>(
+            mut topic: T,
+            set: fn(&mut T, DeliveryPolicy),
+            accepts: &[fn(&mut T, &Event)],
+        ) {
+            let (shared, other) = (Arc::new(AtomicU32::new(0)), Arc::new(AtomicU32::new(0)));
+            topic.subscribe(Keyed(1, shared.clone()), mkfilter! { "a" => ["1"] });
+            topic.subscribe(Keyed(2, other.clone()), mkfilter! { "b" => ["1"] });
+            // The last match, so `accept_owned` has to look past it.
+            topic.subscribe(Keyed(1, shared.clone()), mkfilter! { "b" => ["1"] });
+            let evt = mkevt! {"a" => "1", "b" => "1"};
+            for accept in accepts {
+                set(&mut topic, DeliveryPolicy::PerSubscription);
+                accept(&mut topic, &evt);
+                let counts = || {
+                    (
+                        shared.swap(0, Ordering::SeqCst),
+                        other.swap(0, Ordering::SeqCst),
+                    )
+                };
+                assert_eq!(counts(), (2, 1));
+                set(&mut topic, DeliveryPolicy::OncePerListener);
+                accept(&mut topic, &evt);
+                assert_eq!(counts(), (1, 1));
+            }
+        }
+
+        check(
+            LinearScan::default(),
+            LinearScan::set_delivery_policy,
+            &[
+                |topic, evt| topic.accept(evt),
+                |topic, evt| {
+                    topic.accept_owned(evt.clone());
+                },
+                |topic, evt| {
+                    topic.accept_try(evt);
+                },
+                #[cfg(feature = "std")]
+                |topic, evt| {
+                    topic.accept_isolated(evt);
+                },
+                #[cfg(feature = "rayon")]
+                |topic, evt| topic.accept_parallel(evt),
+            ],
+        );
+        check(
+            TreeScanner::default(),
+            TreeScanner::set_delivery_policy,
+            &[
+                |topic, evt| topic.accept(evt),
+                |topic, evt| {
+                    topic.accept_owned(evt.clone());
+                },
+                |topic, evt| {
+                    topic.accept_try(evt);
+                },
+                #[cfg(feature = "std")]
+                |topic, evt| {
+                    topic.accept_isolated(evt);
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn dead_letter_gets_undelivered_events() {
+        type Log = RecordingListener<Event<u32>>;
+        fn evt(a: &str, data: u32) -> Event<u32> {
+            Event {
+                tags: [("a".to_owned(), a.to_owned())].into(),
+                data,
+            }
+        }
+
+        fn check<T: Topic<Log>>(
+            mut topic: T,
+            set_dead_letter: fn(&mut T, Log),
+            dead_letter: fn(&T) -> Option<&Log>,
+            accepts: &[fn(&mut T, Event<u32>)],
+        ) {
+            // Unset, an unmatched event goes nowhere.
+            accepts[0](&mut topic, evt("2", 0));
+            topic.subscribe(Log::new(), mkfilter! { "a" => ["1"] });
+            set_dead_letter(&mut topic, Log::new());
+            for (n, accept) in (0..).zip(accepts) {
+                accept(&mut topic, evt("1", n));
+                accept(&mut topic, evt("2", n));
+            }
+            let expected: Vec<_> = (0..).zip(accepts).map(|(n, _)| evt("2", n)).collect();
+            assert_eq!(dead_letter(&topic).unwrap().events(), expected);
+        }
+
+        for_each_backend!(T in [LinearScan, TreeScanner] => check(
+            T::default(),
+            T::set_dead_letter,
+            T::dead_letter,
+            &[
+                |topic, evt| topic.accept(&evt),
+                |topic, evt| {
+                    topic.accept_owned(evt);
+                },
+                |topic, evt| {
+                    topic.accept_try(&evt);
+                },
+                #[cfg(feature = "std")]
+                |topic, evt| {
+                    topic.accept_isolated(&evt);
+                },
+            ],
+        ));
+        check(
+            InvertedIndex::default(),
+            InvertedIndex::set_dead_letter,
+            InvertedIndex::dead_letter,
+            &[
+                |topic, evt| topic.accept(&evt),
+                |topic, evt| {
+                    topic.accept_try(&evt);
+                },
+                #[cfg(feature = "std")]
+                |topic, evt| {
+                    topic.accept_isolated(&evt);
+                },
+            ],
+        );
+        check(
+            BitsetScanner::default(),
+            BitsetScanner::set_dead_letter,
+            BitsetScanner::dead_letter,
+            &[|topic, evt| topic.accept(&evt)],
+        );
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let filters = || {
+            vec![
+                FilterSet::from(vec![mkfilter! { "a" => ["1"] }]),
+                FilterSet::from(vec![mkfilter! { "a" => ["1", "2"], "b" => ["1"] }]),
+                FilterSet::from(vec![mkfilter! { "b" => ["2"] }, mkfilter! { "c" => ["1"] }]),
+                FilterSet::from(vec![Filter::EMPTY]),
+            ]
+        };
+        let events = [
+            mkevt! {"a" => "1"},
+            mkevt! {"a" => "2", "b" => "1"},
+            mkevt! {"b" => "2", "c" => "1"},
+            mkevt! {"d" => "1"},
+        ];
+        // Which subscriptions, by position, each event reaches.
+        fn routing<T>(
+            topic: &T,
+            ids: &[SubscriptionId],
+            events: &[Event],
+            matching_ids: impl Fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) -> Vec<Vec<usize>> {
+            events
+                .iter()
+                .map(|evt| {
+                    let matched = matching_ids(topic, evt);
+                    (0..ids.len())
+                        .filter(|&i| matched.contains(&ids[i]))
+                        .collect()
+                })
+                .collect()
+        }
+        fn check<T: Topic<Counter>>(
+            mut topic: T,
+            filters: Vec<FilterSet>,
+            events: &[Event],
+            snapshot: impl Fn(&T) -> Vec<FilterSet>,
+            clear: impl Fn(&mut T),
+            matching_ids: impl Fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let ids: Vec<_> = filters
+                .iter()
+                .map(|fs| topic.subscribe_any(Counter::default(), fs.clone()))
+                .collect();
+            let before = routing(&topic, &ids, events, &matching_ids);
+
+            let saved = snapshot(&topic);
+            assert_eq!(saved, filters);
+            clear(&mut topic);
+            let ids: Vec<_> = saved
+                .into_iter()
+                .map(|fs| topic.subscribe_any(Counter::default(), fs))
+                .collect();
+            assert_eq!(routing(&topic, &ids, events, &matching_ids), before);
+        }
+        for_each_backend!(
+            T in [TreeScanner, InvertedIndex] =>
+            check(T::default(), filters(), &events, T::snapshot, T::clear, T::matching_ids)
+        );
+
+        let mut topic = LinearScan::default();
+        let mut ids: Vec<_> = filters()
+            .into_iter()
+            .map(|fs| topic.subscribe_any(Counter::default(), fs))
+            .collect();
+        ids.push(topic.subscribe_expr(
+            Counter::default(),
+            Expr::Not(Box::new(Expr::Tag {
+                key: "a".to_owned(),
+                values: BTreeSet::new(),
+            })),
+        ));
+        let before = routing(&topic, &ids, &events, LinearScan::matching_ids);
+        let saved = topic.snapshot();
+        topic.clear();
+        let ids: Vec<_> = saved
+            .into_iter()
+            .map(|predicate| match predicate {
+                Predicate::Filter(filter) => topic.subscribe(Counter::default(), filter),
+                Predicate::Any(filters) => topic.subscribe_any(Counter::default(), filters),
+                Predicate::Expr(expr) => topic.subscribe_expr(Counter::default(), expr),
+            })
+            .collect();
+        assert_eq!(
+            routing(&topic, &ids, &events, LinearScan::matching_ids),
+            before
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_serde_round_trip() {
+        let mut topic = LinearScan::default();
+        topic.subscribe(Counter::default(), mkfilter! { "a" => ["1"] });
+        let json = serde_json::to_string(&topic.snapshot()).unwrap();
+        assert_eq!(json, r#"[{"filter":{"tags":{"a":["1"]}}}]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<Predicate>>(&json).unwrap(),
+            topic.snapshot()
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn value_normalizer_matches_uppercase_events_to_lowercase_filters() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut topic = LinearScan::default();
+        topic.set_value_normalizer(|value| value.to_lowercase());
+        topic.subscribe(
+            ChannelListener::new(tx.clone()),
+            mkfilter! { "env" => ["prod"] },
+        );
+        topic.subscribe_expr(
+            ChannelListener::new(tx),
+            Expr::Tag {
+                key: "region".to_owned(),
+                values: mkset!["US"],
+            },
+        );
+        let evt = mkevt! { "env" => "PROD", "region" => "Us" };
+        assert_eq!(topic.accept_counted(&evt), 2);
+        // Listeners see the event as it was accepted, not its normalized values.
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![evt.clone(), evt]);
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "dev" }), 0);
+    }
+
+    #[test]
+    fn value_normalizer_applies_to_earlier_subscriptions() {
+        let mut topic = LinearScan::default();
+        topic.subscribe(Counter::default(), mkfilter! { "env" => ["Prod"] });
+        topic.enable_prefilter();
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "PROD" }), 0);
+        topic.set_value_normalizer(|value| value.to_lowercase());
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "PROD" }), 1);
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "prod" }), 1);
+    }
+
+    #[test]
+    fn value_normalizer_keeps_predicates_as_subscribed() {
+        let mut topic = LinearScan::default();
+        let subscribed = Predicate::Filter(mkfilter! { "env" => ["Prod"] });
+        topic.subscribe(Counter::default(), mkfilter! { "env" => ["Prod"] });
+        topic.set_value_normalizer(|value| value.to_lowercase());
+        topic.subscribe(Counter::default(), mkfilter! { "env" => ["Prod"] });
+        assert!(topic.predicates().all(|predicate| *predicate == subscribed));
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "PROD" }), 2);
+
+        // A second normalizer starts over from the subscribed values.
+        topic.set_value_normalizer(|value| value.to_uppercase());
+        assert_eq!(topic.accept_counted(&mkevt! { "env" => "prod" }), 2);
+        assert_eq!(
+            topic.snapshot(),
+            vec![subscribed.clone(), subscribed.clone()]
+        );
+        assert!(topic
+            .drain()
+            .into_iter()
+            .all(|(_, predicate)| predicate == subscribed));
+    }
+
+    #[test]
+    fn metrics_count_dispatches() {
+        let mut linear = LinearScan::default();
+        let mut tree = TreeScanner::default();
+        let mut index = InvertedIndex::default();
+        for filter in [
+            mkfilter! { "env" => ["prod"] },
+            Filter::single("region", "us"),
+        ] {
+            linear.subscribe(Counter::default(), filter.clone());
+            tree.subscribe(Counter::default(), filter.clone());
+            index.subscribe(Counter::default(), filter);
+        }
+        let events = [
+            mkevt! { "env" => "prod", "region" => "us" },
+            mkevt! { "env" => "prod" },
+            mkevt! { "env" => "dev" },
+        ];
+        for evt in &events {
+            linear.accept(evt);
+            tree.accept(evt);
+            index.accept(evt);
+        }
+        let expected = Metrics {
+            events: 3,
+            deliveries: 3,
+            unmatched: 1,
+        };
+        assert_eq!(*linear.metrics(), expected);
+        assert_eq!(*tree.metrics(), expected);
+        assert_eq!(*index.metrics(), expected);
+
+        // Clearing the subscriptions leaves what was already dispatched on the books.
+        linear.clear();
+        linear.accept(&events[0]);
+        assert_eq!(linear.metrics().unmatched, 2);
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tracing_test::traced_test]
+    #[test]
+    fn accept_emits_dispatch_span() {
+        let mut topic = LinearScan::default();
+        topic.subscribe(Counter::default(), mkfilter! { "env" => ["prod"] });
+        topic.subscribe(Counter::default(), Filter::single("region", "eu"));
+        topic.subscribe(Counter::default(), Filter::EMPTY);
+        assert_eq!(
+            topic.accept_counted(&mkevt! { "env" => "prod", "region" => "us" }),
+            2
+        );
+        let span = r#"dispatch{topic="LinearScan" tags=env=prod region=us"#;
+        assert!(logs_contain(&format!(
+            "{}}}: tagsub: delivering subscription=0",
+            span
+        )));
+        assert!(logs_contain(&format!(
+            "{}}}: tagsub: delivering subscription=2",
+            span
+        )));
+        assert!(!logs_contain("delivering subscription=1"));
+        assert!(logs_contain(&format!("{} matched=2}}", span)));
+    }
+
+    #[test]
+    fn update_filter_reroutes_subscription() {
+        fn check<T: Topic<Counter> + Listener>(
+            mut topic: T,
+            update: fn(&mut T, SubscriptionId, Filter) -> bool,
+            matching_ids: fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let counter = Counter::default();
+            let id = topic.subscribe(counter.clone(), mkfilter! { "env" => ["prod"] });
+            let other = topic.subscribe(Counter::default(), mkfilter! { "region" => ["us"] });
+            let prod = mkevt! { "env" => "prod" };
+            let dev = mkevt! { "env" => "dev", "region" => "eu" };
+            topic.accept(&prod);
+            assert!(update(
+                &mut topic,
+                id,
+                mkfilter! { "env" => ["dev"], "region" => ["eu"] }
+            ));
+            topic.accept(&prod);
+            topic.accept(&dev);
+            assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+            assert_eq!(matching_ids(&topic, &dev), vec![id]);
+            assert!(matching_ids(&topic, &prod).is_empty());
+
+            assert!(topic.unsubscribe(other));
+            assert!(!update(&mut topic, other, Filter::EMPTY));
+            assert!(topic.unsubscribe(id));
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::update_filter, T::matching_ids)
+        );
+    }
+
+    #[test]
+    fn tree_scanner_try_update_filter_keeps_old_filter() {
+        let mut topic = TreeScanner::default();
+        let id = topic.subscribe(Counter::default(), mkfilter! { "env" => ["prod"] });
+        let mut range = Filter::EMPTY;
+        range.matchers.insert(
+            "status".to_owned(),
+            ValueMatch::Range {
+                min: 500.0,
+                max: 599.0,
+                inclusive: true,
+            },
+        );
+        assert!(topic.try_update_filter(id, range).is_err());
+        assert_eq!(topic.matching_ids(&mkevt! { "env" => "prod" }), vec![id]);
+        assert!(topic.node_count() > 1);
+    }
+
+    #[test]
+    fn filter_matches_standalone() {
+        let filter = mkfilter! { "env" => ["prod"], "region" => ["us", "eu"] };
+        assert!(filter.matches(&mkevt! { "env" => "prod", "region" => "eu", "host" => "h1" }));
+        // Missing tag.
+        assert!(!filter.matches(&mkevt! { "env" => "prod" }));
+        // Wrong value.
+        assert!(!filter.matches(&mkevt! { "env" => "prod", "region" => "ap" }));
+        assert!(Filter::EMPTY.matches(&Event::builder().build()));
+        assert!(Filter::EMPTY.matches(&mkevt! { "env" => "dev" }));
+
+        // Topics deliver exactly the events the filter matches.
+        let events = [
+            mkevt! { "env" => "prod", "region" => "us" },
+            mkevt! { "region" => "us" },
+            mkevt! { "env" => "dev", "region" => "us" },
+        ];
+        let mut topic = LinearScan::default();
+        let id = topic.subscribe(Counter::default(), filter.clone());
+        for evt in &events {
+            assert_eq!(topic.matching_ids(evt) == vec![id], filter.matches(evt));
+        }
+    }
+
+    #[test]
+    fn accept_stream_delivers_lazily() {
+        type Stream = Box<dyn Iterator<Item = Event>>;
+        fn check<T: Topic<Recorder>>(mut topic: T, accept_stream: fn(&mut T, Stream) -> usize) {
+            let log = Arc::new(Mutex::new(Vec::new()));
+            topic.subscribe(Recorder(100, log.clone()), mkfilter! { "n" => ["0", "2"] });
+            topic.subscribe(Recorder(200, log.clone()), Filter::EMPTY);
+            // Logs each event as it's produced, so the log shows whether it was delivered before
+            // the next one was produced.
+            let produced = log.clone();
+            let evts = (0..3).map(move |n| {
+                produced.lock().unwrap().push(n);
+                mkevt! { "n" => n.to_string() }
+            });
+            assert_eq!(accept_stream(&mut topic, Box::new(evts)), 5);
+            assert_eq!(*log.lock().unwrap(), vec![0, 100, 200, 1, 200, 2, 100, 200]);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::accept_stream)
+        );
+    }
+
+    #[test]
+    fn fanout_counts_deliveries_without_dispatching() {
+        fn check<T: Topic<Counter>>(
+            mut topic: T,
+            fanout: fn(&T, &Event) -> usize,
+            accept: fn(&mut T, &Event) -> usize,
+        ) {
+            let counter = Counter::default();
+            topic.subscribe(counter.clone(), mkfilter! { "env" => ["prod", "dev"] });
+            topic.subscribe(counter.clone(), Filter::EMPTY);
+            // Reachable along two branches, but only one delivery.
+            let both = vec![
+                mkfilter! { "env" => ["prod"] },
+                mkfilter! { "region" => ["us"] },
+            ];
+            topic.subscribe_any(counter.clone(), both.into());
+            let not_us = Filter {
+                exclude: BTreeMap::from([("region".to_owned(), mkset!["us"])]),
+                ..mkfilter! { "env" => ["prod"] }
+            };
+            topic.subscribe(counter.clone(), not_us);
+
+            let evts = [
+                mkevt! { "env" => "prod", "region" => "us" },
+                mkevt! { "env" => "prod", "region" => "eu" },
+                mkevt! { "env" => "dev" },
+                mkevt! { "region" => "us" },
+                Event::builder().build(),
+            ];
+            for evt in evts {
+                let before = counter.0.load(Ordering::SeqCst);
+                let expected = fanout(&topic, &evt);
+                assert_eq!(counter.0.load(Ordering::SeqCst), before, "{:?}", evt);
+                assert_eq!(accept(&mut topic, &evt), expected, "{:?}", evt);
+                let delivered = counter.0.load(Ordering::SeqCst) - before;
+                assert_eq!(delivered as usize, expected, "{:?}", evt);
+            }
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::fanout, T::accept_counted)
+        );
+    }
+
+    #[test]
+    fn required_tag_matrix() {
+        fn check<T: Topic<Counter>>(
+            mut topic: T,
+            matching_ids: fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let valued = topic.subscribe(Counter::default(), mkfilter! { "k" => ["v"] });
+            let mut present = Filter::EMPTY;
+            present.tags.insert("k".to_owned(), BTreeSet::new());
+            let present = topic.subscribe(Counter::default(), present);
+            let cases = [
+                // The event lacks `k`: neither a value nor mere presence is satisfied.
+                (mkevt! { "other" => "v" }, vec![]),
+                // The event has `k` with a listed value.
+                (mkevt! { "k" => "v" }, vec![valued, present]),
+                // The event has `k`, but not with a listed value.
+                (mkevt! { "k" => "w" }, vec![present]),
+            ];
+            for (evt, expected) in cases {
+                assert_eq!(matching_ids(&topic, &evt), expected, "{:?}", evt);
+            }
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::matching_ids)
+        );
+    }
+
+    #[test]
+    fn multi_tag_filters_require_every_tag() {
+        fn check<T: Topic<Counter>>(
+            mut topic: T,
+            matching_ids: fn(&T, &Event) -> Vec<SubscriptionId>,
+        ) {
+            let both = topic.subscribe(
+                Counter::default(),
+                mkfilter! { "env" => ["prod"], "region" => ["us", "eu"] },
+            );
+            let cases = [
+                (mkevt! { "env" => "prod", "region" => "us" }, vec![both]),
+                (
+                    mkevt! { "env" => "prod", "region" => "eu", "x" => "y" },
+                    vec![both],
+                ),
+                // Only one of the two tags is satisfied, by value or by presence.
+                (mkevt! { "env" => "prod" }, vec![]),
+                (mkevt! { "region" => "us" }, vec![]),
+                (mkevt! { "env" => "prod", "region" => "ap" }, vec![]),
+                (mkevt! { "env" => "dev", "region" => "us" }, vec![]),
+                // Neither is.
+                (mkevt! { "env" => "dev", "region" => "ap" }, vec![]),
+                (mkevt! { "x" => "y" }, vec![]),
+            ];
+            for (evt, expected) in cases {
+                assert_eq!(matching_ids(&topic, &evt), expected, "{:?}", evt);
+            }
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::matching_ids)
+        );
+
+        let counter = Counter::default();
+        let mut topic = LinearScan::default();
+        topic.subscribe(
+            counter.clone(),
+            mkfilter! { "a" => ["1"], "b" => ["2"], "c" => ["3"] },
+        );
+        topic.accept(&mkevt! { "a" => "1", "b" => "2" });
+        topic.accept(&mkevt! { "a" => "1", "c" => "3" });
+        topic.accept(&mkevt! { "b" => "2", "c" => "3" });
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+        topic.accept(&mkevt! { "a" => "1", "b" => "2", "c" => "3" });
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn match_modes_over_the_same_filter() {
+        let filter = Filter {
+            absent: mkset!["debug"],
+            matchers: BTreeMap::from([("host".to_owned(), ValueMatch::Prefix("db".to_owned()))]),
+            ..mkfilter! { "env" => ["prod"], "region" => ["us"] }
+        };
+        let mut topic = LinearScan::default();
+        let all = topic.subscribe_with_mode(Counter::default(), filter.clone(), MatchMode::All);
+        let any = topic.subscribe_with_mode(Counter::default(), filter, MatchMode::Any);
+        let cases = [
+            (
+                mkevt! { "env" => "prod", "region" => "us", "host" => "db1" },
+                vec![all, any],
+            ),
+            (mkevt! { "env" => "prod", "debug" => "1" }, vec![any]),
+            (mkevt! { "region" => "us", "debug" => "1" }, vec![any]),
+            (mkevt! { "host" => "db2", "debug" => "1" }, vec![any]),
+            // Satisfies `absent` and nothing else.
+            (mkevt! { "env" => "dev" }, vec![any]),
+            (mkevt! { "env" => "dev", "debug" => "1" }, vec![]),
+        ];
+        for (evt, expected) in cases {
+            assert_eq!(topic.matching_ids(&evt), expected, "{:?}", evt);
+        }
+
+        // Nothing to satisfy in ANY mode, everything in ALL mode.
+        let mut topic = LinearScan::default();
+        let all = topic.subscribe_with_mode(Counter::default(), Filter::EMPTY, MatchMode::All);
+        topic.subscribe_with_mode(Counter::default(), Filter::EMPTY, MatchMode::Any);
+        assert_eq!(topic.matching_ids(&mkevt! { "env" => "prod" }), vec![all]);
+        assert_eq!(MatchMode::default(), MatchMode::All);
+    }
+
+    #[test]
+    fn filter_stops_at_first_failing_constraint() {
+        // Counts how many tags are looked up.
+        struct Counting(Event, std::cell::Cell<usize>);
+        impl Tags for Counting {
+            fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+                self.1.set(self.1.get() + 1);
+                self.0.values(tag)
+            }
+
+            fn keys(&self) -> impl Iterator<Item = &str> {
+                self.0.keys()
+            }
+        }
+
+        let filter = mkfilter! { "a" => ["1"], "b" => ["2"], "c" => ["3"] };
+        let evt = Counting(mkevt! { "b" => "2", "c" => "3" }, Default::default());
+        assert!(!filter.matches(&evt));
+        assert_eq!(evt.1.get(), 1);
+        let evt = Counting(
+            mkevt! { "a" => "1", "b" => "2", "c" => "3" },
+            Default::default(),
+        );
+        assert!(filter.matches(&evt));
+        assert_eq!(evt.1.get(), 3);
+    }
+
+    #[test]
+    fn integer_tags_match_like_strings() {
+        let evt = |tags: &[(u32, u32)]| Event {
+            tags: tags.iter().copied().collect(),
+            data: (),
+        };
+        let filter: Filter<u32, u32> = Filter {
+            tags: BTreeMap::from([(1, BTreeSet::from([10, 11])), (2, BTreeSet::new())]),
+            exclude: BTreeMap::from([(3, BTreeSet::from([30]))]),
+            absent: BTreeSet::from([4]),
+            ..Filter::default()
+        };
+        assert!(filter.matches_event(&evt(&[(1, 10), (2, 0)])));
+        assert!(filter.matches_event(&evt(&[(1, 11), (2, 5), (3, 31)])));
+        // Wrong value, missing presence-only tag, excluded value, forbidden tag.
+        assert!(!filter.matches_event(&evt(&[(1, 12), (2, 0)])));
+        assert!(!filter.matches_event(&evt(&[(1, 10)])));
+        assert!(!filter.matches_event(&evt(&[(1, 10), (2, 0), (3, 30)])));
+        assert!(!filter.matches_event(&evt(&[(1, 10), (2, 0), (4, 0)])));
+
+        assert!(Filter::<u32, u32>::default().matches_event(&evt(&[])));
+        let with_matcher: Filter<u32, u32> = Filter {
+            matchers: BTreeMap::from([(1, ValueMatch::Prefix("1".to_owned()))]),
+            ..Filter::default()
+        };
+        assert!(!with_matcher.matches_event(&evt(&[(1, 10)])));
+    }
+
+    #[test]
+    fn string_filters_match_events_either_way() {
+        let filter = Filter {
+            exclude: BTreeMap::from([("env".to_owned(), mkset!["dev"])]),
+            ..mkfilter! { "region" => ["us"] }
+        };
+        assert_eq!(Filter::default(), Filter::EMPTY);
+        for evt in [
+            mkevt! { "region" => "us" },
+            mkevt! { "region" => "us", "env" => "dev" },
+            mkevt! { "region" => "eu" },
+        ] {
+            assert_eq!(filter.matches_event(&evt), filter.matches(&evt));
+        }
+    }
+
+    #[test]
+    fn tree_scanner_subscribe_once_prunes_nodes() {
+        let mut topic = TreeScanner::default();
+        topic.subscribe_once(Counter::default(), mkfilter! { "a" => ["1"], "b" => ["2"] });
+        assert_eq!(topic.node_count(), 3);
+        topic.accept(&mkevt! {"a" => "1", "b" => "2"});
+        assert!(topic.is_empty());
+        assert_eq!(topic.node_count(), 1);
+    }
+
+    // Logs its own index, to check which listeners an event reached and in what order.
+    struct Recorder(usize, Arc<Mutex<Vec<usize>>>);
+    impl Listener for Recorder {
+        fn accept(&mut self, _evt: &Event) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[derive(Clone, Debug, Default)]
+    struct Counter(Arc<AtomicU32>);
+    impl<E> Listener<E> for Counter {
+        fn accept(&mut self, _evt: &E) {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+// Topics are as thread-safe as their listeners, so that they can be moved to other threads and
+// shared behind an `Arc`.
+#[cfg(test)]
+mod send_sync {
+    use std::{cell::Cell, rc::Rc};
+
+    use static_assertions::{assert_impl_all, assert_not_impl_any};
+
+    use super::*;
+
+    // Send and Sync.
+    type Shared = Box<dyn Listener + Send + Sync>;
+    // Send but not Sync.
+    type Unshared = Cell<u32>;
+    // Neither.
+    type Local = Rc<()>;
+
+    assert_impl_all!(LinearScan<Shared>: Send, Sync);
+    assert_impl_all!(TreeScanner<Shared>: Send, Sync);
+    assert_impl_all!(InvertedIndex<Shared>: Send, Sync);
+    assert_impl_all!(BitsetScanner<Shared>: Send, Sync);
+    assert_impl_all!(LinearScan<Unshared>: Send);
+    assert_impl_all!(TreeScanner<Unshared>: Send);
+    assert_impl_all!(InvertedIndex<Unshared>: Send);
+    assert_impl_all!(BitsetScanner<Unshared>: Send);
+    assert_not_impl_any!(LinearScan<Unshared>: Sync);
+    assert_not_impl_any!(TreeScanner<Unshared>: Sync);
+    assert_not_impl_any!(InvertedIndex<Unshared>: Sync);
+    assert_not_impl_any!(BitsetScanner<Unshared>: Sync);
+    assert_not_impl_any!(LinearScan<Local>: Send, Sync);
+    assert_not_impl_any!(TreeScanner<Local>: Send, Sync);
+    assert_not_impl_any!(InvertedIndex<Local>: Send, Sync);
+    assert_not_impl_any!(BitsetScanner<Local>: Send, Sync);
+    #[cfg(feature = "std")]
+    assert_impl_all!(SyncTopic<TreeScanner<Unshared>>: Send, Sync);
 }
@@ -0,0 +1,622 @@
+use alloc::{boxed::Box, vec::Vec};
+use core::{any::Any, marker::PhantomData, ops::ControlFlow};
+#[cfg(feature = "std")]
+use std::{
+    collections::HashSet,
+    hash::Hash,
+    sync::{mpsc::Sender, Arc, Mutex, Weak},
+};
+
+use crate::{ConsumingListener, Event, Listener};
+
+/// Adapts a closure into a [`Listener`], so small handlers don't need a dedicated type.
+pub struct FnListener<F, E = Event> {
+    f: F,
+    _evt: PhantomData<fn(&E)>,
+}
+impl<E, F: FnMut(&E)> FnListener<F, E> {
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            _evt: PhantomData,
+        }
+    }
+}
+impl<E, F: FnMut(&E)> Listener<E> for FnListener<F, E> {
+    fn accept(&mut self, evt: &E) {
+        (self.f)(evt);
+    }
+}
+
+/// Adapts a closure into a [`Listener`] that can stop propagation: returning `ControlFlow::Break`
+/// from the closure stops the event from reaching any later listeners.
+pub struct StoppableFnListener<F, E = Event> {
+    f: F,
+    _evt: PhantomData<fn(&E)>,
+}
+impl<E, F: FnMut(&E) -> ControlFlow<()>> StoppableFnListener<F, E> {
+    pub fn new(f: F) -> Self {
+        Self {
+            f,
+            _evt: PhantomData,
+        }
+    }
+}
+impl<E, F: FnMut(&E) -> ControlFlow<()>> Listener<E> for StoppableFnListener<F, E> {
+    fn accept(&mut self, evt: &E) {
+        let _ = (self.f)(evt);
+    }
+
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        (self.f)(evt)
+    }
+}
+
+/// Forwards every event it receives into a channel, so they can be consumed on another thread.
+///
+/// Listeners only borrow the event, so each delivery clones it, payload included. Events sent
+/// after the receiver has been dropped are silently discarded.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct ChannelListener<E = Event> {
+    tx: Sender<E>,
+}
+#[cfg(feature = "std")]
+impl<E> ChannelListener<E> {
+    pub fn new(tx: Sender<E>) -> Self {
+        Self { tx }
+    }
+}
+#[cfg(feature = "std")]
+impl<E: Clone> Listener<E> for ChannelListener<E> {
+    fn accept(&mut self, evt: &E) {
+        let _ = self.tx.send(evt.clone());
+    }
+}
+#[cfg(feature = "std")]
+impl<E: Clone> ConsumingListener<E> for ChannelListener<E> {
+    fn accept_owned(&mut self, evt: E) {
+        let _ = self.tx.send(evt);
+    }
+}
+
+/// Like [`ChannelListener`], but forwards into a `crossbeam_channel` channel, e.g. one that a
+/// consumer `select!`s on alongside others.
+///
+/// Once a send fails because every receiver has been dropped, the listener [expires], so its
+/// topic unsubscribes it rather than cloning events for nobody.
+///
+/// [expires]: Listener::is_expired
+#[cfg(feature = "crossbeam")]
+#[derive(Debug)]
+pub struct CrossbeamListener<E = Event> {
+    tx: crossbeam_channel::Sender<E>,
+    disconnected: bool,
+}
+#[cfg(feature = "crossbeam")]
+impl<E> CrossbeamListener<E> {
+    pub fn new(tx: crossbeam_channel::Sender<E>) -> Self {
+        Self {
+            tx,
+            disconnected: false,
+        }
+    }
+
+    fn send(&mut self, evt: E) {
+        if !self.disconnected {
+            self.disconnected = self.tx.send(evt).is_err();
+        }
+    }
+}
+#[cfg(feature = "crossbeam")]
+impl<E: Clone> Listener<E> for CrossbeamListener<E> {
+    fn accept(&mut self, evt: &E) {
+        self.send(evt.clone());
+    }
+
+    fn is_expired(&self) -> bool {
+        self.disconnected
+    }
+}
+#[cfg(feature = "crossbeam")]
+impl<E: Clone> ConsumingListener<E> for CrossbeamListener<E> {
+    fn accept_owned(&mut self, evt: E) {
+        self.send(evt);
+    }
+}
+
+/// Forwards each distinct event to `inner` only the first time it is delivered, e.g. to drop
+/// duplicates from an upstream that retries.
+///
+/// Events are compared by their tags and payload. Every distinct event is remembered for as long
+/// as the listener lives, so this is best suited to bounded streams.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct DedupListener<L, E = Event> {
+    inner: L,
+    seen: HashSet<E>,
+}
+#[cfg(feature = "std")]
+impl<L, E> DedupListener<L, E> {
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            seen: HashSet::new(),
+        }
+    }
+
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+#[cfg(feature = "std")]
+impl<E: Clone + Eq + Hash, L: Listener<E>> Listener<E> for DedupListener<L, E> {
+    fn accept(&mut self, evt: &E) {
+        let _ = self.accept_flow(evt);
+    }
+
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        if self.seen.contains(evt) {
+            return ControlFlow::Continue(());
+        }
+        self.seen.insert(evt.clone());
+        self.inner.accept_flow(evt)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.inner.is_expired()
+    }
+
+    fn listener_key(&self) -> Option<u64> {
+        self.inner.listener_key()
+    }
+}
+
+/// Delivers to a listener that is owned elsewhere, without keeping it alive: once every `Arc` to
+/// it has been dropped, this listener [expires] and its topic unsubscribes it. Subscribe one with
+/// [`Topic::subscribe_weak`].
+///
+/// Each delivery locks the listener's mutex. A weak listener whose mutex was poisoned by a panic
+/// elsewhere still delivers to it.
+///
+/// [expires]: Listener::is_expired
+/// [`Topic::subscribe_weak`]: crate::Topic::subscribe_weak
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct WeakListener<L> {
+    inner: Weak<Mutex<L>>,
+}
+#[cfg(feature = "std")]
+impl<L> WeakListener<L> {
+    pub fn new(listener: &Arc<Mutex<L>>) -> Self {
+        Self {
+            inner: Arc::downgrade(listener),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl<L> Clone for WeakListener<L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl<E, L: Listener<E>> Listener<E> for WeakListener<L> {
+    fn accept(&mut self, evt: &E) {
+        let _ = self.accept_flow(evt);
+    }
+
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        match self.inner.upgrade() {
+            Some(listener) => listener
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .accept_flow(evt),
+            None => ControlFlow::Continue(()),
+        }
+    }
+
+    fn is_expired(&self) -> bool {
+        self.inner.strong_count() == 0
+    }
+}
+
+/// Keeps a copy of every event it receives, in the order it received them, e.g. to assert on
+/// what a topic delivered in a test.
+///
+/// Read the history back through the topic's `get`, or recover a boxed recorder with
+/// [`AnyListener`].
+///
+/// ```
+/// use tagsub::{Event, Filter, LinearScan, Listener, RecordingListener, Topic};
+///
+/// let mut topic = LinearScan::default();
+/// let id = topic.subscribe(RecordingListener::new(), Filter::single("env", "prod"));
+/// topic.accept(&Event::single("env", "prod"));
+/// topic.accept(&Event::single("env", "dev"));
+/// assert_eq!(topic.get(id).unwrap().events(), [Event::single("env", "prod")]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingListener<E = Event> {
+    events: Vec<E>,
+}
+impl<E> Default for RecordingListener<E> {
+    fn default() -> Self {
+        Self { events: Vec::new() }
+    }
+}
+impl<E> RecordingListener<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every event received so far, oldest first.
+    pub fn events(&self) -> &[E] {
+        &self.events
+    }
+
+    pub fn into_events(self) -> Vec<E> {
+        self.events
+    }
+
+    /// Forgets the events received so far.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+impl<E: Clone> Listener<E> for RecordingListener<E> {
+    fn accept(&mut self, evt: &E) {
+        self.events.push(evt.clone());
+    }
+}
+impl<E: Clone> ConsumingListener<E> for RecordingListener<E> {
+    fn accept_owned(&mut self, evt: E) {
+        self.events.push(evt);
+    }
+}
+
+/// A type-erased listener, for topics whose subscribers aren't all the same type.
+pub type BoxListener<E = Event> = Box<dyn Listener<E>>;
+
+impl<E, L: Listener<E> + ?Sized> Listener<E> for Box<L> {
+    fn accept(&mut self, evt: &E) {
+        (**self).accept(evt);
+    }
+
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        (**self).accept_flow(evt)
+    }
+
+    fn is_expired(&self) -> bool {
+        (**self).is_expired()
+    }
+
+    fn listener_key(&self) -> Option<u64> {
+        (**self).listener_key()
+    }
+}
+
+impl<E, L: ConsumingListener<E> + ?Sized> ConsumingListener<E> for Box<L> {
+    fn accept_owned(&mut self, evt: E) {
+        (**self).accept_owned(evt);
+    }
+}
+
+/// A listener that can be recovered as its concrete type, e.g. to read the state it accumulated
+/// while boxed as an [`AnyBoxListener`]. Implemented for every listener that doesn't borrow.
+///
+/// Downcast with `downcast_ref` rather than calling `as_any` on a box directly, which would hand
+/// back the box itself.
+pub trait AnyListener<E = Event>: Listener<E> + Any {
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+impl<E, L: Listener<E> + Any> AnyListener<E> for L {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+impl<E: 'static> dyn AnyListener<E> {
+    /// The listener as a `T`, if that's what it is.
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+}
+
+/// Like [`BoxListener`], but the listener can be downcast back to its concrete type.
+pub type AnyBoxListener<E = Event> = Box<dyn AnyListener<E>>;
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    #[cfg(feature = "std")]
+    use std::{
+        rc::Rc,
+        sync::{mpsc, Arc, Mutex},
+        thread,
+    };
+
+    use super::*;
+    use crate::{Filter, InvertedIndex, LinearScan, SubscriptionId, Topic, TreeScanner};
+
+    fn hello_world() -> (Event, Filter) {
+        (
+            Event::single("hello", "world"),
+            Filter::single("hello", "world"),
+        )
+    }
+
+    #[test]
+    fn fn_listener_linear_scan() {
+        let (evt, filter) = hello_world();
+        let count = Cell::new(0);
+        let mut topic = LinearScan::default();
+        topic.subscribe(FnListener::new(|_| count.set(count.get() + 1)), filter);
+        topic.accept(&evt);
+        topic.accept(&evt);
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    fn fn_listener_tree_scanner() {
+        let (evt, filter) = hello_world();
+        let mut seen = Vec::new();
+        let mut topic = TreeScanner::default();
+        topic.subscribe(
+            FnListener::new(|e: &Event| seen.push(e["hello"].to_owned())),
+            filter,
+        );
+        topic.accept(&evt);
+        drop(topic);
+        assert_eq!(seen, vec!["world".to_owned()]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn channel_listener_forwards_events() {
+        let (evt, filter) = hello_world();
+        let (tx, rx) = mpsc::channel();
+        let mut topic = LinearScan::default();
+        topic.subscribe(ChannelListener::new(tx), filter);
+
+        let consumer = thread::spawn(move || rx.recv().unwrap());
+        topic.accept(&evt);
+        assert_eq!(consumer.join().unwrap(), evt);
+
+        // Nobody is listening any more, which must not be an error.
+        topic.accept(&evt);
+    }
+
+    #[test]
+    #[cfg(feature = "crossbeam")]
+    fn crossbeam_listener_forwards_events() {
+        let (evt, filter) = hello_world();
+        let (tx, rx) = crossbeam_channel::unbounded();
+        let mut topic = TreeScanner::default();
+        topic.subscribe(CrossbeamListener::new(tx), filter);
+
+        let consumer = thread::spawn(move || rx.recv().unwrap());
+        assert_eq!(topic.accept_counted(&evt), 1);
+        assert_eq!(consumer.join().unwrap(), evt);
+
+        // The failed send expires the listener, and the next dispatch drops it.
+        assert_eq!(topic.accept_counted(&evt), 1);
+        assert_eq!(topic.len(), 1);
+        assert_eq!(topic.accept_counted(&evt), 0);
+        assert!(topic.is_empty());
+    }
+
+    #[cfg(feature = "std")]
+    struct Counter(Rc<Cell<u32>>);
+    #[cfg(feature = "std")]
+    impl Listener for Counter {
+        fn accept(&mut self, _evt: &Event) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn mixed_listeners<T: Topic<BoxListener> + Listener>(mut topic: T) {
+        let (evt, filter) = hello_world();
+        let count = Rc::new(Cell::new(0));
+        let (tx, rx) = mpsc::channel();
+        topic.subscribe(Box::new(Counter(count.clone())), filter.clone());
+        topic.subscribe(Box::new(ChannelListener::new(tx)), filter);
+        topic.accept(&evt);
+        assert_eq!(count.get(), 1);
+        assert_eq!(rx.try_recv().unwrap(), evt);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn dedup_listener_forwards_once() {
+        let (evt, filter) = hello_world();
+        let count = Rc::new(Cell::new(0));
+        let mut topic = LinearScan::default();
+        topic.subscribe(DedupListener::new(Counter(count.clone())), filter);
+        topic.accept(&evt);
+        topic.accept(&evt);
+        assert_eq!(count.get(), 1);
+        topic.accept(&Event::builder().tag("hello", "world").tag("n", "2").build());
+        assert_eq!(count.get(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn accept_owned_moves_into_the_last_listener() {
+        // Remembers whether it was given its own copy of the event.
+        #[derive(Default)]
+        struct Owns(Rc<Cell<u32>>, Rc<Cell<u32>>);
+        impl Listener for Owns {
+            fn accept(&mut self, _evt: &Event) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+        impl ConsumingListener for Owns {
+            fn accept_owned(&mut self, _evt: Event) {
+                self.1.set(self.1.get() + 1);
+            }
+        }
+
+        fn check<T: Topic<Owns>>(mut topic: T, accept_owned: impl Fn(&mut T, Event) -> usize) {
+            let (evt, filter) = hello_world();
+            let borrowed = Rc::new(Cell::new(0));
+            let owned = Rc::new(Cell::new(0));
+            for _ in 0..3 {
+                topic.subscribe(Owns(borrowed.clone(), owned.clone()), filter.clone());
+            }
+            topic.subscribe(Owns::default(), Filter::single("hello", "there"));
+            assert_eq!(accept_owned(&mut topic, evt), 3);
+            assert_eq!((borrowed.get(), owned.get()), (2, 1));
+            assert_eq!(accept_owned(&mut topic, Event::single("bye", "now")), 0);
+        }
+        for_each_backend!(T in [LinearScan, TreeScanner] => check(T::default(), T::accept_owned));
+
+        let (evt, filter) = hello_world();
+        let (tx, rx) = mpsc::channel();
+        let mut topic = LinearScan::default();
+        topic.subscribe(ChannelListener::new(tx), filter);
+        topic.accept_owned(evt.clone());
+        assert_eq!(rx.try_recv().unwrap(), evt);
+    }
+
+    #[test]
+    fn downcast_boxed_listeners() {
+        #[derive(Debug, PartialEq)]
+        struct Tally(u32);
+        impl Listener for Tally {
+            fn accept(&mut self, _evt: &Event) {
+                self.0 += 1;
+            }
+        }
+        struct Noop;
+        impl Listener for Noop {
+            fn accept(&mut self, _evt: &Event) {}
+        }
+
+        fn check<T: Topic<AnyBoxListener> + Listener>(
+            mut topic: T,
+            get: impl Fn(&T, SubscriptionId) -> Option<&AnyBoxListener>,
+            get_mut: impl Fn(&mut T, SubscriptionId) -> Option<&mut AnyBoxListener>,
+        ) {
+            let (evt, filter) = hello_world();
+            let tally = topic.subscribe(Box::new(Tally(0)), filter.clone());
+            let noop = topic.subscribe(Box::new(Noop), filter);
+            topic.accept(&evt);
+            topic.accept(&evt);
+
+            assert_eq!(get(&topic, tally).unwrap().downcast_ref(), Some(&Tally(2)));
+            assert!(get(&topic, noop).unwrap().downcast_ref::<Tally>().is_none());
+            get_mut(&mut topic, tally)
+                .unwrap()
+                .downcast_mut::<Tally>()
+                .unwrap()
+                .0 = 10;
+            topic.accept(&evt);
+            assert_eq!(get(&topic, tally).unwrap().downcast_ref(), Some(&Tally(11)));
+
+            topic.unsubscribe(tally);
+            assert!(get(&topic, tally).is_none());
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::get, T::get_mut)
+        );
+    }
+
+    #[test]
+    fn recording_listener_keeps_deliveries_in_order() {
+        fn check<T: Topic<RecordingListener<Event<u32>>> + Listener<Event<u32>>>(
+            mut topic: T,
+            get: impl Fn(&T, SubscriptionId) -> Option<&RecordingListener<Event<u32>>>,
+        ) {
+            let prod = topic.subscribe(RecordingListener::new(), Filter::single("env", "prod"));
+            let all = topic.subscribe(RecordingListener::new(), Filter::EMPTY);
+            let evts: Vec<Event<u32>> = (0..4)
+                .map(|n| {
+                    let env = if n % 2 == 0 { "prod" } else { "dev" };
+                    Event::builder().tag("env", env).data(n).build()
+                })
+                .collect();
+            for evt in &evts {
+                topic.accept(evt);
+            }
+            let prod_evts: Vec<_> = evts.iter().step_by(2).cloned().collect();
+            assert_eq!(get(&topic, prod).unwrap().events(), prod_evts);
+            assert_eq!(get(&topic, all).unwrap().events(), evts);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::get)
+        );
+
+        let mut recorder = RecordingListener::new();
+        recorder.accept_owned(Event::single("n", "1"));
+        recorder.clear();
+        recorder.accept(&Event::single("n", "2"));
+        assert_eq!(recorder.into_events(), vec![Event::single("n", "2")]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn weak_listeners_expire_with_their_listener() {
+        type Weak = WeakListener<RecordingListener>;
+        fn check<T: Topic<Weak>>(
+            mut topic: T,
+            accept: fn(&mut T, &Event) -> usize,
+            len: fn(&T) -> usize,
+        ) {
+            let (evt, filter) = hello_world();
+            let kept = Arc::new(Mutex::new(RecordingListener::new()));
+            let dropped = Arc::new(Mutex::new(RecordingListener::new()));
+            topic.subscribe_weak(&kept, filter.clone());
+            topic.subscribe_weak(&dropped, filter);
+            assert_eq!(accept(&mut topic, &evt), 2);
+
+            let weak = WeakListener::new(&dropped);
+            drop(dropped);
+            assert!(weak.is_expired());
+            // Dropped listeners stay subscribed until an event matches them.
+            assert_eq!(accept(&mut topic, &Event::single("other", "tag")), 0);
+            assert_eq!(len(&topic), 2);
+            assert_eq!(accept(&mut topic, &evt), 1);
+            assert_eq!(len(&topic), 1);
+            assert_eq!(accept(&mut topic, &evt), 1);
+            assert_eq!(kept.lock().unwrap().events().len(), 3);
+        }
+        for_each_backend!(
+            T in [LinearScan, TreeScanner, InvertedIndex] =>
+            check(T::default(), T::accept_counted, T::len)
+        );
+
+        // Boxed and wrapped listeners expire along with what they wrap.
+        let listener = Arc::new(Mutex::new(RecordingListener::new()));
+        let boxed: BoxListener = Box::new(DedupListener::new(WeakListener::new(&listener)));
+        assert!(!boxed.is_expired());
+        drop(listener);
+        assert!(boxed.is_expired());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn box_listener_linear_scan() {
+        mixed_listeners(LinearScan::default());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn box_listener_tree_scanner() {
+        mixed_listeners(TreeScanner::default());
+    }
+}
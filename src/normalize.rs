@@ -0,0 +1,100 @@
+use alloc::{collections::BTreeMap, string::String, vec::Vec};
+
+use crate::{Expr, Filter, Predicate, Tags};
+
+// An event's tags as they are matched against subscriptions: either as they are, or with every
+// value passed through the topic's normalizer. Listeners are still handed the original event.
+pub(crate) enum TagView<'a, E> {
+    Raw(&'a E),
+    Normalized(BTreeMap<&'a str, Vec<String>>),
+}
+impl<'a, E: Tags> TagView<'a, E> {
+    pub(crate) fn new(normalize: Option<fn(&str) -> String>, evt: &'a E) -> Self {
+        let Some(normalize) = normalize else {
+            return TagView::Raw(evt);
+        };
+        let tags = evt
+            .keys()
+            .map(|tag| (tag, evt.values(tag).map(normalize).collect()))
+            .collect();
+        TagView::Normalized(tags)
+    }
+}
+impl<E: Tags> Tags for TagView<'_, E> {
+    fn values(&self, tag: &str) -> impl Iterator<Item = &str> {
+        match self {
+            TagView::Raw(evt) => Either::Left(evt.values(tag)),
+            TagView::Normalized(tags) => {
+                Either::Right(tags.get(tag).into_iter().flatten().map(String::as_str))
+            }
+        }
+    }
+
+    fn has_tag(&self, tag: &str) -> bool {
+        match self {
+            TagView::Raw(evt) => evt.has_tag(tag),
+            TagView::Normalized(tags) => tags.contains_key(tag),
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        match self {
+            TagView::Raw(evt) => Either::Left(evt.keys()),
+            TagView::Normalized(tags) => Either::Right(tags.keys().copied()),
+        }
+    }
+}
+
+// Lets `TagView` return either of two iterator types.
+enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+impl<T, A: Iterator<Item = T>, B: Iterator<Item = T>> Iterator for Either<A, B> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self {
+            Either::Left(a) => a.next(),
+            Either::Right(b) => b.next(),
+        }
+    }
+}
+
+// A copy of `predicate` with the values it compares event values with normalized, so that they're
+// in the same form as the events' values will be. Matchers are left alone.
+pub(crate) fn normalized(normalize: fn(&str) -> String, predicate: &Predicate) -> Predicate {
+    let mut predicate = predicate.clone();
+    normalize_predicate(normalize, &mut predicate);
+    predicate
+}
+
+fn normalize_predicate(normalize: fn(&str) -> String, predicate: &mut Predicate) {
+    match predicate {
+        Predicate::Filter(filter) => normalize_filter(normalize, filter),
+        Predicate::Any(filters) => {
+            for filter in &mut filters.filters {
+                normalize_filter(normalize, filter);
+            }
+        }
+        Predicate::Expr(expr) => normalize_expr(normalize, expr),
+    }
+}
+
+fn normalize_filter(normalize: fn(&str) -> String, filter: &mut Filter) {
+    for values in filter.tags.values_mut().chain(filter.exclude.values_mut()) {
+        *values = values.iter().map(|v| normalize(v)).collect();
+    }
+}
+
+fn normalize_expr(normalize: fn(&str) -> String, expr: &mut Expr) {
+    match expr {
+        Expr::And(exprs) | Expr::Or(exprs) => {
+            for expr in exprs {
+                normalize_expr(normalize, expr);
+            }
+        }
+        Expr::Not(expr) => normalize_expr(normalize, expr),
+        Expr::Tag { values, .. } => *values = values.iter().map(|v| normalize(v)).collect(),
+    }
+}
@@ -0,0 +1,24 @@
+//! The types and traits almost every user of the crate needs, for a single glob import.
+//!
+//! ```
+//! use tagsub::prelude::*;
+//!
+//! struct Print;
+//! impl Listener for Print {
+//!     fn accept(&mut self, evt: &Event) {
+//!         println!("{:?}", evt.tags);
+//!     }
+//! }
+//!
+//! let mut topic = TreeScanner::default();
+//! topic.subscribe(Print, Filter::builder().eq("env", "prod").build());
+//! assert_eq!(topic.accept_counted(&Event::builder().tag("env", "prod").build()), 1);
+//! ```
+
+pub use crate::{
+    BoxListener, ConsumingListener, Event, EventBuilder, Filter, FilterBuilder, FilterSet,
+    FnListener, InvertedIndex, LinearScan, Listener, MatchingIds, SubscriptionId, Tags, Topic,
+    TreeScanner, TryListener, ValueMatch,
+};
+#[cfg(feature = "std")]
+pub use crate::{ChannelListener, SyncTopic};
@@ -0,0 +1,263 @@
+//! A small query language for building filters, e.g. `env=prod AND region in (us, eu)`.
+//!
+//! A query is one or more clauses joined by `AND`. Each clause constrains a single tag, either to
+//! one value (`tag=value`) or to any of a comma-separated set (`tag in (a, b)`). Keywords are
+//! case-insensitive. Tags and values are bare words: anything other than whitespace and the
+//! characters `=`, `(`, `)` and `,`.
+
+use alloc::{
+    borrow::ToOwned,
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+};
+use core::{fmt, str::FromStr};
+
+use crate::Filter;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterParseError {
+    /// Byte offset into the query where the problem was found.
+    pub position: usize,
+    pub kind: FilterParseErrorKind,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterParseErrorKind {
+    /// The query ended while more input was expected.
+    UnexpectedEnd { expected: &'static str },
+    /// Something other than what the grammar allows at this point.
+    UnexpectedToken {
+        expected: &'static str,
+        found: String,
+    },
+    /// The same tag was constrained by more than one clause.
+    DuplicateTag(String),
+}
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            FilterParseErrorKind::UnexpectedEnd { expected } => {
+                write!(f, "expected {} at end of query", expected)
+            }
+            FilterParseErrorKind::UnexpectedToken { expected, found } => write!(
+                f,
+                "expected {} but found `{}` at position {}",
+                expected, found, self.position
+            ),
+            FilterParseErrorKind::DuplicateTag(tag) => write!(
+                f,
+                "tag `{}` is constrained more than once at position {}",
+                tag, self.position
+            ),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for FilterParseError {}
+
+impl Filter {
+    /// Parses a filter from the query language described in the [module docs](self).
+    pub fn parse(query: &str) -> Result<Filter, FilterParseError> {
+        Parser::new(query).parse()
+    }
+}
+impl FromStr for Filter {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Filter::parse(s)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Word(&'a str),
+    Eq,
+    LParen,
+    RParen,
+    Comma,
+}
+impl Token<'_> {
+    fn text(&self) -> &str {
+        match self {
+            Token::Word(w) => w,
+            Token::Eq => "=",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::Comma => ",",
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    pos: usize,
+}
+impl<'a> Parser<'a> {
+    fn new(query: &'a str) -> Self {
+        Self { query, pos: 0 }
+    }
+
+    // Returns the next token along with the position it starts at, without consuming it.
+    fn peek(&self) -> Option<(usize, Token<'a>)> {
+        let rest = &self.query[self.pos..];
+        let start = self.pos + (rest.len() - rest.trim_start().len());
+        let rest = &self.query[start..];
+        let token = match rest.chars().next()? {
+            '=' => Token::Eq,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            _ => {
+                let end = rest
+                    .find(|c: char| c.is_whitespace() || "=(),".contains(c))
+                    .unwrap_or(rest.len());
+                Token::Word(&rest[..end])
+            }
+        };
+        Some((start, token))
+    }
+
+    fn next(&mut self, expected: &'static str) -> Result<(usize, Token<'a>), FilterParseError> {
+        let Some((start, token)) = self.peek() else {
+            return Err(FilterParseError {
+                position: self.query.len(),
+                kind: FilterParseErrorKind::UnexpectedEnd { expected },
+            });
+        };
+        self.pos = start + token.text().len();
+        Ok((start, token))
+    }
+
+    fn word(&mut self, expected: &'static str) -> Result<(usize, &'a str), FilterParseError> {
+        match self.next(expected)? {
+            (start, Token::Word(w)) => Ok((start, w)),
+            (start, token) => Err(unexpected(start, expected, token)),
+        }
+    }
+
+    fn parse(mut self) -> Result<Filter, FilterParseError> {
+        let mut tags = BTreeMap::new();
+        loop {
+            let (start, tag) = self.word("a tag")?;
+            let values = match self.next("`=` or `in`")? {
+                (_, Token::Eq) => BTreeSet::from([self.word("a value")?.1.to_owned()]),
+                (_, token) if token.is_keyword("in") => self.value_set()?,
+                (pos, token) => return Err(unexpected(pos, "`=` or `in`", token)),
+            };
+            if tags.insert(tag.to_owned(), values).is_some() {
+                return Err(FilterParseError {
+                    position: start,
+                    kind: FilterParseErrorKind::DuplicateTag(tag.to_owned()),
+                });
+            }
+            match self.peek() {
+                None => break,
+                Some((_, token)) if token.is_keyword("and") => {
+                    self.next("`AND`")?;
+                }
+                Some((pos, token)) => return Err(unexpected(pos, "`AND`", token)),
+            }
+        }
+        Ok(Filter {
+            tags,
+            ..Filter::EMPTY
+        })
+    }
+
+    // Parses `(a, b, ...)`, with at least one value.
+    fn value_set(&mut self) -> Result<BTreeSet<String>, FilterParseError> {
+        match self.next("`(`")? {
+            (_, Token::LParen) => {}
+            (pos, token) => return Err(unexpected(pos, "`(`", token)),
+        }
+        let mut values = BTreeSet::new();
+        loop {
+            values.insert(self.word("a value")?.1.to_owned());
+            match self.next("`,` or `)`")? {
+                (_, Token::Comma) => continue,
+                (_, Token::RParen) => return Ok(values),
+                (pos, token) => return Err(unexpected(pos, "`,` or `)`", token)),
+            }
+        }
+    }
+}
+
+fn unexpected(position: usize, expected: &'static str, found: Token) -> FilterParseError {
+    FilterParseError {
+        position,
+        kind: FilterParseErrorKind::UnexpectedToken {
+            expected,
+            found: found.text().to_owned(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_equality() {
+        assert_eq!(
+            Filter::parse("env=prod").unwrap(),
+            mkfilter! { "env" => ["prod"] }
+        );
+        assert_eq!(
+            Filter::parse("  env = prod  ").unwrap(),
+            mkfilter! { "env" => ["prod"] }
+        );
+    }
+
+    #[test]
+    fn parse_set_membership() {
+        let expected = mkfilter! { "env" => ["prod"], "region" => ["eu", "us"] };
+        assert_eq!(
+            Filter::parse("env=prod AND region in (us,eu)").unwrap(),
+            expected
+        );
+        assert_eq!(
+            Filter::parse("region IN ( us , eu ) and env=prod").unwrap(),
+            expected
+        );
+        assert_eq!(
+            "env=prod AND region in (us,eu)".parse::<Filter>().unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn parse_malformed() {
+        assert_eq!(
+            Filter::parse("env=prod region=us").err().unwrap(),
+            FilterParseError {
+                position: 9,
+                kind: FilterParseErrorKind::UnexpectedToken {
+                    expected: "`AND`",
+                    found: "region".to_owned(),
+                },
+            }
+        );
+        assert_eq!(
+            Filter::parse("region in (us,").err().unwrap(),
+            FilterParseError {
+                position: 14,
+                kind: FilterParseErrorKind::UnexpectedEnd {
+                    expected: "a value"
+                },
+            }
+        );
+        assert_eq!(Filter::parse("region in ()").err().unwrap().position, 11);
+        assert_eq!(
+            Filter::parse("env=prod AND env=dev").err().unwrap(),
+            FilterParseError {
+                position: 13,
+                kind: FilterParseErrorKind::DuplicateTag("env".to_owned()),
+            }
+        );
+        assert_eq!(Filter::parse("").err().unwrap().position, 0);
+    }
+}
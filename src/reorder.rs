@@ -0,0 +1,165 @@
+use alloc::{collections::BTreeMap, string::String};
+use core::time::Duration;
+
+use crate::{Event, Filter, FilterSet, Listener, SubscriptionId, Topic};
+
+/// Wraps a topic so that events reach it in timestamp order, even if they arrive slightly out
+/// of order.
+///
+/// Each event's timestamp is read from one of its tags, as a whole number of milliseconds, e.g.
+/// since the Unix epoch. Rather than being delivered right away, an event is held back until an
+/// event at least `window` newer has arrived, since anything older than that is assumed to have
+/// arrived already. This adds up to `window` of latency to every delivery, and more when events
+/// are sparse; [`ReorderingTopic::flush`] delivers everything still held back.
+///
+/// An event that arrives more than `window` late, after newer events have been delivered, is
+/// delivered right away rather than dropped. So is an event without a timestamp, or whose
+/// timestamp doesn't parse. Events with the same timestamp are delivered in the order they
+/// arrived.
+#[derive(Clone, Debug)]
+pub struct ReorderingTopic<T, D = ()> {
+    topic: T,
+    tag: String,
+    window: u64,
+    // Held-back events by timestamp, then by arrival.
+    pending: BTreeMap<(u64, u64), Event<D>>,
+    arrivals: u64,
+    // The newest timestamp seen so far.
+    newest: Option<u64>,
+}
+impl<T, D> ReorderingTopic<T, D> {
+    /// Reorders events by the value of `tag`, holding each back for up to `window`.
+    pub fn new(topic: T, tag: impl Into<String>, window: Duration) -> Self {
+        Self {
+            topic,
+            tag: tag.into(),
+            window: u64::try_from(window.as_millis()).unwrap_or(u64::MAX),
+            pending: BTreeMap::new(),
+            arrivals: 0,
+            newest: None,
+        }
+    }
+
+    pub fn topic(&self) -> &T {
+        &self.topic
+    }
+
+    pub fn topic_mut(&mut self) -> &mut T {
+        &mut self.topic
+    }
+
+    /// Unwraps the topic. Events still held back are dropped; call
+    /// [`ReorderingTopic::flush`] first to deliver them.
+    pub fn into_inner(self) -> T {
+        self.topic
+    }
+
+    /// The number of events held back.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+impl<T: Listener<Event<D>>, D> ReorderingTopic<T, D> {
+    /// Holds `evt` back until its turn, then delivers every held-back event that is now more
+    /// than `window` older than the newest one, oldest first.
+    pub fn accept(&mut self, evt: Event<D>) {
+        let Some(ts) = evt.get(&self.tag).and_then(|v| v.parse::<u64>().ok()) else {
+            self.topic.accept(&evt);
+            return;
+        };
+        self.pending.insert((ts, self.arrivals), evt);
+        self.arrivals += 1;
+        let newest = self.newest.map_or(ts, |n| n.max(ts));
+        self.newest = Some(newest);
+        let cutoff = newest.saturating_sub(self.window);
+        while let Some(entry) = self.pending.first_entry() {
+            if entry.key().0 > cutoff {
+                break;
+            }
+            self.topic.accept(&entry.remove());
+        }
+    }
+
+    /// Delivers every held-back event, oldest first.
+    pub fn flush(&mut self) {
+        while let Some((_, evt)) = self.pending.pop_first() {
+            self.topic.accept(&evt);
+        }
+    }
+}
+impl<L, T: Topic<L>, D> Topic<L> for ReorderingTopic<T, D> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.topic.subscribe(listener, filter)
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.topic.subscribe_any(listener, filters)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.topic.subscribe_limited(listener, filter, max)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.topic.unsubscribe(id)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{LinearScan, TreeScanner};
+
+    #[derive(Clone, Default)]
+    struct Log(Arc<Mutex<Vec<String>>>);
+    impl Listener for Log {
+        fn accept(&mut self, evt: &Event) {
+            self.0.lock().unwrap().push(evt["id"].to_owned());
+        }
+    }
+
+    fn evt(ts: &str, id: &str) -> Event {
+        Event::builder().tag("ts", ts).tag("id", id).build()
+    }
+
+    #[test]
+    fn delivers_in_timestamp_order() {
+        let log = Log::default();
+        let mut topic =
+            ReorderingTopic::new(TreeScanner::default(), "ts", Duration::from_millis(10));
+        topic.subscribe(log.clone(), Filter::EMPTY);
+        for (ts, id) in [("100", "a"), ("105", "c"), ("103", "b"), ("105", "d")] {
+            topic.accept(evt(ts, id));
+        }
+        // Nothing is 10ms older than the newest event yet.
+        assert!(log.0.lock().unwrap().is_empty());
+        assert_eq!(topic.pending(), 4);
+        topic.flush();
+        assert_eq!(*log.0.lock().unwrap(), vec!["a", "b", "c", "d"]);
+        assert_eq!(topic.pending(), 0);
+    }
+
+    #[test]
+    fn releases_events_older_than_the_window() {
+        let log = Log::default();
+        let mut topic =
+            ReorderingTopic::new(LinearScan::default(), "ts", Duration::from_millis(10));
+        topic.subscribe(log.clone(), Filter::EMPTY);
+        topic.accept(evt("102", "b"));
+        topic.accept(evt("100", "a"));
+        topic.accept(evt("111", "d"));
+        assert_eq!(*log.0.lock().unwrap(), vec!["a"]);
+        topic.accept(evt("115", "e"));
+        assert_eq!(*log.0.lock().unwrap(), vec!["a", "b"]);
+        // Too late to be reordered, and no timestamp at all: both go straight through.
+        topic.accept(evt("90", "late"));
+        topic.accept(Event::single("id", "untimed"));
+        topic.flush();
+        assert_eq!(
+            *log.0.lock().unwrap(),
+            vec!["a", "b", "late", "untimed", "d", "e"]
+        );
+    }
+}
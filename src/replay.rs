@@ -0,0 +1,67 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use serde::de::DeserializeOwned;
+
+use crate::{Event, Listener};
+
+/// Feeds a log of events, one JSON-encoded [`Event`] per line, through `topic` in order, e.g. to
+/// backfill a freshly configured topic. Blank lines are skipped. Returns how many events were
+/// replayed.
+///
+/// Reading stops at the first line that isn't a valid event, with an `InvalidData` error naming
+/// its line number; the events before it have already been accepted.
+pub fn replay<T: DeserializeOwned, R: Read>(
+    topic: &mut impl Listener<Event<T>>,
+    reader: R,
+) -> io::Result<usize> {
+    let mut count = 0;
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let evt: Event<T> = serde_json::from_str(&line).map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("line {}: {}", i + 1, err),
+            )
+        })?;
+        topic.accept(&evt);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Filter, LinearScan, Topic};
+
+    const LOG: &str = r#"{"tags":{"env":"prod"},"data":1}
+{"tags":{"env":"dev"},"data":2}
+
+{"tags":{"env":"prod","region":"us"},"data":3}
+"#;
+
+    #[test]
+    fn replay_accepts_each_logged_event() {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut topic = LinearScan::default();
+        topic.subscribe(
+            crate::ChannelListener::new(tx),
+            Filter::single("env", "prod"),
+        );
+        assert_eq!(replay(&mut topic, LOG.as_bytes()).unwrap(), 3);
+        let data: Vec<u32> = rx.try_iter().map(|evt: Event<u32>| evt.data).collect();
+        assert_eq!(data, vec![1, 3]);
+    }
+
+    #[test]
+    fn replay_reports_malformed_line() {
+        let log = format!("{}{{\"tags\":\n", LOG);
+        let mut topic: LinearScan<crate::ChannelListener<Event<u32>>> = LinearScan::default();
+        let err = replay(&mut topic, log.as_bytes()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 5: "), "{}", err);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{ops::ControlFlow, sync::Mutex};
+
+use crate::{Filter, FilterError, FilterSet, Listener, SubscriptionId, Topic};
+
+/// Wraps a topic so it can be shared between threads, e.g. behind an `Arc`.
+///
+/// The whole topic sits behind a single `Mutex`. Delivering an event needs `&mut` access to the
+/// listeners, so `accept` takes the same exclusive lock as `subscribe` and `unsubscribe`:
+/// concurrent producers are serialized rather than dispatching in parallel.
+///
+/// All methods panic if a previous holder of the lock panicked, e.g. inside a listener.
+#[derive(Debug)]
+pub struct SyncTopic<T> {
+    inner: Mutex<T>,
+}
+impl<T> SyncTopic<T> {
+    pub fn new(topic: T) -> Self {
+        Self {
+            inner: Mutex::new(topic),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner().unwrap()
+    }
+
+    pub fn subscribe<L>(&self, listener: L, filter: Filter) -> SubscriptionId
+    where
+        T: Topic<L>,
+    {
+        self.inner.lock().unwrap().subscribe(listener, filter)
+    }
+
+    pub fn subscribe_any<L>(&self, listener: L, filters: FilterSet) -> SubscriptionId
+    where
+        T: Topic<L>,
+    {
+        self.inner.lock().unwrap().subscribe_any(listener, filters)
+    }
+
+    pub fn subscribe_once<L>(&self, listener: L, filter: Filter) -> SubscriptionId
+    where
+        T: Topic<L>,
+    {
+        self.inner.lock().unwrap().subscribe_once(listener, filter)
+    }
+
+    pub fn subscribe_limited<L>(&self, listener: L, filter: Filter, max: usize) -> SubscriptionId
+    where
+        T: Topic<L>,
+    {
+        self.inner
+            .lock()
+            .unwrap()
+            .subscribe_limited(listener, filter, max)
+    }
+
+    pub fn subscribe_checked<L>(
+        &self,
+        listener: L,
+        filter: Filter,
+    ) -> Result<SubscriptionId, FilterError>
+    where
+        T: Topic<L>,
+    {
+        self.inner
+            .lock()
+            .unwrap()
+            .subscribe_checked(listener, filter)
+    }
+
+    pub fn unsubscribe<L>(&self, id: SubscriptionId) -> bool
+    where
+        T: Topic<L>,
+    {
+        self.inner.lock().unwrap().unsubscribe(id)
+    }
+
+    pub fn accept<E>(&self, evt: &E)
+    where
+        T: Listener<E>,
+    {
+        self.inner.lock().unwrap().accept(evt);
+    }
+}
+impl<T: Default> Default for SyncTopic<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+impl<L, T: Topic<L>> Topic<L> for SyncTopic<T> {
+    fn subscribe(&mut self, listener: L, filter: Filter) -> SubscriptionId {
+        self.inner.get_mut().unwrap().subscribe(listener, filter)
+    }
+
+    fn subscribe_any(&mut self, listener: L, filters: FilterSet) -> SubscriptionId {
+        self.inner
+            .get_mut()
+            .unwrap()
+            .subscribe_any(listener, filters)
+    }
+
+    fn subscribe_limited(&mut self, listener: L, filter: Filter, max: usize) -> SubscriptionId {
+        self.inner
+            .get_mut()
+            .unwrap()
+            .subscribe_limited(listener, filter, max)
+    }
+
+    fn subscribe_checked(
+        &mut self,
+        listener: L,
+        filter: Filter,
+    ) -> Result<SubscriptionId, FilterError> {
+        self.inner
+            .get_mut()
+            .unwrap()
+            .subscribe_checked(listener, filter)
+    }
+
+    fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        self.inner.get_mut().unwrap().unsubscribe(id)
+    }
+}
+impl<E, T: Listener<E>> Listener<E> for SyncTopic<T> {
+    fn accept(&mut self, evt: &E) {
+        self.inner.get_mut().unwrap().accept(evt);
+    }
+
+    fn accept_flow(&mut self, evt: &E) -> ControlFlow<()> {
+        self.inner.get_mut().unwrap().accept_flow(evt)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, BTreeSet},
+        sync::{
+            atomic::{AtomicU32, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    use super::*;
+    use crate::{Event, LinearScan, TreeScanner};
+
+    struct Counter(Arc<AtomicU32>);
+    impl Listener for Counter {
+        fn accept(&mut self, _evt: &Event) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn hello_world() -> (Event, Filter) {
+        let evt = Event {
+            tags: BTreeMap::from([("hello".to_owned(), "world".to_owned())]),
+            data: (),
+        };
+        let filter = Filter {
+            tags: BTreeMap::from([("hello".to_owned(), BTreeSet::from(["world".to_owned()]))]),
+            ..Filter::EMPTY
+        };
+        (evt, filter)
+    }
+
+    fn concurrent_accept<T>(topic: SyncTopic<T>)
+    where
+        T: Topic<Counter> + Listener + Send + 'static,
+    {
+        let (evt, filter) = hello_world();
+        let count = Arc::new(AtomicU32::default());
+        topic.subscribe(Counter(count.clone()), filter.clone());
+        topic.subscribe(Counter(count.clone()), filter);
+
+        let topic = Arc::new(topic);
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let topic = topic.clone();
+                let evt = evt.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        topic.accept(&evt);
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        assert_eq!(count.load(Ordering::SeqCst), 2 * 8 * 100);
+    }
+
+    #[test]
+    fn sync_linear_scan() {
+        concurrent_accept(SyncTopic::new(LinearScan::default()));
+    }
+
+    #[test]
+    fn sync_tree_scanner() {
+        concurrent_accept(SyncTopic::new(TreeScanner::default()));
+    }
+}
@@ -0,0 +1,41 @@
+//! Helpers shared by the unit tests.
+
+/// Runs `$body` once per listed backend, with `$topic` naming that backend's type, so a generic
+/// `check` can be driven through each backend's inherent methods:
+///
+/// ```ignore
+/// for_each_backend!(T in [LinearScan, TreeScanner] => check(T::default(), T::accept_owned));
+/// ```
+macro_rules! for_each_backend {
+    ($topic:ident in [$($backend:ident),+ $(,)?] => $body:expr) => {
+        $({
+            use $backend as $topic;
+            $body;
+        })+
+    };
+}
+
+// Fixtures: `mkset!["a", "b"]`, `mkevt! {"env" => "prod"}`, and
+// `mkfilter! { "env" => ["prod", "dev"] }`, which only constrains `Filter::tags`.
+macro_rules! mkset {
+    ($($x:expr),+ $(,)?) => {{
+        let mut s = ::alloc::collections::BTreeSet::new();
+        $( s.insert($x.to_owned());)+
+        s
+    }};
+}
+macro_rules! mkevt {
+    ($($tag:expr => $value:expr),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut tags = ::alloc::collections::BTreeMap::new();
+        $( tags.insert($tag.to_owned(), $value.to_owned()); )*
+        $crate::Event { tags, data: () }
+    }};
+}
+macro_rules! mkfilter {
+    ($($tag:expr => [$($value:expr),+]),+ $(,)?) => {{
+        let mut tags = ::alloc::collections::BTreeMap::new();
+        $( tags.insert($tag.to_owned(), mkset!($($value),+)); )*
+        $crate::Filter { tags, ..$crate::Filter::EMPTY }
+    }};
+}
@@ -0,0 +1,34 @@
+use crate::Tags;
+
+// The span a topic's `accept` runs in. `matched` is recorded once delivery is over.
+pub(crate) fn dispatch_span<E: Tags>(topic: &'static str, evt: &E) -> tracing::Span {
+    tracing::info_span!(
+        "dispatch",
+        topic,
+        tags = %TagList(evt),
+        matched = tracing::field::Empty,
+    )
+}
+
+// Records how many listeners the dispatch in `span` delivered to, and logs that it's over, since
+// the span's fields are only reported along with the events inside it.
+pub(crate) fn finish(span: &tracing::Span, matched: usize) {
+    span.record("matched", matched);
+    tracing::debug!(parent: span, "dispatched");
+}
+
+// Renders an event's tags as `key=value` pairs separated by spaces, with one pair per value of a
+// multi-valued tag.
+struct TagList<'a, E>(&'a E);
+impl<E: Tags> core::fmt::Display for TagList<'_, E> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut sep = "";
+        for key in self.0.keys() {
+            for value in self.0.values(key) {
+                write!(f, "{}{}={}", sep, key, value)?;
+                sep = " ";
+            }
+        }
+        Ok(())
+    }
+}